@@ -0,0 +1,47 @@
+// Copyright 2022-2023 VMware, Inc.
+// SPDX-License-Identifier: BSD-2-Clause
+
+use std::collections::HashMap;
+
+use bounded::{bdd, checker::CheckerAnswer};
+use criterion::{criterion_group, criterion_main, Criterion};
+use fly::sorts::sort_check_module;
+
+pub fn bdd_symmetry_benchmark(c: &mut Criterion) {
+    let source = include_str!("../../temporal-verifier/examples/consensus.fly");
+    let mut module = fly::parser::parse(source).expect("could not parse consensus.fly");
+    sort_check_module(&mut module).expect("could not sort check consensus.fly");
+    let universe = HashMap::from([
+        ("node".to_string(), 3),
+        ("value".to_string(), 3),
+        ("quorum".to_string(), 3),
+    ]);
+
+    let mut group = c.benchmark_group("bdd::check consensus node=3 value=3 quorum=3");
+    group.sample_size(10);
+    for symmetry in [false, true] {
+        let name = if symmetry { "symmetry" } else { "no symmetry" };
+        // with symmetry, only one representative of each set of symmetric states is kept,
+        // which reduces the number of states but not the time, since the BDDs get larger
+        let Ok(CheckerAnswer::Convergence((reachable, indices))) =
+            bdd::check(&module, &universe, None, symmetry, false)
+        else {
+            panic!("expected convergence")
+        };
+        println!(
+            "{name}: {} reachable states, {} BDD nodes",
+            bdd::count_states(&reachable, &indices),
+            reachable.size()
+        );
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                let answer = bdd::check(&module, &universe, None, symmetry, false);
+                assert!(matches!(answer, Ok(CheckerAnswer::Convergence(..))));
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bdd_symmetry_benchmark);
+criterion_main!(benches);
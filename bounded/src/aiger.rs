@@ -0,0 +1,398 @@
+// Copyright 2022-2023 VMware, Inc.
+// SPDX-License-Identifier: BSD-2-Clause
+
+//! Export a bounded instance of a flyvy program as an [AIGER][aiger] circuit,
+//! so that hardware model checkers (for example IC3/PDR in ABC) can be run on
+//! it.
+//!
+//! The transition system is relational, while an AIGER circuit computes its
+//! next state as a function of the current state and the inputs. The circuit
+//! therefore reads a candidate state from its inputs at every step, and keeps
+//! track of whether the trace so far is valid:
+//!   - there is one latch per tuple of every relation, holding the state, and
+//!     functions into uninterpreted sorts have one latch per bit of their value
+//!     at each tuple (see [`value_bits`]);
+//!   - each latch is loaded from the corresponding input (immutable latches
+//!     only on the first step, and keep their value afterwards);
+//!   - the `ok` latch is set on the first step if the inputs satisfy the
+//!     initial conditions, and stays set as long as each new state is a valid
+//!     transition from the previous one;
+//!   - the single output (the bad state property) is set when a valid trace
+//!     reaches a state violating the safety properties.
+//!
+//! All latches are reset to zero, so the file is plain AIGER 1.0 (in the ASCII
+//! format), with the output interpreted as a bad state detector.
+//!
+//! [aiger]: https://fmv.jku.at/aiger/
+
+use crate::{checker::*, indices::*, quant_enum::*};
+use fly::{syntax::*, transitions::*};
+use itertools::Itertools;
+use std::collections::HashMap;
+use std::fmt::Write;
+
+/// An and-inverter graph under construction, using AIGER literals: `2 * var`
+/// for a variable and `2 * var + 1` for its negation, where variable 0 is the
+/// constant false.
+struct Aig {
+    num_inputs: usize,
+    num_latches: usize,
+    ands: Vec<(usize, usize, usize)>,
+    /// Structural hashing of and gates
+    cache: HashMap<(usize, usize), usize>,
+}
+
+const FALSE: usize = 0;
+const TRUE: usize = 1;
+
+/// The literal of input `i`.
+fn input_lit(i: usize) -> usize {
+    2 * (1 + i)
+}
+
+/// The literal of latch `i`, which comes after all the inputs.
+fn latch_lit(num_inputs: usize, i: usize) -> usize {
+    2 * (1 + num_inputs + i)
+}
+
+impl Aig {
+    fn new(num_inputs: usize, num_latches: usize) -> Aig {
+        Aig {
+            num_inputs,
+            num_latches,
+            ands: vec![],
+            cache: HashMap::new(),
+        }
+    }
+
+    fn input(&self, i: usize) -> usize {
+        input_lit(i)
+    }
+
+    fn latch(&self, i: usize) -> usize {
+        latch_lit(self.num_inputs, i)
+    }
+
+    fn and(&mut self, a: usize, b: usize) -> usize {
+        let (a, b) = if a > b { (a, b) } else { (b, a) };
+        if b == FALSE || a == b ^ 1 {
+            return FALSE;
+        }
+        if b == TRUE || a == b {
+            return a;
+        }
+        if let Some(lit) = self.cache.get(&(a, b)) {
+            return *lit;
+        }
+        let lit = 2 * (1 + self.num_inputs + self.num_latches + self.ands.len());
+        self.ands.push((lit, a, b));
+        self.cache.insert((a, b), lit);
+        lit
+    }
+
+    fn or(&mut self, a: usize, b: usize) -> usize {
+        self.and(a ^ 1, b ^ 1) ^ 1
+    }
+
+    fn iff(&mut self, a: usize, b: usize) -> usize {
+        let both = self.and(a, b);
+        let neither = self.and(a ^ 1, b ^ 1);
+        self.or(both, neither)
+    }
+
+    fn ite(&mut self, cond: usize, then: usize, else_: usize) -> usize {
+        let then = self.and(cond, then);
+        let else_ = self.and(cond ^ 1, else_);
+        self.or(then, else_)
+    }
+
+    /// Build a circuit for `term`, where `var` gives the literal for each
+    /// index in [`Indices`].
+    fn enumerated(
+        &mut self,
+        term: &Enumerated,
+        indices: &Indices,
+        var: &impl Fn(usize) -> usize,
+    ) -> usize {
+        match term {
+            Enumerated::And(terms) => terms.iter().fold(TRUE, |acc, term| {
+                let lit = self.enumerated(term, indices, var);
+                self.and(acc, lit)
+            }),
+            Enumerated::Or(terms) => terms.iter().fold(FALSE, |acc, term| {
+                let lit = self.enumerated(term, indices, var);
+                self.or(acc, lit)
+            }),
+            Enumerated::Not(term) => self.enumerated(term, indices, var) ^ 1,
+            Enumerated::Eq(a, b) => {
+                let a = self.enumerated(a, indices, var);
+                let b = self.enumerated(b, indices, var);
+                self.iff(a, b)
+            }
+            Enumerated::App(relation, primes, elements) => {
+                var(indices.get(relation, *primes, elements))
+            }
+        }
+    }
+}
+
+/// Translate a module at the given sort bounds to an AIGER circuit in the
+/// ASCII format. This function assumes that the module has been typechecked,
+/// and like the other bounded checkers it ignores proof blocks.
+pub fn export(module: &Module, universe: &UniverseBounds) -> Result<String, CheckerError> {
+    for sort in &module.signature.sorts {
+        if !universe.contains_key(sort) {
+            return Err(CheckerError::UnknownSort(sort.clone(), universe.clone()));
+        }
+    }
+
+    if !module.defs.is_empty() {
+        panic!("definitions in checker (use Module::inline_defs)")
+    }
+
+    let d = extract(module).map_err(CheckerError::ExtractionError)?;
+    let inits = d.inits.iter().chain(&d.axioms).cloned();
+    let transitions = d
+        .transitions
+        .iter()
+        .chain(d.mutable_axioms(&module.signature.relations))
+        .cloned();
+    let safeties = d.proofs.iter().map(|proof| proof.safety.x.clone());
+
+    // the current and next copies of the mutable tuples, then the immutable ones
+    let indices = Indices::new(&module.signature, universe, 2);
+    let translate = |term| {
+        enumerate_quantifiers(&term, &module.signature, universe)
+            .map_err(CheckerError::EnumerationError)
+    };
+    // functions have to stay in range in every state
+    let init = Enumerated::And(vec![
+        translate(Term::and(inits))?,
+        value_constraints(&module.signature, universe, false),
+        value_constraints(&module.signature, universe, true),
+    ]);
+    let tr = Enumerated::And(vec![
+        translate(Term::and(transitions))?,
+        value_constraints(&module.signature, universe, true).prime(1),
+    ]);
+    let safe = translate(Term::and(safeties))?;
+
+    // state bits are the mutable tuples followed by the immutable ones, so
+    // both copies of a mutable tuple map to the same bit
+    let num_mutables = indices.num_mutables;
+    let num_bits = indices.num_vars - num_mutables;
+    let bit = |i: usize| {
+        if i < num_mutables {
+            i
+        } else {
+            i - num_mutables
+        }
+    };
+    let mut names = vec![String::new(); num_bits];
+    for (relation, tuples) in indices.iter() {
+        let arity = module.signature.relation_decl(relation).args.len();
+        for elements in tuples.keys() {
+            let args = elements[..arity].iter().map(|e| e.to_string()).join(",");
+            let mut name = match arity {
+                0 => relation.to_string(),
+                _ => format!("{relation}({args})"),
+            };
+            // the remaining element is a bit of a function's value
+            if let Some(bit) = elements.get(arity) {
+                write!(name, "[{bit}]").unwrap();
+            }
+            names[bit(indices.get(relation, 0, elements))] = name;
+        }
+    }
+
+    // latches: the state bits, then `started` and `ok`
+    let mut aig = Aig::new(num_bits, num_bits + 2);
+    let started = aig.latch(num_bits);
+    let ok = aig.latch(num_bits + 1);
+
+    // the initial state is read from the inputs
+    let init = aig.enumerated(&init, &indices, &|i| input_lit(bit(i)));
+    // a transition goes from the latches to the inputs, except that immutable
+    // tuples always come from the latches
+    let tr = aig.enumerated(&tr, &indices, &|i| {
+        if (num_mutables..2 * num_mutables).contains(&i) {
+            input_lit(bit(i))
+        } else {
+            latch_lit(num_bits, bit(i))
+        }
+    });
+    let safe = aig.enumerated(&safe, &indices, &|i| latch_lit(num_bits, bit(i)));
+
+    let mut latch_next = vec![];
+    for i in 0..num_bits {
+        let next = if i < num_mutables {
+            aig.input(i)
+        } else {
+            let (latch, input) = (aig.latch(i), aig.input(i));
+            aig.ite(started, latch, input)
+        };
+        latch_next.push((aig.latch(i), next));
+    }
+    let step_ok = aig.and(ok, tr);
+    let ok_next = aig.ite(started, step_ok, init);
+    latch_next.push((started, TRUE));
+    latch_next.push((ok, ok_next));
+    let valid = aig.and(started, ok);
+    let bad = aig.and(valid, safe ^ 1);
+
+    let mut out = String::new();
+    let max_var = aig.num_inputs + aig.num_latches + aig.ands.len();
+    writeln!(
+        out,
+        "aag {max_var} {} {} 1 {}",
+        aig.num_inputs,
+        aig.num_latches,
+        aig.ands.len()
+    )
+    .unwrap();
+    for i in 0..num_bits {
+        writeln!(out, "{}", aig.input(i)).unwrap();
+    }
+    for (latch, next) in &latch_next {
+        writeln!(out, "{latch} {next}").unwrap();
+    }
+    writeln!(out, "{bad}").unwrap();
+    for (lhs, a, b) in &aig.ands {
+        writeln!(out, "{lhs} {a} {b}").unwrap();
+    }
+    for (i, name) in names.iter().enumerate() {
+        writeln!(out, "i{i} {name}'").unwrap();
+    }
+    for (i, name) in names.iter().enumerate() {
+        writeln!(out, "l{i} {name}").unwrap();
+    }
+    writeln!(out, "l{num_bits} started").unwrap();
+    writeln!(out, "l{} ok", num_bits + 1).unwrap();
+    writeln!(out, "o0 bad").unwrap();
+    writeln!(out, "c").unwrap();
+    writeln!(out, "generated by temporal-verifier with bounds:").unwrap();
+    for sort in &module.signature.sorts {
+        writeln!(out, "  {sort}={}", universe[sort]).unwrap();
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cadical::Solver;
+    use fly::sorts::sort_check_module;
+
+    /// Check whether the output of an ASCII AIGER circuit can be set within
+    /// `depth` steps, by unrolling it into a SAT query.
+    fn reachable(aag: &str, depth: usize) -> bool {
+        let lines: Vec<Vec<usize>> = aag
+            .lines()
+            .take_while(|line| !line.starts_with(['i', 'l', 'o', 'c']))
+            .map(|line| {
+                line.split(' ')
+                    .filter_map(|n| n.parse().ok())
+                    .collect::<Vec<usize>>()
+            })
+            .collect();
+        let [m, i, l, o, a] = lines[0][..] else {
+            panic!("bad header")
+        };
+        let latches = &lines[1 + i..1 + i + l];
+        let bad = lines[1 + i + l][0];
+        let ands = &lines[1 + i + l + o..1 + i + l + o + a];
+
+        // variable 1 is false, and frame f's variable v is 2 + f * m + v
+        let lit = |f: usize, lit: usize| {
+            let var = if lit / 2 == 0 { 1 } else { 2 + f * m + lit / 2 } as i32;
+            if lit & 1 == 0 {
+                var
+            } else {
+                -var
+            }
+        };
+        let mut solver: Solver = Default::default();
+        solver.add_clause([-1]);
+        for latch in latches {
+            solver.add_clause([-lit(0, latch[0])]);
+        }
+        for f in 0..=depth {
+            for and in ands {
+                let (x, y, z) = (lit(f, and[0]), lit(f, and[1]), lit(f, and[2]));
+                solver.add_clause([-x, y]);
+                solver.add_clause([-x, z]);
+                solver.add_clause([x, -y, -z]);
+            }
+            if f < depth {
+                for latch in latches {
+                    let (x, y) = (lit(f + 1, latch[0]), lit(f, latch[1]));
+                    solver.add_clause([-x, y]);
+                    solver.add_clause([x, -y]);
+                }
+            }
+        }
+        solver.add_clause((0..=depth).map(|f| lit(f, bad)));
+        solver.solve().unwrap()
+    }
+
+    #[test]
+    fn aiger_lockserver() -> Result<(), CheckerError> {
+        let source = include_str!("../../temporal-verifier/examples/lockserver.fly");
+
+        let mut module = fly::parser::parse(source).unwrap();
+        sort_check_module(&mut module).unwrap();
+        let universe = std::collections::HashMap::from([("node".to_string(), 2)]);
+
+        // the first step loads the initial state
+        assert!(!reachable(&export(&module, &universe)?, 11));
+        Ok(())
+    }
+
+    #[test]
+    fn aiger_lockserver_buggy() -> Result<(), CheckerError> {
+        let source = include_str!("../../temporal-verifier/tests/examples/lockserver_buggy.fly");
+
+        let mut module = fly::parser::parse(source).unwrap();
+        sort_check_module(&mut module).unwrap();
+        let universe = std::collections::HashMap::from([("node".to_string(), 2)]);
+
+        // the bug takes 12 transitions, plus one step to load the initial state
+        let aag = export(&module, &universe)?;
+        assert!(reachable(&aag, 13));
+        assert!(!reachable(&aag, 12));
+        Ok(())
+    }
+
+    #[test]
+    fn aiger_consensus() -> Result<(), CheckerError> {
+        let source = include_str!("../../temporal-verifier/examples/consensus.fly");
+
+        let mut module = fly::parser::parse(source).unwrap();
+        sort_check_module(&mut module).unwrap();
+        let universe = std::collections::HashMap::from([
+            ("node".to_string(), 2),
+            ("quorum".to_string(), 2),
+            ("value".to_string(), 2),
+        ]);
+
+        assert!(!reachable(&export(&module, &universe)?, 6));
+        Ok(())
+    }
+
+    #[test]
+    fn aiger_functions() -> Result<(), CheckerError> {
+        let source = include_str!("../../temporal-verifier/tests/examples/ring_functions.fly");
+
+        let mut module = fly::parser::parse(source).unwrap();
+        sort_check_module(&mut module).unwrap();
+        let universe = std::collections::HashMap::from([("node".to_string(), 3)]);
+
+        // each bit of a function's value is a latch
+        let aag = export(&module, &universe)?;
+        assert!(aag.lines().any(|line| line.ends_with(" succ(0)[1]")));
+        // the bug takes 2 transitions, plus one step to load the initial state
+        assert!(reachable(&aag, 3));
+        assert!(!reachable(&aag, 2));
+        Ok(())
+    }
+}
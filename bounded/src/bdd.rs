@@ -13,14 +13,50 @@ use std::collections::HashMap;
 /// Check a given Module out to some depth.
 /// This assumes that the module has been typechecked.
 /// Passing `None` for depth means to run until a counterexample is found.
+/// With `symmetry`, the search only keeps one representative of each set of states
+/// that are equal up to a permutation of the sort elements, and on convergence only these
+/// representatives are returned. This explores fewer states, but the sets of representatives
+/// usually have larger BDDs than the (symmetric) sets of all states, so it is often slower
+/// (about ten times slower on consensus.fly with three nodes, values, and quorums, see
+/// `benches/bdd_benchmark.rs`). For this reason it is not offered by `bdd-check`.
 /// The checker ignores proof blocks.
 pub fn check<'a>(
     module: &'a Module,
     universe: &'a UniverseBounds,
     depth: Option<usize>,
+    symmetry: bool,
     print_timing: bool,
 ) -> Result<CheckerAnswer<(Bdd, Indices<'a>)>, CheckerError> {
-    check_internal(module, universe, depth, print_timing, false)
+    check_internal(
+        module,
+        universe,
+        depth,
+        symmetry,
+        print_timing,
+        false,
+        &|| false,
+    )
+}
+
+/// The same as `check`, but `canceled` is called at every depth, and once it returns `true`
+/// the search stops with [`CheckerAnswer::Unknown`].
+pub fn check_cancelable<'a>(
+    module: &'a Module,
+    universe: &'a UniverseBounds,
+    depth: Option<usize>,
+    symmetry: bool,
+    print_timing: bool,
+    canceled: &dyn Fn() -> bool,
+) -> Result<CheckerAnswer<(Bdd, Indices<'a>)>, CheckerError> {
+    check_internal(
+        module,
+        universe,
+        depth,
+        symmetry,
+        print_timing,
+        false,
+        canceled,
+    )
 }
 
 /// The same as `check`, but instead of starting at `init` and going until it gets to `not_safe`,
@@ -30,17 +66,28 @@ pub fn check_reversed<'a>(
     module: &'a Module,
     universe: &'a UniverseBounds,
     depth: Option<usize>,
+    symmetry: bool,
     print_timing: bool,
 ) -> Result<CheckerAnswer<(Bdd, Indices<'a>)>, CheckerError> {
-    check_internal(module, universe, depth, print_timing, true)
+    check_internal(
+        module,
+        universe,
+        depth,
+        symmetry,
+        print_timing,
+        true,
+        &|| false,
+    )
 }
 
 fn check_internal<'a>(
     module: &'a Module,
     universe: &'a UniverseBounds,
     depth: Option<usize>,
+    symmetry: bool,
     print_timing: bool,
     reversed: bool,
+    canceled: &dyn Fn() -> bool,
 ) -> Result<CheckerAnswer<(Bdd, Indices<'a>)>, CheckerError> {
     for sort in &module.signature.sorts {
         if !universe.contains_key(sort) {
@@ -48,12 +95,6 @@ fn check_internal<'a>(
         }
     }
 
-    for relation in &module.signature.relations {
-        if relation.sort != Sort::Bool {
-            panic!("non-bool relations in checker (use Module::convert_non_bool_relations)")
-        }
-    }
-
     if !module.defs.is_empty() {
         panic!("definitions in checker (use Module::inline_defs)")
     }
@@ -78,10 +119,38 @@ fn check_internal<'a>(
     println!("starting translation...");
     let time = std::time::Instant::now();
 
-    let init = translate(Term::and(inits))?;
-    let tr = translate(Term::and(transitions))?;
+    // functions have to stay in range in every state
+    let init = translate(Term::and(inits))?
+        .and(&indices.bdd_from_enumerated(value_constraints(&module.signature, universe, false)))
+        .and(&indices.bdd_from_enumerated(value_constraints(&module.signature, universe, true)));
+    let tr =
+        translate(Term::and(transitions))?
+            .and(&indices.bdd_from_enumerated(
+                value_constraints(&module.signature, universe, true).prime(1),
+            ));
     let not_safe = translate(Term::and(safeties))?.not();
 
+    // everything above is invariant under permuting the elements of a sort,
+    // so each frontier can be replaced by one representative of each orbit
+    let symmetry = symmetry.then(|| Symmetry::new(&indices));
+    let orbit = |bdd: &Bdd| match &symmetry {
+        Some(symmetry) => symmetry.orbit(&indices, bdd),
+        None => bdd.clone(),
+    };
+    let reduce = |bdd: Bdd| match &symmetry {
+        Some(symmetry) => symmetry.reduce(&indices, &bdd),
+        None => bdd,
+    };
+    // counterexamples are reconstructed from the full orbits of each frontier
+    let orbits = |trace: &[Bdd]| trace.iter().map(orbit).collect::<Vec<_>>();
+    let reduce_new = |bdd: Bdd, reachable: &Bdd| match &symmetry {
+        // the representatives of states that were reached before are already known
+        Some(symmetry) => symmetry
+            .reduce(&indices, &bdd.and_not(reachable))
+            .and_not(reachable),
+        None => bdd,
+    };
+
     if print_timing {
         println!(
             "translation finished in {:0.1}s",
@@ -92,6 +161,10 @@ fn check_internal<'a>(
     let time = std::time::Instant::now();
 
     // Choose which way to search
+    let (init, not_safe) = match reversed {
+        false => (reduce(init), not_safe),
+        true => (init, reduce(not_safe)),
+    };
     let (mut trace, mut current, mut reachable, not_safe, update): (
         Vec<Bdd>,
         Bdd,
@@ -149,12 +222,16 @@ fn check_internal<'a>(
 
     // Do the search
     if let Some(valuation) = current.and(&not_safe).sat_witness() {
-        let models = trace_to_models(&indices, &valuation, &trace, &tr, reversed);
+        let models = trace_to_models(&indices, &valuation, &orbits(&trace), &tr, reversed);
         return Ok(CheckerAnswer::Counterexample(models));
     }
     let mut i = 0;
     while depth.map(|d| i < d).unwrap_or(true) {
+        if canceled() {
+            break;
+        }
         update(&mut current, &indices);
+        current = reduce_new(current, &reachable);
         let new_reachable = reachable.or(&current);
 
         if print_timing {
@@ -169,7 +246,7 @@ fn check_internal<'a>(
 
         trace.push(current.clone());
         if let Some(valuation) = current.and(&not_safe).sat_witness() {
-            let models = trace_to_models(&indices, &valuation, &trace, &tr, reversed);
+            let models = trace_to_models(&indices, &valuation, &orbits(&trace), &tr, reversed);
             return Ok(CheckerAnswer::Counterexample(models));
         }
 
@@ -179,6 +256,301 @@ fn check_internal<'a>(
     Ok(CheckerAnswer::Unknown)
 }
 
+/// The symmetries of a universe, which are generated by swapping adjacent elements of a sort.
+struct Symmetry {
+    /// Each generator, with the states that are lexicographically no larger than their image.
+    generators: Vec<(Transposition, Bdd)>,
+    /// Holds for at least one state in every orbit (the lexicographically smallest one),
+    /// and for as few other states as can be cheaply excluded.
+    canonical: Bdd,
+}
+
+/// A swap of two elements of a sort, as it acts on the indices of one copy of the relations.
+struct Transposition {
+    /// Pairs of indices that exchange their values.
+    swaps: Vec<(usize, usize)>,
+    /// The bits of a function value where the two elements are exchanged.
+    values: Vec<(Vec<usize>, [Element; 2])>,
+    /// Relates the mutable indices in the primed copy to those of the transposed state.
+    relation: Bdd,
+}
+
+impl Symmetry {
+    fn new(indices: &Indices) -> Symmetry {
+        let mut generators = vec![];
+        for sort in indices.universe.keys().sorted() {
+            for a in 1..indices.universe[sort] {
+                let swap = |x: Element| match x {
+                    x if x == a - 1 => a,
+                    x if x == a => a - 1,
+                    x => x,
+                };
+                let mut swaps = vec![];
+                let mut values = vec![];
+                for (name, rest) in indices.iter() {
+                    let relation = indices.signature.relation_decl(name);
+                    let arity = relation.args.len();
+                    for elements in rest.keys() {
+                        let image: Vec<_> = elements
+                            .iter()
+                            .enumerate()
+                            .map(|(i, x)| match relation.args.get(i) {
+                                Some(Sort::Uninterpreted(s)) if s == sort => swap(*x),
+                                _ => *x,
+                            })
+                            .collect();
+                        let (src, dst) =
+                            (indices.get(name, 0, elements), indices.get(name, 0, &image));
+                        if src < dst {
+                            swaps.push((src, dst));
+                        }
+                    }
+                    if relation.sort == Sort::Uninterpreted(sort.clone()) {
+                        // visit each argument tuple once, through its lowest bit
+                        for elements in rest.keys().filter(|elements| elements[arity] == 0) {
+                            let bits = (0..value_bits(indices.universe[sort]))
+                                .map(|bit| {
+                                    let mut elements = elements.clone();
+                                    elements[arity] = bit;
+                                    indices.get(name, 0, &elements)
+                                })
+                                .collect();
+                            values.push((bits, [a - 1, a]));
+                        }
+                    }
+                }
+                generators.push(Transposition::new(indices, swaps, values));
+            }
+        }
+
+        let generators: Vec<_> = generators
+            .into_iter()
+            .map(|generator| {
+                let leader = generator.lex_leader(indices);
+                (generator, leader)
+            })
+            .collect();
+        let canonical = indices.bdd_and(generators.iter().map(|(_, leader)| leader.clone()));
+
+        Symmetry {
+            generators,
+            canonical,
+        }
+    }
+
+    /// Close a set of states under all of the symmetries.
+    fn orbit(&self, indices: &Indices, bdd: &Bdd) -> Bdd {
+        let mut orbit = bdd.clone();
+        let mut frontier = bdd.clone();
+        while !frontier.is_false() {
+            frontier = indices
+                .bdd_or(
+                    self.generators
+                        .iter()
+                        .map(|(g, _)| g.apply(indices, &frontier)),
+                )
+                .and_not(&orbit);
+            orbit = orbit.or(&frontier);
+        }
+        orbit
+    }
+
+    /// Replace a set of states with canonical states from the same orbits, by repeatedly
+    /// applying the generators that make a state lexicographically smaller.
+    /// This keeps at least one state from every orbit without computing the full orbits.
+    fn reduce(&self, indices: &Indices, bdd: &Bdd) -> Bdd {
+        let mut reduced = bdd.and(&self.canonical);
+        let mut pending = bdd.and_not(&self.canonical);
+        while !pending.is_false() {
+            // each state only takes the first generator that makes it smaller, and since
+            // states keep getting smaller this terminates
+            let mut next = indices.bdd_context.mk_false();
+            for (g, leader) in &self.generators {
+                next = next.or(&g.apply(indices, &pending.and_not(leader)));
+                pending = pending.and(leader);
+            }
+            reduced = reduced.or(&next.and(&self.canonical));
+            pending = next.and_not(&self.canonical);
+        }
+        reduced
+    }
+}
+
+impl Transposition {
+    fn new(
+        indices: &Indices,
+        swaps: Vec<(usize, usize)>,
+        values: Vec<(Vec<usize>, [Element; 2])>,
+    ) -> Transposition {
+        let mutables = indices.num_mutables;
+        let var = |i: usize| indices.bdd_context.mk_var(indices.bdd_variables[i]);
+        let is = |bits: &[usize], v: Element| {
+            indices.bdd_and(bits.iter().enumerate().map(|(bit, i)| {
+                indices
+                    .bdd_context
+                    .mk_literal(indices.bdd_variables[*i], v >> bit & 1 == 1)
+            }))
+        };
+
+        let mut image: Vec<usize> = (0..mutables).collect();
+        for &(i, j) in &swaps {
+            if j < mutables {
+                (image[i], image[j]) = (j, i);
+            }
+        }
+        let mut relation: Vec<(usize, Bdd)> = vec![];
+        let mut grouped = vec![false; mutables];
+        for (bits, [a, b]) in values.iter().filter(|(bits, _)| bits[0] < mutables) {
+            let primed: Vec<_> = bits.iter().map(|i| i + mutables).collect();
+            let unprimed: Vec<_> = bits.iter().map(|i| image[*i]).collect();
+            let map = |v: Element| match v {
+                v if v == *a => *b,
+                v if v == *b => *a,
+                v => v,
+            };
+            let eq = indices
+                .bdd_or((0..1 << bits.len()).map(|v| is(&primed, v).and(&is(&unprimed, map(v)))));
+            relation.push((bits[0], eq));
+            for i in bits {
+                grouped[*i] = true;
+            }
+        }
+        for i in (0..mutables).filter(|i| !grouped[*i]) {
+            relation.push((i, var(i + mutables).iff(&var(image[i]))));
+        }
+        // conjoin from the bottom of the variable order up, to keep intermediate BDDs small
+        relation.sort_by_key(|(i, _)| std::cmp::Reverse(*i));
+        let relation = indices.bdd_and(relation.into_iter().map(|(_, bdd)| bdd));
+
+        Transposition {
+            swaps,
+            values,
+            relation,
+        }
+    }
+
+    /// Substitute the transposed state into a set of states. Since a transposition is its
+    /// own inverse, this computes the image of the set.
+    fn apply(&self, indices: &Indices, bdd: &Bdd) -> Bdd {
+        let mutables = indices.num_mutables;
+        let var = |i: usize| indices.bdd_variables[i];
+        // the mutable indices are permuted through the primed copy
+        let mut out = bdd.clone();
+        for i in (0..mutables).rev() {
+            unsafe {
+                out.rename_variable(var(i), var(i + mutables));
+            }
+        }
+        let mut out = Bdd::binary_op_with_exists(
+            &out,
+            &self.relation,
+            op_function::and,
+            &indices.bdd_variables[mutables..mutables * 2],
+        );
+        // and the immutable ones in place
+        for &(i, j) in self
+            .swaps
+            .iter()
+            .filter(|(i, _)| *i >= mutables * indices.num_mutable_copies)
+        {
+            let (x, y) = (
+                indices.bdd_context.mk_var(var(i)),
+                indices.bdd_context.mk_var(var(j)),
+            );
+            out = indices.bdd_or([
+                x.iff(&y).and(&out),
+                x.and_not(&y)
+                    .and(&out.restrict(&[(var(i), false), (var(j), true)])),
+                y.and_not(&x)
+                    .and(&out.restrict(&[(var(i), true), (var(j), false)])),
+            ]);
+        }
+        for (bits, [a, b]) in self
+            .values
+            .iter()
+            .filter(|(bits, _)| bits[0] >= mutables * indices.num_mutable_copies)
+        {
+            let value = |v: Element| {
+                bits.iter()
+                    .enumerate()
+                    .map(|(bit, i)| (var(*i), v >> bit & 1 == 1))
+                    .collect::<Vec<_>>()
+            };
+            let is = |v: Element| {
+                indices.bdd_and(
+                    value(v)
+                        .into_iter()
+                        .map(|(var, b)| indices.bdd_context.mk_literal(var, b)),
+                )
+            };
+            out = indices.bdd_or([
+                is(*a).or(&is(*b)).not().and(&out),
+                is(*a).and(&out.restrict(&value(*b))),
+                is(*b).and(&out.restrict(&value(*a))),
+            ]);
+        }
+        out
+    }
+
+    /// The states that are lexicographically no larger than their image.
+    fn lex_leader(&self, indices: &Indices) -> Bdd {
+        let var = |i: usize| indices.bdd_context.mk_var(indices.bdd_variables[i]);
+        // the value of each index in the image, in terms of the original state
+        let mut image: HashMap<usize, Bdd> = HashMap::new();
+        for &(i, j) in &self.swaps {
+            image.insert(i, var(j));
+            image.insert(j, var(i));
+        }
+        for (bits, [a, b]) in &self.values {
+            let is = |v: Element| {
+                indices.bdd_and(bits.iter().enumerate().map(|(bit, i)| {
+                    let x = image.get(i).cloned().unwrap_or_else(|| var(*i));
+                    if v >> bit & 1 == 1 {
+                        x
+                    } else {
+                        x.not()
+                    }
+                }))
+            };
+            let (is_a, is_b) = (is(*a), is(*b));
+            let unchanged = is_a.or(&is_b).not();
+            let images: Vec<_> = bits
+                .iter()
+                .enumerate()
+                .map(|(bit, i)| {
+                    let x = image.get(i).cloned().unwrap_or_else(|| var(*i));
+                    let mut y = x.and(&unchanged);
+                    if b >> bit & 1 == 1 {
+                        y = y.or(&is_a);
+                    }
+                    if a >> bit & 1 == 1 {
+                        y = y.or(&is_b);
+                    }
+                    y
+                })
+                .collect();
+            for (i, y) in bits.iter().zip(images) {
+                image.insert(*i, y);
+            }
+        }
+
+        image
+            .into_iter()
+            .sorted_by_key(|(i, _)| std::cmp::Reverse(*i))
+            .fold(indices.bdd_context.mk_true(), |le, (i, y)| {
+                let x = var(i);
+                x.not().and(&y).or(&x.iff(&y).and(&le))
+            })
+    }
+}
+
+/// The number of states in a set of states over the unprimed indices, such as the
+/// reachable states returned by [`check`].
+pub fn count_states(bdd: &Bdd, indices: &Indices) -> f64 {
+    // the primed copy of the mutable indices is unconstrained
+    bdd.cardinality() / 2f64.powi(indices.num_mutables as i32)
+}
+
 fn trace_to_models(
     indices: &Indices,
     valuation: &BddValuation,
@@ -309,47 +681,12 @@ pub fn bdd_to_term<'a>(
         }
     }
 
-    // Build a map from sort elements to Term variable names
-    let mut next_binding = 0;
-    let mut bindings: HashMap<(&str, usize), String> = HashMap::new();
-    for (sort, bound) in indices.universe {
-        for i in 0..*bound {
-            bindings.insert((sort, i), format!("${next_binding}"));
-            next_binding += 1;
-        }
-    }
-
     // Build a map from BDD variable names to Terms
-    let mut vars_to_terms: HashMap<String, Term> = HashMap::new();
-    for (relation, map) in indices.iter() {
-        for (elements, (i, _mutable)) in map {
-            let name = indices.bdd_context.name_of(indices.bdd_variables[*i]);
-            let args = indices
-                .signature
-                .relations
-                .iter()
-                .find(|r| &r.name == relation)
-                .unwrap()
-                .args
-                .iter()
-                .zip_eq(elements)
-                .map(|(sort, element)| match sort {
-                    Sort::Uninterpreted(sort) => {
-                        Term::Id(bindings[&(sort.as_str(), *element)].clone())
-                    }
-                    Sort::Bool => match element {
-                        0 => Term::Literal(false),
-                        1 => Term::Literal(true),
-                        _ => unreachable!(),
-                    },
-                });
-            let term = match args.len() {
-                0 => Term::Id(relation.to_string()),
-                _ => Term::App(relation.to_string(), 0, args.collect()),
-            };
-            vars_to_terms.insert(name, term);
-        }
-    }
+    let (terms, bindings) = indices.terms();
+    let vars_to_terms: HashMap<String, Term> = terms
+        .into_iter()
+        .map(|(i, term)| (indices.bdd_context.name_of(indices.bdd_variables[i]), term))
+        .collect();
 
     // Convert the BDD to a Term
     let term = to_term(
@@ -373,11 +710,11 @@ mod tests {
         let universe = HashMap::from([]);
 
         assert!(matches!(
-            check(&module, &universe, Some(0), false)?,
+            check(&module, &universe, Some(0), false, false)?,
             CheckerAnswer::Unknown,
         ));
         assert!(matches!(
-            check(&module, &universe, Some(1), false)?,
+            check(&module, &universe, Some(1), false, false)?,
             CheckerAnswer::Counterexample(_),
         ));
 
@@ -393,7 +730,7 @@ mod tests {
         let universe = HashMap::from([("node".to_string(), 2)]);
 
         assert!(matches!(
-            check(&module, &universe, None, false)?,
+            check(&module, &universe, None, false, false)?,
             CheckerAnswer::Convergence(..),
         ));
 
@@ -408,12 +745,12 @@ mod tests {
         sort_check_module(&mut module).unwrap();
         let universe = HashMap::from([("node".to_string(), 2)]);
 
-        let bug = check(&module, &universe, Some(12), false)?;
+        let bug = check(&module, &universe, Some(12), false, false)?;
         assert!(matches!(bug, CheckerAnswer::Counterexample(_)));
-        let bug = check(&module, &universe, None, false)?;
+        let bug = check(&module, &universe, None, false, false)?;
         assert!(matches!(bug, CheckerAnswer::Counterexample(_)));
 
-        let too_short = check(&module, &universe, Some(11), false)?;
+        let too_short = check(&module, &universe, Some(11), false, false)?;
         assert!(matches!(too_short, CheckerAnswer::Unknown));
 
         Ok(())
@@ -432,13 +769,38 @@ mod tests {
         ]);
 
         assert!(matches!(
-            check(&module, &universe, Some(0), false)?,
+            check(&module, &universe, Some(0), false, false)?,
             CheckerAnswer::Unknown,
         ));
 
         Ok(())
     }
 
+    #[test]
+    fn checker_bdd_functions() -> Result<(), CheckerError> {
+        let source = include_str!("../../temporal-verifier/tests/examples/ring_functions.fly");
+        let mut module = fly::parser::parse(source).unwrap();
+        sort_check_module(&mut module).unwrap();
+        let universe = std::collections::HashMap::from([("node".to_string(), 3)]);
+
+        assert!(matches!(
+            check(&module, &universe, Some(1), false, false)?,
+            CheckerAnswer::Unknown,
+        ));
+        let CheckerAnswer::Counterexample(models) = check(&module, &universe, None, false, false)?
+        else {
+            panic!("expected a counterexample")
+        };
+        assert_eq!(3, models.len());
+        let owner = fly::parser::term("owner");
+        let next_owner = fly::parser::term("succ(owner)");
+        for (pre, post) in models.iter().zip(&models[1..]) {
+            assert!(pre.eval(&owner) < 3);
+            assert_eq!(pre.eval(&next_owner), post.eval(&owner));
+        }
+        Ok(())
+    }
+
     #[test]
     fn checker_bdd_immutability() -> Result<(), CheckerError> {
         let source =
@@ -447,12 +809,68 @@ mod tests {
         sort_check_module(&mut module).unwrap();
         let universe = std::collections::HashMap::new();
         assert!(matches!(
-            check(&module, &universe, None, false)?,
+            check(&module, &universe, None, false, false)?,
             CheckerAnswer::Convergence(..),
         ));
         Ok(())
     }
 
+    #[test]
+    fn checker_bdd_symmetry() -> Result<(), CheckerError> {
+        let examples = [
+            (
+                include_str!("../../temporal-verifier/examples/lockserver.fly"),
+                vec![("node", 3)],
+            ),
+            (
+                include_str!("../../temporal-verifier/tests/examples/lockserver_buggy.fly"),
+                vec![("node", 2)],
+            ),
+            (
+                include_str!("../../temporal-verifier/tests/examples/ring_functions.fly"),
+                vec![("node", 3)],
+            ),
+            (
+                include_str!("../../temporal-verifier/examples/consensus.fly"),
+                vec![("node", 2), ("quorum", 2), ("value", 2)],
+            ),
+        ];
+        for (source, universe) in examples {
+            let mut module = fly::parser::parse(source).unwrap();
+            sort_check_module(&mut module).unwrap();
+            let universe = universe
+                .into_iter()
+                .map(|(sort, size)| (sort.to_string(), size))
+                .collect();
+            for check in [check, check_reversed] {
+                // symmetry reduction finds representatives of the same reachable states,
+                // and traces of the same length
+                match (
+                    check(&module, &universe, None, false, false)?,
+                    check(&module, &universe, None, true, false)?,
+                ) {
+                    (
+                        CheckerAnswer::Convergence((expected, _)),
+                        CheckerAnswer::Convergence((reachable, indices)),
+                    ) => {
+                        let orbit = Symmetry::new(&indices).orbit(&indices, &reachable);
+                        assert_eq!(expected, orbit);
+                        assert!(
+                            count_states(&reachable, &indices) < count_states(&expected, &indices)
+                        );
+                    }
+                    (
+                        CheckerAnswer::Counterexample(expected),
+                        CheckerAnswer::Counterexample(models),
+                    ) => assert_eq!(expected.len(), models.len()),
+                    _ => panic!("symmetry reduction changed the answer"),
+                }
+            }
+        }
+
+        Ok(())
+    }
+
     #[test]
     fn checker_bdd_basic_reversed() -> Result<(), CheckerError> {
         let source = include_str!("../../temporal-verifier/tests/examples/basic2.fly");
@@ -462,11 +880,11 @@ mod tests {
         let universe = HashMap::from([]);
 
         assert!(matches!(
-            check_reversed(&module, &universe, Some(0), false)?,
+            check_reversed(&module, &universe, Some(0), false, false)?,
             CheckerAnswer::Unknown,
         ));
         assert!(matches!(
-            check_reversed(&module, &universe, Some(1), false)?,
+            check_reversed(&module, &universe, Some(1), false, false)?,
             CheckerAnswer::Counterexample(_),
         ));
 
@@ -482,7 +900,7 @@ mod tests {
         let universe = HashMap::from([("node".to_string(), 2)]);
 
         assert!(matches!(
-            check_reversed(&module, &universe, None, false)?,
+            check_reversed(&module, &universe, None, false, false)?,
             CheckerAnswer::Convergence(..),
         ));
 
@@ -497,12 +915,12 @@ mod tests {
         sort_check_module(&mut module).unwrap();
         let universe = HashMap::from([("node".to_string(), 2)]);
 
-        let bug = check_reversed(&module, &universe, Some(12), false)?;
+        let bug = check_reversed(&module, &universe, Some(12), false, false)?;
         assert!(matches!(bug, CheckerAnswer::Counterexample(_)));
-        let bug = check_reversed(&module, &universe, None, false)?;
+        let bug = check_reversed(&module, &universe, None, false, false)?;
         assert!(matches!(bug, CheckerAnswer::Counterexample(_)));
 
-        let too_short = check_reversed(&module, &universe, Some(11), false)?;
+        let too_short = check_reversed(&module, &universe, Some(11), false, false)?;
         assert!(matches!(too_short, CheckerAnswer::Unknown));
 
         Ok(())
@@ -521,7 +939,7 @@ mod tests {
         ]);
 
         assert!(matches!(
-            check_reversed(&module, &universe, Some(0), false)?,
+            check_reversed(&module, &universe, Some(0), false, false)?,
             CheckerAnswer::Unknown,
         ));
 
@@ -536,7 +954,7 @@ mod tests {
         sort_check_module(&mut module).unwrap();
         let universe = std::collections::HashMap::new();
         assert!(matches!(
-            check_reversed(&module, &universe, None, false)?,
+            check_reversed(&module, &universe, None, false, false)?,
             CheckerAnswer::Convergence(..),
         ));
         Ok(())
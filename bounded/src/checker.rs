@@ -37,18 +37,18 @@ pub enum CheckerError {
     SatSolverFailed,
 
     // set.rs specific
-    /// The translated formula was not a conjunction
-    #[error("the set checker currently only handles initial conditions that are a conjunction")]
-    InitNotConj,
-    /// The transition system extraction found more than one transition relation
-    #[error("the set checker currently only handles a single transition relation")]
-    MultipleTrs,
     /// `Formula`s are single-vocabulary
     #[error("a transition contained a disjunction that contained a prime")]
     PrimeInFormula,
-    /// We can't support unproven mutable axioms without post-guards
-    #[error("an axiom that mentioned mutable relations couldn't be proven")]
-    UnprovenMutableAxiom,
+    /// The states of the module have too many bits for the set checker
+    #[error("states need {0} bits at these sort bounds, but the set checker supports at most {1}")]
+    StateTooLarge(usize, usize),
+    /// The frontier could not be written to or read from disk
+    #[error("could not spill the frontier to disk: {0}")]
+    SpillError(String),
+    /// The thread pool for the search could not be created
+    #[error("could not create threads: {0}")]
+    ThreadPool(String),
 
     // smt.rs
     /// See solver::SolveError
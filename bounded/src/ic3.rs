@@ -0,0 +1,600 @@
+// Copyright 2022-2023 VMware, Inc.
+// SPDX-License-Identifier: BSD-2-Clause
+
+//! A checker for finite instances of flyvy programs using IC3, also known as property
+//! directed reachability (PDR), on top of the [CaDiCaL][cadical] SAT solver.
+//!
+//! [cadical]: https://fmv.jku.at/cadical/
+
+use crate::{checker::*, indices::*, quant_enum::*, sat::*};
+use fly::{semantics::*, syntax::*};
+use itertools::Itertools;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+
+/// A value for one of the state variables, which are identified by their index in
+/// [`Indices`]. Immutable relations are part of the state, and never change.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Literal {
+    /// The index of the state variable
+    pub index: usize,
+    /// The value of the state variable
+    pub value: bool,
+}
+
+/// A disjunction of literals. An inductive invariant is a conjunction of clauses.
+pub type Clause = Vec<Literal>;
+
+/// A conjunction of literals, which is the negation of a clause.
+type Cube = Vec<Literal>;
+
+/// Check a given Module using IC3, giving up after `depth` frames if it is given.
+/// When the frames converge, the answer contains an inductive invariant that implies the
+/// safety property, as a set of clauses over the indices of the returned [`Indices`].
+/// This function assumes that the module has been typechecked.
+/// The checker ignores proof blocks.
+pub fn check<'a>(
+    module: &'a Module,
+    universe: &'a UniverseBounds,
+    depth: Option<usize>,
+    print_timing: bool,
+) -> Result<CheckerAnswer<(Vec<Clause>, Indices<'a>)>, CheckerError> {
+    println!("starting translation...");
+    let translation = std::time::Instant::now();
+
+    let (program, indices) = translate(module, universe)?;
+
+    if print_timing {
+        println!(
+            "translation finished in {:0.1}s",
+            translation.elapsed().as_secs_f64()
+        );
+    }
+
+    println!("starting search...");
+    let search = std::time::Instant::now();
+
+    let answer = Ic3::new(&program, &indices).search(depth);
+
+    if print_timing {
+        println!("search finished in {:0.1}s", search.elapsed().as_secs_f64());
+    }
+
+    Ok(match answer? {
+        CheckerAnswer::Counterexample(models) => CheckerAnswer::Counterexample(models),
+        CheckerAnswer::Unknown => CheckerAnswer::Unknown,
+        CheckerAnswer::Convergence(clauses) => CheckerAnswer::Convergence((clauses, indices)),
+    })
+}
+
+/// Convert the clauses of an invariant into `Term`s over the elements of the universe.
+/// Returns the terms and a map from (sort, element) pairs to the name of the identifier
+/// that represents the element.
+pub fn clauses_to_terms<'a>(
+    clauses: &[Clause],
+    indices: &Indices<'a>,
+) -> (Vec<Term>, HashMap<(&'a str, usize), String>) {
+    let (terms, bindings) = indices.terms();
+    let clauses = clauses
+        .iter()
+        .map(|clause| {
+            Term::or(clause.iter().map(|literal| match literal.value {
+                true => terms[&literal.index].clone(),
+                false => Term::not(terms[&literal.index].clone()),
+            }))
+        })
+        .collect();
+    (clauses, bindings)
+}
+
+/// The state of the IC3 search.
+struct Ic3<'a> {
+    indices: &'a Indices<'a>,
+    program: &'a Program,
+    /// The indices of the state variables
+    state: Vec<usize>,
+    /// A solver that only holds the initial states
+    init: Unrolling<'a>,
+    /// A solver for each frame that holds the states of the frame followed by a transition.
+    /// The states of the first frame are the initial states, and the states of the others
+    /// are the states that satisfy the axioms and the clauses of the frame.
+    solvers: Vec<Unrolling<'a>>,
+    /// A solver for each frame that only holds the states of the frame, without requiring
+    /// them to have a successor. This is where bad states are found, since a bad state
+    /// might not have any successors.
+    states: Vec<Unrolling<'a>>,
+    /// The clauses that were learned at each frame. The clauses of a frame are the ones
+    /// that were learned at that frame or at any later frame.
+    frames: Vec<Vec<Clause>>,
+    /// The literal for the negation of the safety property in each solver of `states`
+    bad: Vec<i32>,
+}
+
+/// Get the solver literal for a state literal in the pre-state (time 0) or post-state
+/// (time 1) of the transition relation.
+fn solver_literal(unrolling: &mut Unrolling, literal: Literal, time: usize) -> i32 {
+    let var = unrolling.var(time, literal.index);
+    if literal.value {
+        var
+    } else {
+        -var
+    }
+}
+
+/// The result of checking whether a cube is unreachable from the previous frame.
+enum Relative {
+    /// A predecessor of the cube exists
+    Predecessor(Cube),
+    /// The cube is unreachable, and so is the given subset of it
+    Blocked(Cube),
+}
+
+impl<'a> Ic3<'a> {
+    fn new(program: &'a Program, indices: &'a Indices<'a>) -> Ic3<'a> {
+        let state = (0..indices.num_mutables)
+            .chain(indices.num_mutables * 2..indices.num_vars)
+            .collect();
+        let mut init = Unrolling::new(indices);
+        init.assert(&program.init, 0);
+        let mut ic3 = Ic3 {
+            indices,
+            program,
+            state,
+            init,
+            solvers: vec![],
+            states: vec![],
+            frames: vec![],
+            bad: vec![],
+        };
+        ic3.push_frame();
+        ic3
+    }
+
+    /// Add a new frame with no clauses.
+    fn push_frame(&mut self) {
+        let states = match self.frames.len() {
+            0 => &self.program.init,
+            _ => &self.program.start,
+        };
+        let mut solver = Unrolling::new(self.indices);
+        solver.assert(states, 0);
+        solver.assert(&self.program.tr, 0);
+        let mut states_solver = Unrolling::new(self.indices);
+        states_solver.assert(states, 0);
+        let bad = -states_solver.literal(&self.program.safe, 0);
+        self.solvers.push(solver);
+        self.states.push(states_solver);
+        self.frames.push(vec![]);
+        self.bad.push(bad);
+    }
+
+    /// Get the solver literal for a state literal in the pre-state (time 0) or
+    /// post-state (time 1) of the transition relation.
+    fn literal(&mut self, frame: usize, literal: Literal, time: usize) -> i32 {
+        solver_literal(&mut self.solvers[frame], literal, time)
+    }
+
+    /// Read the pre-state out of the last satisfying assignment of a solver.
+    fn cube(state: &[usize], solver: &mut Unrolling) -> Cube {
+        state
+            .iter()
+            .map(|&index| {
+                let var = solver.var(0, index);
+                Literal {
+                    index,
+                    value: solver.solver.value(var).unwrap_or(false),
+                }
+            })
+            .collect()
+    }
+
+    /// Find a bad state in the given frame, if there is one.
+    fn bad_cube(&mut self, frame: usize) -> Result<Option<Cube>, CheckerError> {
+        let solver = &mut self.states[frame];
+        match solver.solver.solve_with([self.bad[frame]].into_iter()) {
+            None => Err(CheckerError::SatSolverFailed),
+            Some(false) => Ok(None),
+            Some(true) => Ok(Some(Self::cube(&self.state, solver))),
+        }
+    }
+
+    /// Check whether a cube contains an initial state.
+    fn intersects_init(&mut self, cube: &Cube) -> Result<bool, CheckerError> {
+        let assumptions: Vec<_> = cube
+            .iter()
+            .map(|literal| solver_literal(&mut self.init, *literal, 0))
+            .collect();
+        self.init
+            .solver
+            .solve_with(assumptions.into_iter())
+            .ok_or(CheckerError::SatSolverFailed)
+    }
+
+    /// Check whether the clauses of a frame already exclude a cube.
+    fn is_blocked(&mut self, cube: &Cube, frame: usize) -> Result<bool, CheckerError> {
+        let solver = &mut self.states[frame];
+        let assumptions: Vec<_> = cube
+            .iter()
+            .map(|literal| solver_literal(solver, *literal, 0))
+            .collect();
+        match solver.solver.solve_with(assumptions.into_iter()) {
+            None => Err(CheckerError::SatSolverFailed),
+            Some(sat) => Ok(!sat),
+        }
+    }
+
+    /// Check whether a cube is unreachable in one step from the given frame, assuming
+    /// that it is unreachable in the pre-state.
+    fn relative(&mut self, cube: &Cube, frame: usize) -> Result<Relative, CheckerError> {
+        let activation = self.solvers[frame].fresh();
+        let clause: Vec<_> = cube
+            .iter()
+            .map(|literal| -self.literal(frame, *literal, 0))
+            .chain([-activation])
+            .collect();
+        self.solvers[frame].solver.add_clause(clause);
+        let primed: Vec<_> = cube
+            .iter()
+            .map(|literal| self.literal(frame, *literal, 1))
+            .collect();
+        let assumptions = primed.iter().copied().chain([activation]);
+        let answer = match self.solvers[frame].solver.solve_with(assumptions) {
+            None => Err(CheckerError::SatSolverFailed),
+            Some(true) => Ok(Relative::Predecessor(Self::cube(
+                &self.state,
+                &mut self.solvers[frame],
+            ))),
+            Some(false) => {
+                let solver = &self.solvers[frame].solver;
+                Ok(Relative::Blocked(
+                    cube.iter()
+                        .zip(&primed)
+                        .filter(|(_, lit)| solver.failed(**lit))
+                        .map(|(literal, _)| *literal)
+                        .collect(),
+                ))
+            }
+        };
+        // retire the activation literal so that the clause has no effect
+        self.solvers[frame].solver.add_clause([-activation]);
+        answer
+    }
+
+    /// Shrink a cube that is blocked at the given frame, keeping it disjoint from the
+    /// initial states.
+    fn generalize(&mut self, cube: &Cube, core: Cube, frame: usize) -> Result<Cube, CheckerError> {
+        let mut cube = match self.intersects_init(&core)? {
+            false => core,
+            true => cube.clone(),
+        };
+        let mut i = 0;
+        while i < cube.len() {
+            let mut smaller = cube.clone();
+            smaller.remove(i);
+            if !self.intersects_init(&smaller)? {
+                if let Relative::Blocked(core) = self.relative(&smaller, frame - 1)? {
+                    cube = match self.intersects_init(&core)? {
+                        false if core.len() < smaller.len() => core,
+                        _ => smaller,
+                    };
+                    continue;
+                }
+            }
+            i += 1;
+        }
+        Ok(cube)
+    }
+
+    /// Add a clause that blocks the cube to the given frame and every earlier frame.
+    fn add_clause(&mut self, cube: &Cube, frame: usize) {
+        let clause: Clause = cube
+            .iter()
+            .map(|literal| Literal {
+                index: literal.index,
+                value: !literal.value,
+            })
+            .collect();
+        for i in 1..=frame {
+            self.assert_clause(&clause, i);
+        }
+        self.frames[frame].push(clause);
+    }
+
+    /// Add a clause to the solvers of a frame.
+    fn assert_clause(&mut self, clause: &Clause, frame: usize) {
+        for solver in [&mut self.solvers[frame], &mut self.states[frame]] {
+            let lits: Vec<_> = clause
+                .iter()
+                .map(|literal| solver_literal(solver, *literal, 0))
+                .collect();
+            solver.solver.add_clause(lits);
+        }
+    }
+
+    /// Block a bad cube at the last frame, or return a counterexample that reaches it.
+    fn block(&mut self, bad: Cube) -> Result<Option<Vec<Model>>, CheckerError> {
+        let top = self.frames.len() - 1;
+        let mut obligations: Vec<(Cube, Option<usize>)> = vec![(bad, None)];
+        let mut queue = BinaryHeap::from([Reverse((top, 0))]);
+        while let Some(Reverse((frame, n))) = queue.pop() {
+            if frame == 0 {
+                // the obligations from here to the bad cube form a trace
+                let mut trace = vec![];
+                let mut next = Some(n);
+                while let Some(n) = next {
+                    trace.push(self.model(&obligations[n].0));
+                    next = obligations[n].1;
+                }
+                return Ok(Some(trace));
+            }
+            let cube = obligations[n].0.clone();
+            if self.is_blocked(&cube, frame)? {
+                if frame < top {
+                    queue.push(Reverse((frame + 1, n)));
+                }
+                continue;
+            }
+            match self.relative(&cube, frame - 1)? {
+                Relative::Predecessor(predecessor) => {
+                    obligations.push((predecessor, Some(n)));
+                    queue.push(Reverse((frame - 1, obligations.len() - 1)));
+                    queue.push(Reverse((frame, n)));
+                }
+                Relative::Blocked(core) => {
+                    let mut cube = self.generalize(&cube, core, frame)?;
+                    // push the clause as far forward as it goes
+                    let mut level = frame;
+                    while level < top {
+                        match self.relative(&cube, level)? {
+                            Relative::Blocked(core) if !self.intersects_init(&core)? => {
+                                cube = core;
+                                level += 1;
+                            }
+                            Relative::Blocked(_) => level += 1,
+                            Relative::Predecessor(_) => break,
+                        }
+                    }
+                    self.add_clause(&cube, level);
+                    if frame < top {
+                        queue.push(Reverse((frame + 1, n)));
+                    }
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// Move clauses forward through the frames. Returns the clauses of a frame that is
+    /// equal to the next one, which are an inductive invariant.
+    fn propagate(&mut self) -> Result<Option<Vec<Clause>>, CheckerError> {
+        for frame in 1..self.frames.len() - 1 {
+            for clause in std::mem::take(&mut self.frames[frame]) {
+                let cube: Cube = clause
+                    .iter()
+                    .map(|literal| Literal {
+                        index: literal.index,
+                        value: !literal.value,
+                    })
+                    .collect();
+                match self.relative(&cube, frame)? {
+                    Relative::Blocked(_) => {
+                        self.assert_clause(&clause, frame + 1);
+                        self.frames[frame + 1].push(clause);
+                    }
+                    Relative::Predecessor(_) => self.frames[frame].push(clause),
+                }
+            }
+            if self.frames[frame].is_empty() {
+                // remove the clauses that are implied by a smaller clause
+                let mut invariant: Vec<Clause> = vec![];
+                for clause in self.frames[frame + 1..]
+                    .concat()
+                    .into_iter()
+                    .sorted_by_key(Vec::len)
+                {
+                    if !invariant
+                        .iter()
+                        .any(|smaller| smaller.iter().all(|literal| clause.contains(literal)))
+                    {
+                        invariant.push(clause);
+                    }
+                }
+                return Ok(Some(invariant));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Construct a `Model` from a cube that assigns every state variable.
+    fn model(&self, cube: &Cube) -> Model {
+        let mut values = vec![false; self.indices.num_vars];
+        for literal in cube {
+            values[literal.index] = literal.value;
+        }
+        self.indices.model(0, |i| values[i] as Element)
+    }
+
+    fn search(&mut self, depth: Option<usize>) -> Result<CheckerAnswer<Vec<Clause>>, CheckerError> {
+        // check the initial states directly
+        if let Some(cube) = self.bad_cube(0)? {
+            return Ok(CheckerAnswer::Counterexample(vec![self.model(&cube)]));
+        }
+        self.push_frame();
+
+        loop {
+            let top = self.frames.len() - 1;
+            println!(
+                "considering new frame: {top}. there are {} clauses in the frames.",
+                self.frames.iter().map(Vec::len).sum::<usize>()
+            );
+            // block every bad state in the last frame
+            while let Some(bad) = self.bad_cube(top)? {
+                if let Some(trace) = self.block(bad)? {
+                    return Ok(CheckerAnswer::Counterexample(trace));
+                }
+            }
+
+            if depth == Some(top) {
+                return Ok(CheckerAnswer::Unknown);
+            }
+            self.push_frame();
+            if let Some(invariant) = self.propagate()? {
+                return Ok(CheckerAnswer::Convergence(invariant));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fly::sorts::sort_check_module;
+    use std::collections::HashMap;
+
+    fn module(source: &str) -> Module {
+        let mut module = fly::parser::parse(source).unwrap();
+        sort_check_module(&mut module).unwrap();
+        module
+    }
+
+    #[test]
+    fn checker_ic3_lockserver() -> Result<(), CheckerError> {
+        let module = module(include_str!(
+            "../../temporal-verifier/examples/lockserver.fly"
+        ));
+        let universe = HashMap::from([("node".to_string(), 2)]);
+
+        let CheckerAnswer::Convergence((clauses, indices)) =
+            check(&module, &universe, None, false)?
+        else {
+            panic!("expected an invariant")
+        };
+        assert!(!clauses.is_empty());
+
+        // the invariant holds on the initial states, is inductive, and implies safety
+        let (program, _) = translate(&module, &universe)?;
+        let apps: HashMap<usize, Enumerated> = indices
+            .iter()
+            .flat_map(|(relation, map)| {
+                map.iter().map(|(elements, (i, _))| {
+                    (
+                        *i,
+                        Enumerated::App(relation.to_string(), 0, elements.clone()),
+                    )
+                })
+            })
+            .collect();
+        let invariant = Enumerated::And(
+            clauses
+                .iter()
+                .map(|clause| {
+                    Enumerated::Or(
+                        clause
+                            .iter()
+                            .map(|literal| {
+                                let app = apps[&literal.index].clone();
+                                match literal.value {
+                                    true => app,
+                                    false => Enumerated::Not(Box::new(app)),
+                                }
+                            })
+                            .collect(),
+                    )
+                })
+                .collect(),
+        );
+        let not = |term: &Enumerated| Enumerated::Not(Box::new(term.clone()));
+        let valid = |terms: Vec<Enumerated>| {
+            let mut solver = Unrolling::new(&indices);
+            for term in &terms {
+                solver.assert(term, 0);
+            }
+            solver.solver.solve() == Some(false)
+        };
+        assert!(valid(vec![program.init.clone(), not(&invariant)]));
+        assert!(valid(vec![
+            invariant.clone(),
+            program.tr.clone(),
+            not(&invariant.clone().prime(1)),
+        ]));
+        assert!(valid(vec![invariant.clone(), not(&program.safe)]));
+
+        // the invariant can be written over the elements of the universe
+        let (terms, bindings) = clauses_to_terms(&clauses, &indices);
+        assert_eq!(clauses.len(), terms.len());
+        assert_eq!(2, bindings.len());
+
+        Ok(())
+    }
+
+    #[test]
+    fn checker_ic3_lockserver_buggy() -> Result<(), CheckerError> {
+        let module = module(include_str!(
+            "../../temporal-verifier/tests/examples/lockserver_buggy.fly"
+        ));
+        let universe = HashMap::from([("node".to_string(), 2)]);
+
+        let bug = check(&module, &universe, None, false)?;
+        let CheckerAnswer::Counterexample(models) = bug else {
+            panic!("expected a counterexample")
+        };
+        let safe = fly::parser::term(
+            "forall N1:node, N2:node. holds_lock(N1) & holds_lock(N2) -> N1 = N2",
+        );
+        assert!(models.len() > 1);
+        assert_eq!(1, models[0].eval(&safe));
+        assert_eq!(0, models.last().unwrap().eval(&safe));
+
+        let too_short = check(&module, &universe, Some(1), false)?;
+        assert!(matches!(too_short, CheckerAnswer::Unknown));
+
+        Ok(())
+    }
+
+    #[test]
+    fn checker_ic3_functions() -> Result<(), CheckerError> {
+        let module = module(include_str!(
+            "../../temporal-verifier/tests/examples/ring_functions.fly"
+        ));
+        let universe = HashMap::from([("node".to_string(), 3)]);
+
+        let bug = check(&module, &universe, None, false)?;
+        let CheckerAnswer::Counterexample(models) = bug else {
+            panic!("expected a counterexample")
+        };
+        let owner = fly::parser::term("owner");
+        let next_owner = fly::parser::term("succ(owner)");
+        for (pre, post) in models.iter().zip(&models[1..]) {
+            assert_eq!(pre.eval(&next_owner), post.eval(&owner));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn checker_ic3_deadlock() -> Result<(), CheckerError> {
+        // the bad state has no successor, so it can only be found without a transition
+        let module = module(
+            "
+mutable x: bool
+assume !x
+assume always !x & x'
+assert always !x
+            ",
+        );
+        let universe = HashMap::new();
+
+        let bug = check(&module, &universe, None, false)?;
+        let CheckerAnswer::Counterexample(models) = bug else {
+            panic!("expected a counterexample")
+        };
+        let x = fly::parser::term("x");
+        let values = models
+            .iter()
+            .map(|model| model.eval(&x))
+            .collect::<Vec<_>>();
+        assert_eq!(values, vec![0, 1]);
+
+        Ok(())
+    }
+}
@@ -6,13 +6,16 @@
 use crate::quant_enum::*;
 use biodivine_lib_bdd::*;
 use fly::{ouritertools::OurItertools, semantics::*, syntax::*};
+use itertools::Itertools;
 use std::collections::HashMap;
 
 /// Holds a map from (relation name, arguments) pairs to a number. The number is used
 /// for different purposes depending on the checker, but this is common functionality
 /// among most of the bounded model checkers. This map also keeps track of the number
 /// of primes on mutable relations, and also supports creating unique indices that
-/// don't correspond to relations. Other features:
+/// don't correspond to relations. Functions into uninterpreted sorts are stored as
+/// one boolean per bit of their value (see [`value_bits`]), where bit `i` of `f(xs)`
+/// is keyed by the arguments `xs` followed by `i`. Other features:
 ///   - It also remembers the signature and universe that were used to create it,
 ///   because functions that need this object frequently also need the signature or
 ///   the universe, and this means that they don't need to accept them separately.
@@ -38,10 +41,10 @@ pub struct Indices<'a> {
     indices: HashMap<&'a str, HashMap<Vec<Element>, (usize, bool)>>,
 }
 
-impl Indices<'_> {
+impl<'a> Indices<'a> {
     /// Create a new `Indices` object from a signature, universe bounds, and the number
     /// of mutable copies to include.
-    pub fn new<'a>(
+    pub fn new(
         signature: &'a Signature,
         universe: &'a UniverseBounds,
         num_mutable_copies: usize,
@@ -51,10 +54,15 @@ impl Indices<'_> {
             .iter()
             .partition(|relation| relation.mutable);
         let elements = |relation: &&'a RelationDecl| {
+            let value = match relation.sort {
+                Sort::Bool => None,
+                _ => Some(value_bits(cardinality(universe, &relation.sort))),
+            };
             relation
                 .args
                 .iter()
                 .map(|sort| cardinality(universe, sort))
+                .chain(value)
                 .map(|card| (0..card).collect::<Vec<usize>>())
                 .multi_cartesian_product_fixed()
                 .map(|element| (relation.name.as_str(), element))
@@ -79,7 +87,18 @@ impl Indices<'_> {
         let num_vars = num_mutables * num_mutable_copies + num_immutables;
 
         let bdd_context = BddVariableSet::new_anonymous(num_vars.try_into().unwrap());
-        let bdd_variables = bdd_context.variables();
+        // interleave the copies of each mutable index in the variable order, so that
+        // relations between copies (like frame conditions) have small BDDs. Otherwise the
+        // transition relation is exponential in the number of unchanged indices: with the
+        // copies one after the other, bdd-check on lockserver with node=5 takes minutes
+        // rather than milliseconds.
+        let variables = bdd_context.variables();
+        let bdd_variables = (0..num_vars)
+            .map(|i| match i < num_mutables * num_mutable_copies {
+                true => variables[i % num_mutables * num_mutable_copies + i / num_mutables],
+                false => variables[i],
+            })
+            .collect();
 
         Indices {
             signature,
@@ -144,6 +163,66 @@ impl Indices<'_> {
         }
     }
 
+    /// Construct a `Term` for each index in one copy of the relations, where each element
+    /// of the universe is represented by an identifier.
+    /// Returns the terms and a map from (sort, element) pairs to the name of the identifier.
+    pub fn terms(&self) -> (HashMap<usize, Term>, HashMap<(&'a str, usize), String>) {
+        // Build a map from sort elements to Term variable names
+        let mut next_binding = 0;
+        let mut bindings: HashMap<(&str, usize), String> = HashMap::new();
+        for (sort, bound) in self.universe.iter().sorted() {
+            for i in 0..*bound {
+                bindings.insert((sort, i), format!("${next_binding}"));
+                next_binding += 1;
+            }
+        }
+
+        // Build a map from indices to Terms
+        let mut terms = HashMap::new();
+        for (relation, map) in self.iter() {
+            let relation = self.signature.relation_decl(relation);
+            for (elements, (i, _mutable)) in map {
+                let args: Vec<_> = relation
+                    .args
+                    .iter()
+                    .zip(elements)
+                    .map(|(sort, element)| match sort {
+                        Sort::Uninterpreted(sort) => {
+                            Term::Id(bindings[&(sort.as_str(), *element)].clone())
+                        }
+                        Sort::Bool => match element {
+                            0 => Term::Literal(false),
+                            1 => Term::Literal(true),
+                            _ => unreachable!(),
+                        },
+                    })
+                    .collect();
+                let app = match args.len() {
+                    0 => Term::Id(relation.name.clone()),
+                    _ => Term::App(relation.name.clone(), 0, args),
+                };
+                let term =
+                    match &relation.sort {
+                        Sort::Bool => app,
+                        // the index is one bit of the function's value
+                        Sort::Uninterpreted(sort) => {
+                            let bit = elements[relation.args.len()];
+                            Term::or((0..self.universe[sort]).filter(|v| v >> bit & 1 == 1).map(
+                                |v| {
+                                    Term::equals(
+                                        app.clone(),
+                                        Term::Id(bindings[&(sort.as_str(), v)].clone()),
+                                    )
+                                },
+                            ))
+                        }
+                    };
+                terms.insert(*i, term);
+            }
+        }
+        (terms, bindings)
+    }
+
     /// Construct a [`Model`] given a function over its indices at some time.
     pub fn model(&self, primes: usize, f: impl Fn(usize) -> usize) -> Model {
         Model::new(
@@ -158,13 +237,29 @@ impl Indices<'_> {
                 .relations
                 .iter()
                 .map(|r| {
+                    let card = cardinality(self.universe, &r.sort);
                     let shape = r
                         .args
                         .iter()
                         .map(|s| cardinality(self.universe, s))
-                        .chain([cardinality(self.universe, &r.sort)])
+                        .chain([card])
                         .collect();
-                    Interpretation::new(&shape, |xs| f(self.get(&r.name, primes, xs)))
+                    match r.sort {
+                        Sort::Bool => {
+                            Interpretation::new(&shape, |xs| f(self.get(&r.name, primes, xs)))
+                        }
+                        _ => Interpretation::new(&shape, |xs| {
+                            let mut xs = xs.to_vec();
+                            (0..value_bits(card))
+                                .map(|i| {
+                                    xs.push(i);
+                                    let bit = f(self.get(&r.name, primes, &xs));
+                                    xs.pop();
+                                    bit << i
+                                })
+                                .sum()
+                        }),
+                    }
                 })
                 .collect(),
         )
@@ -14,11 +14,14 @@
 #![allow(rustdoc::private_intra_doc_links)]
 #![deny(rustdoc::broken_intra_doc_links)]
 
+pub mod aiger;
 pub mod checker;
 pub mod indices;
 pub mod quant_enum;
 
 pub mod bdd;
+pub mod ic3;
 pub mod sat;
 pub mod set;
+pub mod simulate;
 pub mod smt;
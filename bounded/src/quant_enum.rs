@@ -59,10 +59,21 @@ pub fn enumerate_quantifiers(
     term: &Term,
     signature: &Signature,
     universe: &UniverseBounds,
+) -> Result<Enumerated, EnumerationError> {
+    enumerate_quantifiers_with(term, signature, universe, &HashMap::default())
+}
+
+/// Like [`enumerate_quantifiers`], but with the given free variables of the term
+/// assigned to elements of the universe.
+pub fn enumerate_quantifiers_with(
+    term: &Term,
+    signature: &Signature,
+    universe: &UniverseBounds,
+    assignments: &HashMap<String, Element>,
 ) -> Result<Enumerated, EnumerationError> {
     let term = nullary_id_to_app(term, &signature.relations);
     let term = fly::term::prime::Next::new(signature).normalize(&term);
-    term_to_enumerated(&term, universe, &HashMap::default())
+    term_to_enumerated(&term, signature, universe, assignments)
 }
 
 fn nullary_id_to_app(term: &Term, rs: &[RelationDecl]) -> Term {
@@ -188,23 +199,239 @@ impl Enumerated {
     }
 }
 
-fn term_to_enumerated(
+/// Returns the number of bits used to encode an element of a sort with `card` elements.
+/// Functions into that sort are represented by this many boolean variables per argument
+/// tuple, where variable `i` is the `i`th bit of the function's value.
+pub fn value_bits(card: usize) -> usize {
+    (usize::BITS - card.saturating_sub(1).leading_zeros()) as usize
+}
+
+/// Constrains the values of all functions with the given mutability to be elements of
+/// their sort. This is only a nontrivial constraint for sorts whose cardinality isn't
+/// a power of two, where some bit patterns don't correspond to any element.
+pub fn value_constraints(
+    signature: &Signature,
+    universe: &UniverseBounds,
+    mutable: bool,
+) -> Enumerated {
+    let mut constraints = vec![];
+    for relation in &signature.relations {
+        if relation.mutable != mutable || relation.sort == Sort::Bool {
+            continue;
+        }
+        let card = cardinality(universe, &relation.sort);
+        for args in relation
+            .args
+            .iter()
+            .map(|sort| (0..cardinality(universe, sort)).collect::<Vec<Element>>())
+            .multi_cartesian_product_fixed()
+        {
+            let bits = (0..value_bits(card))
+                .map(|i| {
+                    let mut elements = args.clone();
+                    elements.push(i);
+                    Enumerated::App(relation.name.clone(), 0, elements)
+                })
+                .collect::<Vec<_>>();
+            constraints.push(Enumerated::less_than(&bits, card));
+        }
+    }
+    Enumerated::and(constraints)
+}
+
+impl Enumerated {
+    /// Constructs the term that represents the given boolean.
+    fn constant(value: bool) -> Enumerated {
+        if value {
+            Enumerated::always_true()
+        } else {
+            Enumerated::always_false()
+        }
+    }
+
+    /// Constructs a bi-implication, doing minor simplifications.
+    fn iff(a: Enumerated, b: Enumerated) -> Enumerated {
+        if a == Enumerated::always_true() {
+            b
+        } else if a == Enumerated::always_false() {
+            b.not()
+        } else if b == Enumerated::always_true() {
+            a
+        } else if b == Enumerated::always_false() {
+            a.not()
+        } else if a == b {
+            Enumerated::always_true()
+        } else {
+            Enumerated::Eq(Box::new(a), Box::new(b))
+        }
+    }
+
+    /// Constructs the term that is true when the given bits (least significant first)
+    /// encode `value`.
+    fn equals_value(bits: &[Enumerated], value: Element) -> Enumerated {
+        Enumerated::and(bits.iter().enumerate().map(|(i, bit)| {
+            Enumerated::iff(bit.clone(), Enumerated::constant(value >> i & 1 == 1))
+        }))
+    }
+
+    /// Constructs the term that is true when the given bits (least significant first)
+    /// encode a number less than `bound`.
+    fn less_than(bits: &[Enumerated], bound: usize) -> Enumerated {
+        if bits.len() >= usize::BITS as usize || bound >= 1 << bits.len() {
+            return Enumerated::always_true();
+        }
+        // Some bit that is set in `bound` is unset in the value,
+        // and all of the higher bits are the same.
+        Enumerated::or((0..bits.len()).filter(|i| bound >> i & 1 == 1).map(|i| {
+            Enumerated::and(
+                [bits[i].clone().not()]
+                    .into_iter()
+                    .chain((i + 1..bits.len()).map(|j| {
+                        Enumerated::iff(bits[j].clone(), Enumerated::constant(bound >> j & 1 == 1))
+                    })),
+            )
+        }))
+    }
+}
+
+/// Returns the sort of the given term if it evaluates to an element of an uninterpreted
+/// sort through some function, or `None` otherwise.
+fn value_sort<'a>(term: &Term, signature: &'a Signature) -> Option<&'a Sort> {
+    match term {
+        Term::App(name, _, _) => signature
+            .relations
+            .iter()
+            .find(|r| r.name == *name)
+            .map(|r| &r.sort)
+            .filter(|sort| **sort != Sort::Bool),
+        Term::Ite { then, else_, .. } => {
+            value_sort(then, signature).or_else(|| value_sort(else_, signature))
+        }
+        _ => None,
+    }
+}
+
+/// Statically evaluates a term to an element.
+fn term_to_element(
     term: &Term,
+    signature: &Signature,
     universe: &UniverseBounds,
     assignments: &HashMap<String, Element>,
-) -> Result<Enumerated, EnumerationError> {
-    let go = |term| term_to_enumerated(term, universe, assignments);
-    let element = |term: &Term| match term {
+) -> Result<Element, EnumerationError> {
+    match term {
         Term::Id(id) => match assignments.get(id) {
             Some(x) => Ok(*x),
             None => Err(EnumerationError::UnknownId(term.clone())),
         },
-        term => match term_to_enumerated(term, universe, assignments) {
+        term if value_sort(term, signature).is_some() => {
+            Err(EnumerationError::NotAnElement(term.clone()))
+        }
+        term => match term_to_enumerated(term, signature, universe, assignments) {
             Ok(formula) if formula == Enumerated::always_true() => Ok(1),
             Ok(formula) if formula == Enumerated::always_false() => Ok(0),
             _ => Err(EnumerationError::NotAnElement(term.clone())),
         },
-    };
+    }
+}
+
+/// Translates a term that evaluates to an element of a sort with `card` elements
+/// into the bits of its value, least significant first.
+fn term_to_bits(
+    term: &Term,
+    card: usize,
+    signature: &Signature,
+    universe: &UniverseBounds,
+    assignments: &HashMap<String, Element>,
+) -> Result<Vec<Enumerated>, EnumerationError> {
+    match term {
+        Term::App(name, primes, args) if value_sort(term, signature).is_some() => {
+            if *primes > 1 {
+                return Err(EnumerationError::TooManyPrimes(term.clone()));
+            }
+            let cases = args_to_cases(args, signature, universe, assignments)?;
+            Ok((0..value_bits(card))
+                .map(|i| {
+                    Enumerated::or(cases.iter().map(|(cond, elements)| {
+                        let mut elements = elements.clone();
+                        elements.push(i);
+                        Enumerated::and([
+                            cond.clone(),
+                            Enumerated::App(name.clone(), *primes, elements),
+                        ])
+                    }))
+                })
+                .collect())
+        }
+        Term::Ite { cond, then, else_ } => {
+            let cond = term_to_enumerated(cond, signature, universe, assignments)?;
+            let then = term_to_bits(then, card, signature, universe, assignments)?;
+            let else_ = term_to_bits(else_, card, signature, universe, assignments)?;
+            Ok(then
+                .into_iter()
+                .zip_eq(else_)
+                .map(|(then, else_)| {
+                    Enumerated::or([
+                        Enumerated::and([cond.clone(), then]),
+                        Enumerated::and([cond.clone().not(), else_]),
+                    ])
+                })
+                .collect())
+        }
+        term => {
+            let value = term_to_element(term, signature, universe, assignments)?;
+            Ok((0..value_bits(card))
+                .map(|i| Enumerated::constant(value >> i & 1 == 1))
+                .collect())
+        }
+    }
+}
+
+/// Translates the arguments of an application into the possible tuples of elements
+/// they can evaluate to, each paired with the condition under which it does. Arguments
+/// that are applications of functions are split into one case per possible value.
+fn args_to_cases(
+    args: &[Term],
+    signature: &Signature,
+    universe: &UniverseBounds,
+    assignments: &HashMap<String, Element>,
+) -> Result<Vec<(Enumerated, Vec<Element>)>, EnumerationError> {
+    let values = args
+        .iter()
+        .map(|arg| match value_sort(arg, signature) {
+            Some(sort) => {
+                let card = cardinality(universe, sort);
+                let bits = term_to_bits(arg, card, signature, universe, assignments)?;
+                Ok((0..card)
+                    .map(|value| (Enumerated::equals_value(&bits, value), value))
+                    .filter(|(cond, _)| *cond != Enumerated::always_false())
+                    .collect::<Vec<_>>())
+            }
+            None => Ok(vec![(
+                Enumerated::always_true(),
+                term_to_element(arg, signature, universe, assignments)?,
+            )]),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(values
+        .into_iter()
+        .multi_cartesian_product_fixed()
+        .map(|case| {
+            let (conds, elements): (Vec<_>, Vec<_>) = case.into_iter().unzip();
+            (Enumerated::and(conds), elements)
+        })
+        .filter(|(cond, _)| *cond != Enumerated::always_false())
+        .collect())
+}
+
+fn term_to_enumerated(
+    term: &Term,
+    signature: &Signature,
+    universe: &UniverseBounds,
+    assignments: &HashMap<String, Element>,
+) -> Result<Enumerated, EnumerationError> {
+    let go = |term| term_to_enumerated(term, signature, universe, assignments);
+    let element = |term| term_to_element(term, signature, universe, assignments);
+    let bits = |term, card| term_to_bits(term, card, signature, universe, assignments);
 
     let enumerated = match term {
         Term::Literal(true) => Enumerated::always_true(),
@@ -218,15 +445,33 @@ fn term_to_enumerated(
             if *primes > 1 {
                 return Err(EnumerationError::TooManyPrimes(term.clone()));
             }
-            let args = args.iter().map(element).collect::<Result<Vec<_>, _>>()?;
-            Enumerated::App(name.clone(), *primes, args)
+            if value_sort(term, signature).is_some() {
+                return Err(EnumerationError::NotAnElement(term.clone()));
+            }
+            let cases = args_to_cases(args, signature, universe, assignments)?;
+            Enumerated::or(cases.into_iter().map(|(cond, elements)| {
+                Enumerated::and([cond, Enumerated::App(name.clone(), *primes, elements)])
+            }))
         }
         Term::UnaryOp(UOp::Not, term) => go(term)?.not(),
-        Term::BinOp(BinOp::Equals | BinOp::Iff, a, b) => match (element(a), element(b)) {
-            (Ok(a), Ok(b)) if a == b => Enumerated::always_true(),
-            (Ok(a), Ok(b)) if a != b => Enumerated::always_false(),
-            _ => Enumerated::Eq(Box::new(go(a)?), Box::new(go(b)?)),
-        },
+        Term::BinOp(BinOp::Equals | BinOp::Iff, a, b) => {
+            match value_sort(a, signature).or_else(|| value_sort(b, signature)) {
+                Some(sort) => {
+                    let card = cardinality(universe, sort);
+                    Enumerated::and(
+                        bits(a, card)?
+                            .into_iter()
+                            .zip_eq(bits(b, card)?)
+                            .map(|(a, b)| Enumerated::iff(a, b)),
+                    )
+                }
+                None => match (element(a), element(b)) {
+                    (Ok(a), Ok(b)) if a == b => Enumerated::always_true(),
+                    (Ok(a), Ok(b)) if a != b => Enumerated::always_false(),
+                    _ => Enumerated::Eq(Box::new(go(a)?), Box::new(go(b)?)),
+                },
+            }
+        }
         Term::BinOp(BinOp::NotEquals, a, b) => {
             go(&Term::BinOp(BinOp::Equals, a.clone(), b.clone()))?.not()
         }
@@ -260,7 +505,7 @@ fn term_to_enumerated(
                     for (binder, element) in binders.iter().zip_eq(elements) {
                         new_assignments.insert(binder.name.clone(), element);
                     }
-                    term_to_enumerated(body, universe, &new_assignments)
+                    term_to_enumerated(body, signature, universe, &new_assignments)
                 })
                 .collect::<Result<Vec<_>, _>>()?;
             match quantifier {
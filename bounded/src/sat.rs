@@ -9,24 +9,111 @@ use crate::{checker::*, indices::*, quant_enum::*};
 use cadical::Solver;
 use fly::{semantics::*, syntax::*, transitions::*};
 
-/// Check a given Module out to some depth.
+/// Check a given Module out to some depth, or forever if `depth` is `None`. The solver is
+/// incremental: each step adds one more copy of the transition relation and checks for a
+/// bad state at the end of the trace under an assumption.
+/// With `k_induction`, each step also tries to show that no simple path of safe states can
+/// be followed by a bad state, which proves that the module is safe at any depth for the
+/// given universe, since the longest simple path in a finite instance is bounded.
+/// Without a `depth`, k-induction is always used, since otherwise the search would never
+/// terminate on a safe module.
 /// This function assumes that the module has been typechecked.
 /// The checker ignores proof blocks.
 pub fn check(
     module: &Module,
     universe: &UniverseBounds,
-    depth: usize,
+    depth: Option<usize>,
+    k_induction: bool,
     print_timing: bool,
 ) -> Result<CheckerAnswer<()>, CheckerError> {
-    for sort in &module.signature.sorts {
-        if !universe.contains_key(sort) {
-            return Err(CheckerError::UnknownSort(sort.clone(), universe.clone()));
+    println!("starting translation...");
+    let translation = std::time::Instant::now();
+
+    let (program, indices) = translate(module, universe)?;
+    let Program {
+        init,
+        start,
+        tr,
+        safe,
+    } = program;
+    let not_safe = Enumerated::Not(Box::new(safe.clone()));
+
+    if print_timing {
+        println!(
+            "translation finished in {:0.1}s",
+            translation.elapsed().as_secs_f64()
+        );
+    }
+
+    println!("starting search...");
+    let search = std::time::Instant::now();
+
+    let mut bmc = Unrolling::new(&indices);
+    bmc.assert(&init, 0);
+    let mut induction = (k_induction || depth.is_none()).then(|| {
+        let mut induction = Unrolling::new(&indices);
+        induction.assert(&start, 0);
+        induction
+    });
+
+    let mut k = 0;
+    let answer = loop {
+        // is there a trace of length k that ends in a bad state?
+        let bad = bmc.literal(&not_safe, k);
+        match bmc.solver.solve_with([bad].into_iter()) {
+            None => break Err(CheckerError::SatSolverFailed),
+            Some(true) => break Ok(CheckerAnswer::Counterexample(bmc.models(k))),
+            Some(false) => bmc.solver.add_clause([-bad]),
+        }
+
+        // is there a simple path of k + 1 safe states that is followed by a bad state?
+        if let Some(induction) = &mut induction {
+            induction.assert(&safe, k);
+            induction.assert(&tr, k);
+            induction.distinct(k + 1);
+            let bad = induction.literal(&not_safe, k + 1);
+            match induction.solver.solve_with([bad].into_iter()) {
+                None => break Err(CheckerError::SatSolverFailed),
+                Some(true) => {}
+                Some(false) => break Ok(CheckerAnswer::Convergence(())),
+            }
+        }
+
+        if depth == Some(k) {
+            break Ok(CheckerAnswer::Unknown);
         }
+        bmc.assert(&tr, k);
+        k += 1;
+    };
+
+    if print_timing {
+        println!("search finished in {:0.1}s", search.elapsed().as_secs_f64());
     }
 
-    for relation in &module.signature.relations {
-        if relation.sort != Sort::Bool {
-            panic!("non-bool relations in checker (use Module::convert_non_bool_relations)")
+    answer
+}
+
+/// A module translated into terms over the indices of a single transition
+/// (see [`Indices`] with two mutable copies).
+pub(crate) struct Program {
+    /// The initial states
+    pub(crate) init: Enumerated,
+    /// The states that satisfy the axioms, which is where an induction step starts
+    pub(crate) start: Enumerated,
+    /// The transition relation
+    pub(crate) tr: Enumerated,
+    /// The safety property
+    pub(crate) safe: Enumerated,
+}
+
+/// Translate a flyvy module into a [`Program`], given the bounds on the sort sizes.
+pub(crate) fn translate<'a>(
+    module: &'a Module,
+    universe: &'a UniverseBounds,
+) -> Result<(Program, Indices<'a>), CheckerError> {
+    for sort in &module.signature.sorts {
+        if !universe.contains_key(sort) {
+            return Err(CheckerError::UnknownSort(sort.clone(), universe.clone()));
         }
     }
 
@@ -43,135 +130,164 @@ pub fn check(
         .cloned();
     let safeties = d.proofs.iter().map(|proof| proof.safety.x.clone());
 
-    let mut indices = Indices::new(&module.signature, universe, depth + 1);
+    let indices = Indices::new(&module.signature, universe, 2);
 
     let translate = |term| {
         enumerate_quantifiers(&term, &module.signature, universe)
             .map_err(CheckerError::EnumerationError)
     };
 
-    println!("starting translation...");
-    let translation = std::time::Instant::now();
-
-    let init = translate(Term::and(inits))?;
-    let tr = translate(Term::and(transitions))?;
-    let not_safe = Enumerated::Not(Box::new(translate(Term::and(safeties))?));
-
-    let mut program = vec![init];
-    for i in 0..depth {
-        program.push(tr.clone().prime(i));
-    }
-    program.push(not_safe.prime(depth));
+    // functions have to stay in range in every state
+    let in_range = Enumerated::And(vec![
+        value_constraints(&module.signature, universe, false),
+        value_constraints(&module.signature, universe, true),
+    ]);
+    let program = Program {
+        init: Enumerated::And(vec![translate(Term::and(inits))?, in_range.clone()]),
+        start: Enumerated::And(vec![translate(Term::and(d.axioms.clone()))?, in_range]),
+        tr: Enumerated::And(vec![
+            translate(Term::and(transitions))?,
+            value_constraints(&module.signature, universe, true).prime(1),
+        ]),
+        safe: translate(Term::and(safeties))?,
+    };
+    Ok((program, indices))
+}
 
-    let cnf = tseytin(&Enumerated::And(program), &mut indices);
+/// A SAT solver that holds an unrolling of a transition system. The indices of a single
+/// transition (see [`Indices`] with two mutable copies) are mapped onto the solver
+/// variables of each step, which are allocated as the unrolling grows.
+pub(crate) struct Unrolling<'a> {
+    indices: &'a Indices<'a>,
+    pub(crate) solver: Solver,
+    /// The solver variables for the mutable indices of each state
+    states: Vec<Vec<i32>>,
+    /// The solver variables for the immutable indices, which are shared by all states
+    immutables: Vec<i32>,
+    /// The number of solver variables that have been allocated
+    num_vars: i32,
+}
 
-    if print_timing {
-        println!(
-            "translation finished in {:0.1}s",
-            translation.elapsed().as_secs_f64()
-        );
+impl<'a> Unrolling<'a> {
+    pub(crate) fn new(indices: &'a Indices<'a>) -> Unrolling<'a> {
+        let num_immutables = indices.num_vars - indices.num_mutables * 2;
+        let mut unrolling = Unrolling {
+            indices,
+            solver: Solver::default(),
+            states: vec![],
+            immutables: vec![],
+            num_vars: 0,
+        };
+        unrolling.immutables = (0..num_immutables).map(|_| unrolling.fresh()).collect();
+        unrolling
     }
 
-    println!("starting search...");
-    let search = std::time::Instant::now();
-
-    let mut solver: Solver = Default::default();
-    for clause in &cnf {
-        let cadical_clause = clause
-            .iter()
-            .map(|l| (l.var as i32 + 1) * if l.pos { 1 } else { -1 });
-        solver.add_clause(cadical_clause);
+    /// Allocate a new solver variable.
+    pub(crate) fn fresh(&mut self) -> i32 {
+        self.num_vars += 1;
+        self.num_vars
     }
 
-    let answer = match solver.solve() {
-        None => Err(CheckerError::SatSolverFailed),
-        Some(false) => Ok(CheckerAnswer::Unknown),
-        Some(true) => Ok(CheckerAnswer::Counterexample(
-            (0..indices.num_mutable_copies)
-                .map(|primes| {
-                    indices.model(primes, |i| {
-                        solver.value(i as i32 + 1).unwrap_or(false) as Element
-                    })
-                })
-                .collect(),
-        )),
-    };
-
-    if print_timing {
-        println!("search finished in {:0.1}s", search.elapsed().as_secs_f64());
+    /// Get the solver variables for the mutable indices of the state at the given time.
+    fn state(&mut self, time: usize) -> &[i32] {
+        while self.states.len() <= time {
+            let state = (0..self.indices.num_mutables)
+                .map(|_| self.fresh())
+                .collect();
+            self.states.push(state);
+        }
+        &self.states[time]
     }
 
-    answer
-}
-
-#[derive(Clone, Debug, PartialEq)]
-struct Literal {
-    var: usize,
-    pos: bool,
-}
-type Clause = Vec<Literal>;
-type Cnf = Vec<Clause>;
-
-impl Literal {
-    fn t(var: usize) -> Literal {
-        Literal { var, pos: true }
-    }
-    fn f(var: usize) -> Literal {
-        Literal { var, pos: false }
+    /// Get the solver variable for an index of the transition that starts at the given time.
+    pub(crate) fn var(&mut self, time: usize, index: usize) -> i32 {
+        let num_mutables = self.indices.num_mutables;
+        if index < num_mutables {
+            self.state(time)[index]
+        } else if index < num_mutables * 2 {
+            self.state(time + 1)[index - num_mutables]
+        } else {
+            self.immutables[index - num_mutables * 2]
+        }
     }
-}
 
-fn tseytin(term: &Enumerated, indices: &mut Indices) -> Cnf {
-    fn inner(term: &Enumerated, indices: &mut Indices, out: &mut Cnf) -> usize {
-        let mut go = |term| inner(term, indices, out);
+    /// Add clauses to the solver that define a new literal to be equivalent to the
+    /// given term at the given time using the Tseytin transformation, and return it.
+    pub(crate) fn literal(&mut self, term: &Enumerated, time: usize) -> i32 {
         match term {
             Enumerated::And(terms) => {
-                let olds: Vec<_> = terms.iter().map(go).collect();
-                let new = indices.var();
+                let olds: Vec<_> = terms.iter().map(|term| self.literal(term, time)).collect();
+                let new = self.fresh();
                 for old in &olds {
-                    out.push(vec![Literal::t(*old), Literal::f(new)]);
+                    self.solver.add_clause([*old, -new]);
                 }
-                let mut clause: Vec<_> = olds.into_iter().map(Literal::f).collect();
-                clause.push(Literal::t(new));
-                out.push(clause);
+                self.solver
+                    .add_clause(olds.into_iter().map(|old| -old).chain([new]));
                 new
             }
             Enumerated::Or(terms) => {
-                let olds: Vec<_> = terms.iter().map(go).collect();
-                let new = indices.var();
+                let olds: Vec<_> = terms.iter().map(|term| self.literal(term, time)).collect();
+                let new = self.fresh();
                 for old in &olds {
-                    out.push(vec![Literal::f(*old), Literal::t(new)]);
+                    self.solver.add_clause([-*old, new]);
                 }
-                let mut clause: Vec<_> = olds.into_iter().map(Literal::t).collect();
-                clause.push(Literal::f(new));
-                out.push(clause);
-                new
-            }
-            Enumerated::Not(term) => {
-                let old = go(term);
-                let new = indices.var();
-                out.push(vec![Literal::t(old), Literal::t(new)]);
-                out.push(vec![Literal::f(old), Literal::f(new)]);
+                self.solver.add_clause(olds.into_iter().chain([-new]));
                 new
             }
+            Enumerated::Not(term) => -self.literal(term, time),
             Enumerated::Eq(left, right) => {
-                let a = go(left);
-                let b = go(right);
-                let c = indices.var();
-                out.push(vec![Literal::f(a), Literal::f(b), Literal::t(c)]);
-                out.push(vec![Literal::f(a), Literal::t(b), Literal::f(c)]);
-                out.push(vec![Literal::t(a), Literal::f(b), Literal::f(c)]);
-                out.push(vec![Literal::t(a), Literal::t(b), Literal::t(c)]);
+                let a = self.literal(left, time);
+                let b = self.literal(right, time);
+                let c = self.fresh();
+                self.solver.add_clause([-a, -b, c]);
+                self.solver.add_clause([-a, b, -c]);
+                self.solver.add_clause([a, -b, -c]);
+                self.solver.add_clause([a, b, c]);
                 c
             }
-            Enumerated::App(relation, primes, elements) => indices.get(relation, *primes, elements),
+            Enumerated::App(relation, primes, elements) => {
+                let index = self.indices.get(relation, *primes, elements);
+                self.var(time, index)
+            }
+        }
+    }
+
+    /// Add the given term at the given time to the solver.
+    pub(crate) fn assert(&mut self, term: &Enumerated, time: usize) {
+        let literal = self.literal(term, time);
+        self.solver.add_clause([literal]);
+    }
+
+    /// Constrain the state at the given time to be different from every earlier state.
+    fn distinct(&mut self, time: usize) {
+        for earlier in 0..time {
+            let diffs: Vec<_> = (0..self.indices.num_mutables)
+                .map(|i| {
+                    let a = self.state(earlier)[i];
+                    let b = self.state(time)[i];
+                    let diff = self.fresh();
+                    self.solver.add_clause([-diff, a, b]);
+                    self.solver.add_clause([-diff, -a, -b]);
+                    diff
+                })
+                .collect();
+            self.solver.add_clause(diffs);
         }
     }
 
-    let mut out = vec![];
-    let var = inner(term, indices, &mut out);
-    out.push(vec![Literal::t(var)]);
-    out
+    /// Read the states up to the given depth out of a satisfying assignment.
+    fn models(&mut self, depth: usize) -> Vec<Model> {
+        (0..=depth)
+            .map(|time| {
+                let vars: Vec<_> = (0..self.indices.num_mutables * 2 + self.immutables.len())
+                    .map(|index| self.var(time, index))
+                    .collect();
+                self.indices.model(0, |index| {
+                    self.solver.value(vars[index]).unwrap_or(false) as Element
+                })
+            })
+            .collect()
+    }
 }
 
 #[cfg(test)]
@@ -188,9 +304,12 @@ mod tests {
         sort_check_module(&mut module).unwrap();
         let universe = HashMap::from([]);
 
-        assert_eq!(CheckerAnswer::Unknown, check(&module, &universe, 0, false)?);
+        assert_eq!(
+            CheckerAnswer::Unknown,
+            check(&module, &universe, Some(0), false, false)?
+        );
         assert!(matches!(
-            check(&module, &universe, 1, false)?,
+            check(&module, &universe, Some(1), false, false)?,
             CheckerAnswer::Counterexample(..),
         ));
 
@@ -207,7 +326,7 @@ mod tests {
 
         assert_eq!(
             CheckerAnswer::Unknown,
-            check(&module, &universe, 10, false)?
+            check(&module, &universe, Some(10), false, false)?
         );
 
         Ok(())
@@ -221,15 +340,54 @@ mod tests {
         sort_check_module(&mut module).unwrap();
         let universe = HashMap::from([("node".to_string(), 2)]);
 
-        let bug = check(&module, &universe, 12, false)?;
-        assert!(matches!(bug, CheckerAnswer::Counterexample(..)));
+        let bug = check(&module, &universe, Some(12), false, false)?;
+        let CheckerAnswer::Counterexample(models) = bug else {
+            panic!("expected a counterexample but got {bug:?}")
+        };
+        // the incremental search returns the shortest counterexample
+        assert_eq!(13, models.len());
 
-        let too_short = check(&module, &universe, 11, false)?;
+        let too_short = check(&module, &universe, Some(11), false, false)?;
         assert_eq!(CheckerAnswer::Unknown, too_short);
 
         Ok(())
     }
 
+    #[test]
+    fn checker_sat_k_induction() -> Result<(), CheckerError> {
+        let source = include_str!("../../temporal-verifier/examples/lockserver.fly");
+        let mut module = fly::parser::parse(source).unwrap();
+        sort_check_module(&mut module).unwrap();
+        let universe = HashMap::from([("node".to_string(), 1)]);
+        assert_eq!(
+            CheckerAnswer::Convergence(()),
+            check(&module, &universe, None, true, false)?
+        );
+
+        // the base case still finds the shortest counterexample
+        let source = include_str!("../../temporal-verifier/tests/examples/lockserver_buggy.fly");
+        let mut module = fly::parser::parse(source).unwrap();
+        sort_check_module(&mut module).unwrap();
+        let universe = HashMap::from([("node".to_string(), 2)]);
+        let bug = check(&module, &universe, None, true, false)?;
+        let CheckerAnswer::Counterexample(models) = bug else {
+            panic!("expected a counterexample but got {bug:?}")
+        };
+        assert_eq!(13, models.len());
+
+        // an unbounded search uses k-induction even if it isn't asked for
+        let source = include_str!("../../temporal-verifier/examples/lockserver.fly");
+        let mut module = fly::parser::parse(source).unwrap();
+        sort_check_module(&mut module).unwrap();
+        let universe = HashMap::from([("node".to_string(), 1)]);
+        assert_eq!(
+            CheckerAnswer::Convergence(()),
+            check(&module, &universe, None, false, false)?
+        );
+
+        Ok(())
+    }
+
     #[test]
     fn checker_sat_consensus() -> Result<(), CheckerError> {
         let source = include_str!("../../temporal-verifier/examples/consensus.fly");
@@ -244,12 +402,38 @@ mod tests {
 
         assert_eq!(
             CheckerAnswer::Unknown,
-            check(&module, &universe, 10, false)?
+            check(&module, &universe, Some(10), false, false)?
         );
 
         Ok(())
     }
 
+    #[test]
+    fn checker_sat_functions() -> Result<(), CheckerError> {
+        let source = include_str!("../../temporal-verifier/tests/examples/ring_functions.fly");
+        let mut module = fly::parser::parse(source).unwrap();
+        sort_check_module(&mut module).unwrap();
+        let universe = std::collections::HashMap::from([("node".to_string(), 3)]);
+
+        assert_eq!(
+            CheckerAnswer::Unknown,
+            check(&module, &universe, Some(1), false, false)?
+        );
+        let CheckerAnswer::Counterexample(models) =
+            check(&module, &universe, Some(2), false, false)?
+        else {
+            panic!("expected a counterexample")
+        };
+        assert_eq!(3, models.len());
+        let owner = fly::parser::term("owner");
+        let next_owner = fly::parser::term("succ(owner)");
+        for (pre, post) in models.iter().zip(&models[1..]) {
+            assert!(pre.eval(&owner) < 3);
+            assert_eq!(pre.eval(&next_owner), post.eval(&owner));
+        }
+        Ok(())
+    }
+
     #[test]
     fn checker_sat_immutability() -> Result<(), CheckerError> {
         let source =
@@ -259,7 +443,11 @@ mod tests {
         let universe = std::collections::HashMap::new();
         assert_eq!(
             CheckerAnswer::Unknown,
-            check(&module, &universe, 10, false)?
+            check(&module, &universe, Some(10), false, false)?
+        );
+        assert_eq!(
+            CheckerAnswer::Convergence(()),
+            check(&module, &universe, None, true, false)?
         );
         Ok(())
     }
@@ -2,19 +2,63 @@
 // SPDX-License-Identifier: BSD-2-Clause
 
 //! A bounded model checker for flyvy programs. Use `translate` to turn a flyvy `Module`
-//! into a `BoundedProgram`, then use `interpret` to evaluate it.
+//! into a `BoundedProgram`, then use `interpret` to evaluate it, or `walk_interpret` to search
+//! it with random walks.
+//!
+//! `interpret` expands each depth of the search in parallel, and can be configured with a
+//! [`SearchConfig`] to remember fingerprints of states instead of the states themselves and
+//! to spill the frontier to disk.
 
 use crate::{checker::*, indices::*, quant_enum::*};
 use bitvec::prelude::*;
 use fly::{ouritertools::OurItertools, semantics::*, syntax::*, transitions::*};
 use itertools::Itertools;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use rayon::prelude::*;
 use std::collections::VecDeque;
 use std::fmt::{Debug, Formatter};
+use std::fs::File;
+use std::hash::{Hash, Hasher};
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 // We use FxHashMap and FxHashSet because the hash function performance is about 25% faster
 // and the bounded model checker is essentially a hashing microbenchmark :)
 use fxhash::{FxHashMap as HashMap, FxHashSet as HashSet};
 
+/// The largest number of bits in a state that the set checker supports.
+const MAX_STATE_LEN: usize = BoundedState::<256>::LEN;
+
+/// Call a function that is generic over the width of states with the smallest width that
+/// has at least the given number of bits, or return an error if there is no such width.
+macro_rules! with_state_width {
+    ($bits:expr, $f:ident($($arg:expr),* $(,)?)) => {{
+        let bits: usize = $bits;
+        match bits.div_ceil(usize::BITS as usize) {
+            0..=2 => $f::<2>($($arg),*),
+            3..=4 => $f::<4>($($arg),*),
+            5..=8 => $f::<8>($($arg),*),
+            9..=16 => $f::<16>($($arg),*),
+            17..=32 => $f::<32>($($arg),*),
+            33..=64 => $f::<64>($($arg),*),
+            65..=128 => $f::<128>($($arg),*),
+            129..=256 => $f::<256>($($arg),*),
+            _ => Err(CheckerError::StateTooLarge(bits, MAX_STATE_LEN)),
+        }
+    }};
+}
+
+/// The number of bits in a state of the module at the given sort bounds.
+fn state_len(module: &Module, universe: &UniverseBounds) -> Result<usize, CheckerError> {
+    for sort in &module.signature.sorts {
+        if !universe.contains_key(sort) {
+            return Err(CheckerError::UnknownSort(sort.clone(), universe.clone()));
+        }
+    }
+    Ok(Indices::new(&module.signature, universe, 1).num_vars)
+}
+
 /// Combined entry point to both translate and search the module.
 pub fn check(
     module: &Module,
@@ -23,8 +67,72 @@ pub fn check(
     compress_traces: TraceCompression,
     print_timing: bool,
 ) -> Result<CheckerAnswer<()>, CheckerError> {
-    let (program, indices) = translate(module, universe, print_timing)?;
-    match interpret(&program, depth, compress_traces, print_timing, &indices) {
+    check_with(
+        module,
+        universe,
+        depth,
+        compress_traces,
+        print_timing,
+        &SearchConfig::default(),
+    )
+}
+
+/// Like [`check`], but with control over how the search uses memory and threads.
+pub fn check_with(
+    module: &Module,
+    universe: &UniverseBounds,
+    depth: Option<usize>,
+    compress_traces: TraceCompression,
+    print_timing: bool,
+    config: &SearchConfig,
+) -> Result<CheckerAnswer<()>, CheckerError> {
+    with_state_width!(
+        state_len(module, universe)?,
+        check_width(
+            module,
+            universe,
+            depth,
+            compress_traces,
+            print_timing,
+            config
+        )
+    )
+}
+
+fn check_width<const W: usize>(
+    module: &Module,
+    universe: &UniverseBounds,
+    depth: Option<usize>,
+    compress_traces: TraceCompression,
+    print_timing: bool,
+    config: &SearchConfig,
+) -> Result<CheckerAnswer<()>, CheckerError> {
+    let (program, indices) = translate::<W>(module, universe, print_timing)?;
+    let result = match config.threads {
+        None => interpret(
+            &program,
+            depth,
+            compress_traces,
+            print_timing,
+            &indices,
+            config,
+        )?,
+        Some(threads) => rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()
+            .map_err(|e| CheckerError::ThreadPool(e.to_string()))?
+            .install(|| {
+                interpret(
+                    &program,
+                    depth,
+                    compress_traces,
+                    print_timing,
+                    &indices,
+                    config,
+                )
+            })?,
+    };
+    match result {
         InterpreterResult::Unknown => Ok(CheckerAnswer::Unknown),
         InterpreterResult::Convergence => Ok(CheckerAnswer::Convergence(())),
         InterpreterResult::Counterexample(trace) => {
@@ -50,17 +158,175 @@ pub fn check(
     }
 }
 
-/// Compile-time upper bound on the bounded universe size.
-const STATE_LEN: usize = 128;
+/// Combined entry point to translate the module and enumerate its reachable states, up to
+/// the given depth if there is one. Only one state is kept out of each set of states that are
+/// equal up to permuting sort elements. Unlike [`check`], this ignores the safety properties.
+pub fn reachable(
+    module: &Module,
+    universe: &UniverseBounds,
+    depth: Option<usize>,
+    print_timing: bool,
+) -> Result<Vec<Model>, CheckerError> {
+    with_state_width!(
+        state_len(module, universe)?,
+        reachable_width(module, universe, depth, print_timing)
+    )
+}
+
+fn reachable_width<const W: usize>(
+    module: &Module,
+    universe: &UniverseBounds,
+    depth: Option<usize>,
+    print_timing: bool,
+) -> Result<Vec<Model>, CheckerError> {
+    let (program, indices) = translate::<W>(module, universe, print_timing)?;
+    let mut seen = IsoStateSet::new(&indices, SeenStates::Full);
+    let mut transitions = Transitions::new();
+    for tr in &program.trs {
+        transitions.insert(tr);
+    }
+
+    let mut states: Vec<BoundedState<W>> = vec![];
+    let mut layer: Vec<BoundedState<W>> = program
+        .inits
+        .iter()
+        .filter(|init| seen.insert(init))
+        .copied()
+        .collect();
+    let mut current_depth = 0;
+    while !layer.is_empty() {
+        states.extend(&layer);
+        if depth.map(|d| current_depth >= d).unwrap_or(false) {
+            break;
+        }
+        let successors: Vec<Vec<BoundedState<W>>> = layer
+            .par_iter()
+            .map(|state| {
+                transitions
+                    .get_subsets(state)
+                    .into_iter()
+                    .filter_map(|tr| tr.apply(state))
+                    .filter(|next| !seen.contains(next))
+                    .collect()
+            })
+            .collect();
+        layer = successors
+            .into_iter()
+            .flatten()
+            .filter(|next| seen.insert(next))
+            .collect();
+        current_depth += 1;
+    }
+
+    Ok(states
+        .iter()
+        .map(|state| indices.model(0, |i| state.get(i) as Element))
+        .collect())
+}
+
+/// How the set checker remembers the states it has already seen.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SeenStates {
+    /// Keep every state, which is exact
+    #[default]
+    Full,
+    /// Keep a 64-bit hash of every state. States whose hash collides with a
+    /// seen state are skipped, so the search may miss counterexamples.
+    Fingerprint64,
+    /// Keep a 128-bit hash of every state, for which collisions are very unlikely
+    Fingerprint128,
+}
+
+/// Options for the breadth-first search in [`check_with`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SearchConfig {
+    /// How to remember the states that have been seen
+    pub seen: SeenStates,
+    /// Number of threads to expand the frontier with (by default, rayon's global pool)
+    pub threads: Option<usize>,
+    /// Directory to write the frontier to when it gets too large (by default,
+    /// the frontier is kept in memory)
+    pub spill_dir: Option<PathBuf>,
+    /// Number of traces of the frontier to keep in memory before spilling the
+    /// rest to disk, which is also the number that are expanded together
+    pub spill_after: usize,
+}
+
+impl Default for SearchConfig {
+    fn default() -> Self {
+        SearchConfig {
+            seen: SeenStates::Full,
+            threads: None,
+            spill_dir: None,
+            spill_after: 1_000_000,
+        }
+    }
+}
+
+/// Limits for [`simulate`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WalkConfig {
+    /// Number of random walks to perform
+    pub walks: usize,
+    /// Maximum number of transitions in each walk
+    pub depth: usize,
+    /// Seed for the random choices; the same seed gives the same answer
+    pub seed: u64,
+    /// Maximum number of states each walk remembers having visited
+    pub seen_limit: usize,
+}
+
+/// Combined entry point to translate the module and search it with random walks.
+///
+/// Unlike [`check`], this does not keep the set of all reachable states, so it
+/// can find bugs at bounds where the breadth-first search runs out of memory.
+/// It never reports convergence. The counterexample is the shortest one found
+/// by any of the walks.
+pub fn simulate(
+    module: &Module,
+    universe: &UniverseBounds,
+    config: &WalkConfig,
+    print_timing: bool,
+) -> Result<CheckerAnswer<()>, CheckerError> {
+    with_state_width!(
+        state_len(module, universe)?,
+        simulate_width(module, universe, config, print_timing)
+    )
+}
+
+fn simulate_width<const W: usize>(
+    module: &Module,
+    universe: &UniverseBounds,
+    config: &WalkConfig,
+    print_timing: bool,
+) -> Result<CheckerAnswer<()>, CheckerError> {
+    let (program, indices) = translate::<W>(module, universe, print_timing)?;
+    match walk_interpret(&program, config, print_timing) {
+        InterpreterResult::Counterexample(Trace::Trace(states)) => {
+            Ok(CheckerAnswer::Counterexample(
+                states
+                    .iter()
+                    .map(|state| indices.model(0, |i| state.get(i) as Element))
+                    .collect(),
+            ))
+        }
+        InterpreterResult::Unknown => Ok(CheckerAnswer::Unknown),
+        _ => unreachable!("random walks keep full traces and never converge"),
+    }
+}
 
 /// A state in the bounded system. Conceptually, this is an interpretation of the signature on the
-/// bounded universe. We represent states concretely as a bitvector, where each bit represents the
-/// presence of a tuple in a relation. The order of the bits is determined by [Indices].
+/// bounded universe. We represent states concretely as a bitvector of `W` words, where each bit
+/// represents the presence of a tuple in a relation. The order of the bits is determined by
+/// [Indices].
+///
+/// The width is a compile-time constant so that states can be copied and hashed without
+/// allocating. `with_state_width` picks the smallest supported width for a module.
 #[derive(Clone, Copy, Eq, PartialOrd)]
-struct BoundedState(BitArr!(for STATE_LEN));
+struct BoundedState<const W: usize>(BitArray<[usize; W]>);
 
 // Go word by word instead of bit by bit.
-impl std::hash::Hash for BoundedState {
+impl<const W: usize> std::hash::Hash for BoundedState<W> {
     fn hash<H>(&self, h: &mut H)
     where
         H: std::hash::Hasher,
@@ -68,30 +334,30 @@ impl std::hash::Hash for BoundedState {
         self.0.as_raw_slice().hash(h)
     }
 }
-impl PartialEq for BoundedState {
-    fn eq(&self, other: &BoundedState) -> bool {
+impl<const W: usize> PartialEq for BoundedState<W> {
+    fn eq(&self, other: &BoundedState<W>) -> bool {
         self.0.as_raw_slice().eq(other.0.as_raw_slice())
     }
 }
 
-impl BoundedState {
-    const ZERO: BoundedState = BoundedState(BitArray::ZERO);
+impl<const W: usize> BoundedState<W> {
+    const ZERO: BoundedState<W> = BoundedState(BitArray::ZERO);
+    /// The number of bits in a state.
+    const LEN: usize = W * usize::BITS as usize;
 
     fn get(&self, index: usize) -> bool {
-        assert!(index < STATE_LEN, "STATE_LEN is too small");
         self.0[index]
     }
 
     fn set(&mut self, index: usize, value: bool) {
-        assert!(index < STATE_LEN, "STATE_LEN is too small");
         self.0.set(index, value);
     }
 }
 
-impl Debug for BoundedState {
+impl<const W: usize> Debug for BoundedState<W> {
     fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
         write!(f, "[")?;
-        let mut max = STATE_LEN - 1;
+        let mut max = Self::LEN - 1;
         while !self.get(max) {
             if max == 0 {
                 return write!(f, "]");
@@ -107,9 +373,9 @@ impl Debug for BoundedState {
 
 /// A BoundedProgram is a set of initial states, a set of transitions, and a safety property
 #[derive(Clone, Debug, PartialEq)]
-struct BoundedProgram {
+struct BoundedProgram<const W: usize> {
     /// List of initial states
-    inits: Vec<BoundedState>,
+    inits: Vec<BoundedState<W>>,
     /// List of transitions to potentially take at each step. The transition relation is the
     /// disjunction of all these transitions.
     trs: Vec<Transition>,
@@ -127,11 +393,15 @@ struct BoundedProgram {
 ///
 /// If the guard is true, then the transition is enabled and can step to the updated state.
 /// If the guard is false, then the transition is not enabled.
+///
+/// The `post_guard` is evaluated on the updated state, and the step is only taken if it holds.
+/// It is used for mutable axioms that can't be discharged statically.
 #[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
 struct Transition {
     guards: Vec<Guard>,
     updates: Vec<Update>,
     slow_guard: Formula,
+    post_guard: Formula,
 }
 
 /// A Guard is a logical literal, i.e., a possibly negated relation applied to an argument tuple
@@ -161,40 +431,60 @@ impl Transition {
     // This function constructs a Transition that comes from taking all of the
     // input transitions at the same time. If any of the input transitions would
     // not be run for a given state, the new transition will not be run for that state.
+    //
+    // Two guards on the same index with different values make the transition never run. Two
+    // updates to the same index are kept as one update, with the guard that both updates give
+    // the same value.
     fn from_conjunction(trs: impl IntoIterator<Item = Transition>) -> Transition {
         let mut guards: Vec<_> = vec![];
-        let mut updates: Vec<_> = vec![];
+        let mut updates: Vec<Update> = vec![];
         let mut slow_guard = Formula::always_true();
+        let mut post_guard = Formula::always_true();
         for tr in trs {
             guards.extend(tr.guards);
             updates.extend(tr.updates);
             slow_guard = Formula::and([slow_guard, tr.slow_guard]);
+            post_guard = Formula::and([post_guard, tr.post_guard]);
         }
 
         guards.sort();
         guards.dedup();
-        for g in &guards {
-            for h in &guards {
-                if g != h && g.index == h.index {
-                    panic!("found two parallel guards that conflict\n{g:?}\n{h:?}")
-                }
-            }
+        if guards
+            .iter()
+            .tuple_windows()
+            .any(|(g, h)| g.index == h.index)
+        {
+            slow_guard = Formula::always_false();
         }
         updates.sort();
         updates.dedup();
-        for u in &updates {
-            for v in &updates {
-                if u != v && u.index == v.index {
-                    panic!("found two parallel updates that conflict\n{u:?}\n{v:?}")
+        let mut merged: Vec<Update> = vec![];
+        for update in updates {
+            match merged.last() {
+                Some(last) if last.index == update.index => {
+                    let same = last.formula.clone().iff(update.formula);
+                    slow_guard = Formula::and([slow_guard, same]);
                 }
+                _ => merged.push(update),
             }
         }
 
         Transition {
             guards,
-            updates,
+            updates: merged,
             slow_guard,
+            post_guard,
+        }
+    }
+
+    /// The state after taking this transition from a state where it is enabled, or `None` if the
+    /// post-guard does not hold there.
+    fn apply<const W: usize>(&self, state: &BoundedState<W>) -> Option<BoundedState<W>> {
+        let mut next = *state;
+        for update in &self.updates {
+            next.set(update.index, update.formula.evaluate(state));
         }
+        self.post_guard.evaluate(&next).then_some(next)
     }
 }
 
@@ -205,25 +495,19 @@ impl Transition {
 /// name and the argument values.
 /// The module is assumed to have already been typechecked.
 /// The translator ignores proof blocks.
-fn translate<'a>(
+fn translate<'a, const W: usize>(
     module: &'a Module,
     universe: &'a UniverseBounds,
     print_timing: bool,
-) -> Result<(BoundedProgram, Indices<'a>), CheckerError> {
-    for relation in &module.signature.relations {
-        if relation.sort != Sort::Bool {
-            panic!("non-bool relations in checker (use Module::convert_non_bool_relations)")
-        }
-    }
-
-    let indices = Indices::new(&module.signature, universe, 1);
-
+) -> Result<(BoundedProgram<W>, Indices<'a>), CheckerError> {
     for sort in &module.signature.sorts {
         if !universe.contains_key(sort) {
             return Err(CheckerError::UnknownSort(sort.clone(), universe.clone()));
         }
     }
 
+    let indices = Indices::new(&module.signature, universe, 1);
+
     if !module.defs.is_empty() {
         panic!("definitions in checker (use Module::inline_defs)")
     }
@@ -241,12 +525,14 @@ fn translate<'a>(
 
     // compute initial states
     let inits = Term::and(d.inits.iter().chain(&d.axioms));
-    let inits = indices.bdd_from_enumerated(
+    let inits = indices.bdd_from_enumerated(Enumerated::And(vec![
         enumerate_quantifiers(&inits, &module.signature, universe)
             .map_err(CheckerError::EnumerationError)?,
-    );
+        value_constraints(&module.signature, universe, false),
+        value_constraints(&module.signature, universe, true),
+    ]));
     println!("enumerating {} initial states", inits.exact_cardinality());
-    let inits: Vec<BoundedState> = inits
+    let inits: Vec<BoundedState<W>> = inits
         .sat_valuations()
         .map(|valuation| {
             let mut init = BoundedState::ZERO;
@@ -262,16 +548,22 @@ fn translate<'a>(
         })
         .collect();
 
-    // compute imperative transitions
+    // compute imperative transitions. the transition relation is the conjunction of all of the
+    // transition assumptions, so each transition takes one disjunct from each of them
     let trs = match d.transitions.as_slice() {
         [] => vec![],
-        [tr] => enumerate_quantifiers(tr, &module.signature, universe)
-            .map_err(CheckerError::EnumerationError)?
-            .get_or()
+        trs => trs
+            .iter()
+            .map(|tr| {
+                enumerate_quantifiers(tr, &module.signature, universe)
+                    .map(Enumerated::get_or)
+                    .map_err(CheckerError::EnumerationError)
+            })
+            .collect::<Result<Vec<_>, _>>()?
             .into_iter()
-            .map(|term| enumerated_to_transition(term, &indices))
+            .multi_cartesian_product_fixed()
+            .map(|terms| enumerated_to_transition(Enumerated::And(terms), &indices))
             .collect::<Result<Vec<_>, _>>()?,
-        _ => return Err(CheckerError::MultipleTrs),
     };
     let trs: Vec<(Transition, _)> = trs
         .into_iter()
@@ -344,8 +636,20 @@ fn translate<'a>(
         })
         .collect();
 
-    // filter transitions using the mutable axioms
-    let mutable_axioms = formula(Term::and(d.mutable_axioms(&module.signature.relations)))?;
+    // filter transitions using the mutable axioms, which include that functions stay in range
+    let mutable_axioms = enumerate_quantifiers(
+        &Term::and(d.mutable_axioms(&module.signature.relations)),
+        &module.signature,
+        universe,
+    )
+    .map_err(CheckerError::EnumerationError)?;
+    let mutable_axioms = enumerated_to_formula(
+        Enumerated::And(vec![
+            mutable_axioms,
+            value_constraints(&module.signature, universe, true),
+        ]),
+        &indices,
+    )?;
     let guard_indices = mutable_axioms.guard_indices();
     let mut should_keep = vec![true; trs.len()];
     for (i, should_keep) in should_keep.iter_mut().enumerate() {
@@ -389,7 +693,8 @@ fn translate<'a>(
                 .chain(true_or_false_updates),
         ) {
             Some(b) => *should_keep = b,
-            None => return Err(CheckerError::UnprovenMutableAxiom),
+            // check the axioms in the post-state instead
+            None => trs[i].post_guard = mutable_axioms.clone(),
         }
     }
     let mut i = 0;
@@ -553,7 +858,7 @@ impl Formula {
         ])
     }
 
-    fn evaluate(&self, state: &BoundedState) -> bool {
+    fn evaluate<const W: usize>(&self, state: &BoundedState<W>) -> bool {
         match self {
             Formula::And(terms) => terms.iter().all(|term| term.evaluate(state)),
             Formula::Or(terms) => terms.iter().any(|term| term.evaluate(state)),
@@ -630,6 +935,7 @@ fn enumerated_to_transition(
                         guards: vec![],
                         updates: vec![],
                         slow_guard: formula(term)?,
+                        post_guard: Formula::always_true(),
                     }
                 }
             }
@@ -643,6 +949,7 @@ fn enumerated_to_transition(
                     guards: vec![],
                     updates: vec![Update { index, formula }],
                     slow_guard: Formula::always_true(),
+                    post_guard: Formula::always_true(),
                 }
             } else {
                 unreachable!()
@@ -655,6 +962,7 @@ fn enumerated_to_transition(
                 formula: Formula::always_true(),
             }],
             slow_guard: Formula::always_true(),
+            post_guard: Formula::always_true(),
         },
         term => {
             let terms = formula(term)?.get_and();
@@ -669,12 +977,14 @@ fn enumerated_to_transition(
                         .collect(),
                     updates: vec![],
                     slow_guard: Formula::always_true(),
+                    post_guard: Formula::always_true(),
                 }
             } else {
                 Transition {
                     guards: vec![],
                     updates: vec![],
                     slow_guard: Formula::and(terms),
+                    post_guard: Formula::always_true(),
                 }
             }
         }
@@ -722,19 +1032,19 @@ impl From<bool> for TraceCompression {
 /// last state together with its depth. (The depth of a trace is the number of transitions it
 /// contains, or one less than the number of states it contains.)
 #[derive(Clone, Debug, PartialEq)]
-enum Trace {
+enum Trace<const W: usize> {
     /// Uncompressed trace, which keeps all states
-    Trace(Vec<BoundedState>),
+    Trace(Vec<BoundedState<W>>),
     /// Compressed trace, keeping only the last state and its depth
-    CompressedTrace(BoundedState, usize),
+    CompressedTrace(BoundedState<W>, usize),
 }
 
-impl Trace {
+impl<const W: usize> Trace<W> {
     /// Construct a singleton trace. Note that the decision of whether to compress or not is made at
     /// construction time. If the trace is constructed as compressed (`TraceCompression::Yes`), then
     /// future calls to `push` on this trace will only increment the depth and replace the (one)
     /// state.
-    fn new(state: BoundedState, compression: TraceCompression) -> Trace {
+    fn new(state: BoundedState<W>, compression: TraceCompression) -> Trace<W> {
         match compression {
             TraceCompression::Yes => Trace::CompressedTrace(state, 0),
             TraceCompression::No => Trace::Trace(vec![state]),
@@ -750,7 +1060,7 @@ impl Trace {
     }
 
     /// The last state of a trace. Since all traces are constructed to be nonempty, this never fails.
-    fn last(&self) -> &BoundedState {
+    fn last(&self) -> &BoundedState<W> {
         match self {
             Trace::CompressedTrace(s, _) => s,
 
@@ -762,7 +1072,7 @@ impl Trace {
     /// Extend the trace with one new state on the end. Note that if `self` is a compressed trace,
     /// then only the last state is tracked, so `push` will lose the information about the previous
     /// state.
-    fn push(&mut self, state: BoundedState) {
+    fn push(&mut self, state: BoundedState<W>) {
         match self {
             Trace::CompressedTrace(s, n) => {
                 *s = state;
@@ -773,13 +1083,136 @@ impl Trace {
             }
         }
     }
+
+    /// Write the trace in the format read by [`Trace::read`].
+    fn write(&self, w: &mut impl Write) -> std::io::Result<()> {
+        let (n, states) = match self {
+            Trace::CompressedTrace(s, n) => (*n, std::slice::from_ref(s)),
+            Trace::Trace(v) => (v.len(), &v[..]),
+        };
+        w.write_all(&(n as u64).to_le_bytes())?;
+        for state in states {
+            for word in state.0.as_raw_slice() {
+                w.write_all(&(*word as u64).to_le_bytes())?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Read a trace written by [`Trace::write`], or `None` at the end of the input.
+    fn read(r: &mut impl Read, compression: TraceCompression) -> std::io::Result<Option<Trace<W>>> {
+        let mut word = [0; 8];
+        let mut read_word = |r: &mut dyn Read| -> std::io::Result<u64> {
+            r.read_exact(&mut word)?;
+            Ok(u64::from_le_bytes(word))
+        };
+        let n = match read_word(r) {
+            Ok(n) => n as usize,
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let mut read_state = |r: &mut dyn Read| -> std::io::Result<BoundedState<W>> {
+            let mut state = BoundedState::ZERO;
+            for word in state.0.as_raw_mut_slice() {
+                *word = read_word(r)? as usize;
+            }
+            Ok(state)
+        };
+        Ok(Some(match compression {
+            TraceCompression::Yes => Trace::CompressedTrace(read_state(r)?, n),
+            TraceCompression::No => Trace::Trace((0..n).map(|_| read_state(r)).try_collect()?),
+        }))
+    }
+}
+
+/// Counter to give each spill file a different name.
+static SPILL_FILES: AtomicUsize = AtomicUsize::new(0);
+
+/// The traces at one depth of the breadth-first search, in order. The traces are kept in memory
+/// in chunks of at most `spill_after` traces, and all but the last chunk are written to files in
+/// the spill directory if there is one.
+struct Layer<'a, const W: usize> {
+    config: &'a SearchConfig,
+    compression: TraceCompression,
+    files: VecDeque<PathBuf>,
+    memory: Vec<Trace<W>>,
+    len: usize,
+}
+
+impl<'a, const W: usize> Layer<'a, W> {
+    fn new(config: &'a SearchConfig, compression: TraceCompression) -> Layer<'a, W> {
+        Layer {
+            config,
+            compression,
+            files: VecDeque::new(),
+            memory: vec![],
+            len: 0,
+        }
+    }
+
+    fn push(&mut self, trace: Trace<W>) -> Result<(), CheckerError> {
+        self.memory.push(trace);
+        self.len += 1;
+        match &self.config.spill_dir {
+            Some(dir) if self.memory.len() >= self.config.spill_after => {
+                let spill_error = |e: std::io::Error| CheckerError::SpillError(e.to_string());
+                let path = dir.join(format!(
+                    "flyvy-frontier-{}-{}",
+                    std::process::id(),
+                    SPILL_FILES.fetch_add(1, Ordering::Relaxed)
+                ));
+                let mut w = BufWriter::new(File::create(&path).map_err(spill_error)?);
+                self.files.push_back(path);
+                for trace in self.memory.drain(..) {
+                    trace.write(&mut w).map_err(spill_error)?;
+                }
+                w.flush().map_err(spill_error)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// The number of traces on disk.
+    fn spilled(&self) -> usize {
+        self.len - self.memory.len()
+    }
+
+    /// Remove the next chunk of traces from the layer.
+    fn pop_chunk(&mut self) -> Result<Option<Vec<Trace<W>>>, CheckerError> {
+        let chunk = match self.files.pop_front() {
+            Some(path) => {
+                let spill_error = |e: std::io::Error| CheckerError::SpillError(e.to_string());
+                let mut r = BufReader::new(File::open(&path).map_err(spill_error)?);
+                let mut chunk = vec![];
+                while let Some(trace) =
+                    Trace::read(&mut r, self.compression).map_err(spill_error)?
+                {
+                    chunk.push(trace);
+                }
+                std::fs::remove_file(&path).map_err(spill_error)?;
+                chunk
+            }
+            None if !self.memory.is_empty() => std::mem::take(&mut self.memory),
+            None => return Ok(None),
+        };
+        self.len -= chunk.len();
+        Ok(Some(chunk))
+    }
+}
+
+impl<const W: usize> Drop for Layer<'_, W> {
+    fn drop(&mut self) {
+        for path in &self.files {
+            let _ = std::fs::remove_file(path);
+        }
+    }
 }
 
 /// The bounded model checker will either find a counterexample or say "no bugs found"
 #[derive(Debug, PartialEq)]
-enum InterpreterResult {
+enum InterpreterResult<const W: usize> {
     /// The checker found a counterexample, here it is
-    Counterexample(Trace),
+    Counterexample(Trace<W>),
     /// The checker could not find any counterexamples
     Unknown,
     /// The checker found that the set of states stopped changing
@@ -793,22 +1226,27 @@ enum InterpreterResult {
 /// so if max_depth is Some(3), it means there will be 3 transitions (so 4 states).
 /// If max_depth is None, it means "no upper bound". The program will run until its
 /// state space is exhausted or the process is killed.
-fn interpret(
-    program: &BoundedProgram,
+///
+/// The search goes one depth at a time. The successors of the states at each depth are computed
+/// in parallel, and then added to the seen set in order, so the result is the same as a
+/// sequential breadth-first search no matter how many threads are used.
+fn interpret<const W: usize>(
+    program: &BoundedProgram<W>,
     max_depth: Option<usize>,
     compress_traces: TraceCompression,
     print_timing: bool,
     indices: &Indices,
-) -> InterpreterResult {
+    config: &SearchConfig,
+) -> Result<InterpreterResult<W>, CheckerError> {
     // States we have seen so far.
-    let mut seen = IsoStateSet::new(indices);
-    // The BFS queue, i.e., states on the frontier that need to be explored.
-    // The queue is always a subset of seen.
-    let mut queue: VecDeque<Trace> = VecDeque::new();
+    let mut seen = IsoStateSet::new(indices, config.seen);
+    // The states on the frontier at the current depth, which need to be explored.
+    // The frontier is always a subset of seen.
+    let mut layer = Layer::new(config, compress_traces);
 
     for init in &program.inits {
         if seen.insert(init) {
-            queue.push_back(Trace::new(*init, compress_traces));
+            layer.push(Trace::new(*init, compress_traces))?;
         }
     }
 
@@ -821,50 +1259,171 @@ fn interpret(
     let start_time = std::time::Instant::now();
     println!(
         "starting search from depth 0. there are {} initial states in the queue.",
-        queue.len()
+        layer.len
     );
 
-    while let Some(trace) = queue.pop_front() {
-        let depth = trace.depth();
-        if depth > current_depth {
-            current_depth += 1;
-            if print_timing {
-                print!("({:0.1}s since start) ", start_time.elapsed().as_secs_f64());
+    loop {
+        let mut next_layer = Layer::new(config, compress_traces);
+        while let Some(chunk) = layer.pop_chunk()? {
+            // The seen set is only read while expanding, which filters out the successors that
+            // were seen at earlier depths. Successors of states at this depth can still be
+            // duplicates of each other, which is resolved when inserting them in order below.
+            let successors: Vec<Option<Vec<BoundedState<W>>>> = chunk
+                .par_iter()
+                .map(|trace| {
+                    let state = trace.last();
+                    if !program.safe.evaluate(state) {
+                        return None;
+                    }
+                    if max_depth.map(|md| trace.depth() >= md).unwrap_or(false) {
+                        return Some(vec![]);
+                    }
+                    let trs = transitions.get_subsets(state);
+                    Some(
+                        trs.into_iter()
+                            .filter_map(|tr| tr.apply(state))
+                            .filter(|next| !seen.contains(next))
+                            .collect(),
+                    )
+                })
+                .collect();
+
+            for (trace, successors) in chunk.into_iter().zip(successors) {
+                let Some(successors) = successors else {
+                    return Ok(InterpreterResult::Counterexample(trace));
+                };
+                for next in successors {
+                    if seen.insert(&next) {
+                        let mut trace = trace.clone();
+                        trace.push(next);
+                        next_layer.push(trace)?;
+                    }
+                }
             }
-            println!(
-                "considering new depth: {current_depth}. \
-                 queue length is {}. seen {} unique states.",
-                queue.len() + 1, // include current state
-                seen.set.len()
-            );
         }
 
-        let state = trace.last();
-        if !program.safe.evaluate(state) {
-            return InterpreterResult::Counterexample(trace);
+        if next_layer.len == 0 {
+            break;
         }
-
-        if max_depth.map(|md| depth < md).unwrap_or(true) {
-            let trs = transitions.get_subsets(state);
-
-            for tr in trs {
-                let mut next = *state;
-                tr.updates
-                    .iter()
-                    .for_each(|update| next.set(update.index, update.formula.evaluate(state)));
-                if seen.insert(&next) {
-                    let mut trace = trace.clone();
-                    trace.push(next);
-                    queue.push_back(trace);
-                }
+        layer = next_layer;
+        current_depth += 1;
+        if print_timing {
+            print!("({:0.1}s since start) ", start_time.elapsed().as_secs_f64());
+        }
+        print!(
+            "considering new depth: {current_depth}. \
+             queue length is {}. seen {} unique states.",
+            layer.len,
+            seen.set.len()
+        );
+        if layer.spilled() > 0 {
+            print!(" {} states are spilled to disk.", layer.spilled());
+        }
+        if print_timing {
+            if let Some(usage) = memory_stats::memory_stats() {
+                print!(
+                    " using {} MiB of memory.",
+                    usage.physical_mem / (1024 * 1024)
+                );
             }
         }
+        println!();
     }
 
     if max_depth.map(|md| current_depth < md).unwrap_or(true) {
-        InterpreterResult::Convergence
+        Ok(InterpreterResult::Convergence)
     } else {
-        InterpreterResult::Unknown
+        Ok(InterpreterResult::Unknown)
+    }
+}
+
+/// Search a BoundedProgram with random walks in parallel, returning the shortest
+/// counterexample found (with ties broken by the lowest-numbered walk).
+///
+/// Each walk starts from a random initial state and repeatedly takes a random
+/// enabled transition, preferring transitions to states that the walk has not
+/// seen before. Walks stop early once they are deeper than the shortest
+/// counterexample found so far, which cannot change the answer.
+fn walk_interpret<const W: usize>(
+    program: &BoundedProgram<W>,
+    config: &WalkConfig,
+    print_timing: bool,
+) -> InterpreterResult<W> {
+    let mut transitions = Transitions::new();
+    for tr in &program.trs {
+        transitions.insert(tr);
+    }
+
+    let start_time = std::time::Instant::now();
+    println!(
+        "starting {} random walks of depth {}",
+        config.walks, config.depth
+    );
+    let shortest = AtomicUsize::new(usize::MAX);
+    let result = (0..config.walks)
+        .into_par_iter()
+        .filter_map(|i| {
+            let rng = StdRng::seed_from_u64(config.seed.wrapping_add(i as u64));
+            walk(program, &transitions, config, rng, &shortest).map(|trace| (trace, i))
+        })
+        .min_by_key(|(trace, i)| (trace.len(), *i));
+    if print_timing {
+        println!(
+            "random walks finished in {:0.1}s",
+            start_time.elapsed().as_secs_f64()
+        );
+    }
+
+    match result {
+        Some((trace, _)) => InterpreterResult::Counterexample(Trace::Trace(trace)),
+        None => InterpreterResult::Unknown,
+    }
+}
+
+/// Perform a single random walk, returning the trace if it reaches an unsafe state.
+fn walk<const W: usize>(
+    program: &BoundedProgram<W>,
+    transitions: &Transitions,
+    config: &WalkConfig,
+    mut rng: StdRng,
+    shortest: &AtomicUsize,
+) -> Option<Vec<BoundedState<W>>> {
+    if program.inits.is_empty() {
+        return None;
+    }
+    let mut seen: HashSet<BoundedState<W>> = HashSet::default();
+    let mut trace = vec![program.inits[rng.gen_range(0..program.inits.len())]];
+    loop {
+        let state = trace.last().unwrap();
+        if !program.safe.evaluate(state) {
+            shortest.fetch_min(trace.len(), Ordering::Relaxed);
+            return Some(trace);
+        }
+        // a longer counterexample would not be reported
+        if trace.len() > config.depth || trace.len() >= shortest.load(Ordering::Relaxed) {
+            return None;
+        }
+
+        if seen.len() >= config.seen_limit {
+            seen.clear();
+        }
+        seen.insert(*state);
+        let successors: Vec<BoundedState<W>> = transitions
+            .get_subsets(state)
+            .into_iter()
+            .filter_map(|tr| tr.apply(state))
+            .collect();
+        let unseen: Vec<&BoundedState<W>> =
+            successors.iter().filter(|s| !seen.contains(s)).collect();
+        let next = if !unseen.is_empty() {
+            *unseen[rng.gen_range(0..unseen.len())]
+        } else if !successors.is_empty() {
+            successors[rng.gen_range(0..successors.len())]
+        } else {
+            // deadlock
+            return None;
+        };
+        trace.push(next);
     }
 }
 
@@ -912,7 +1471,7 @@ impl<'a> Transitions<'a> {
     }
 
     /// Get all the transitions whose guards are a subset of the given set.
-    fn get_subsets(&self, set: &BoundedState) -> Vec<&'a Transition> {
+    fn get_subsets<const W: usize>(&self, set: &BoundedState<W>) -> Vec<&'a Transition> {
         let mut out = vec![];
         self.get_subsets_into_vec(set, &mut out);
         out
@@ -920,7 +1479,11 @@ impl<'a> Transitions<'a> {
 
     // Destination passing style helper to recursively collect all the transitions whose guards are
     // a subset of the given set.
-    fn get_subsets_into_vec(&self, set: &BoundedState, out: &mut Vec<&'a Transition>) {
+    fn get_subsets_into_vec<const W: usize>(
+        &self,
+        set: &BoundedState<W>,
+        out: &mut Vec<&'a Transition>,
+    ) {
         out.extend(self.data.iter().filter(|tr| tr.slow_guard.evaluate(set)));
         for (key, child) in &self.children {
             if set.get(key.index) == key.value {
@@ -930,14 +1493,76 @@ impl<'a> Transitions<'a> {
     }
 }
 
+/// A set of states, which may only keep fingerprints of the states (see [`SeenStates`]).
+enum StateSet<const W: usize> {
+    Full(HashSet<BoundedState<W>>),
+    Fingerprint64(HashSet<u64>),
+    Fingerprint128(HashSet<u128>),
+}
+
+/// A hash of the state that does not depend on the process, with `salt` selecting the hash
+/// function.
+fn fingerprint<const W: usize>(state: &BoundedState<W>, salt: u64) -> u64 {
+    let mut h = std::collections::hash_map::DefaultHasher::new();
+    salt.hash(&mut h);
+    state.hash(&mut h);
+    h.finish()
+}
+
+fn fingerprint128<const W: usize>(state: &BoundedState<W>) -> u128 {
+    ((fingerprint(state, 0) as u128) << 64) | fingerprint(state, 1) as u128
+}
+
+impl<const W: usize> StateSet<W> {
+    fn new(seen: SeenStates) -> StateSet<W> {
+        match seen {
+            SeenStates::Full => StateSet::Full(HashSet::default()),
+            SeenStates::Fingerprint64 => StateSet::Fingerprint64(HashSet::default()),
+            SeenStates::Fingerprint128 => StateSet::Fingerprint128(HashSet::default()),
+        }
+    }
+
+    fn len(&self) -> usize {
+        match self {
+            StateSet::Full(set) => set.len(),
+            StateSet::Fingerprint64(set) => set.len(),
+            StateSet::Fingerprint128(set) => set.len(),
+        }
+    }
+
+    fn contains(&self, x: &BoundedState<W>) -> bool {
+        match self {
+            StateSet::Full(set) => set.contains(x),
+            StateSet::Fingerprint64(set) => set.contains(&fingerprint(x, 0)),
+            StateSet::Fingerprint128(set) => set.contains(&fingerprint128(x)),
+        }
+    }
+
+    fn insert(&mut self, x: BoundedState<W>) {
+        match self {
+            StateSet::Full(set) => set.insert(x),
+            StateSet::Fingerprint64(set) => set.insert(fingerprint(&x, 0)),
+            StateSet::Fingerprint128(set) => set.insert(fingerprint128(&x)),
+        };
+    }
+}
+
 /// Can answer the question "have I seen a state that is isomorphic to this one before"?
-struct IsoStateSet {
-    set: HashSet<BoundedState>,
-    orderings: Vec<Vec<(usize, usize)>>,
+struct IsoStateSet<const W: usize> {
+    set: StateSet<W>,
+    orderings: Vec<Permutation>,
+}
+
+/// The instructions to apply one permutation of the universe to a state.
+struct Permutation {
+    /// Copy the bit of a relation from a src index to a dst index.
+    copies: Vec<(usize, usize)>,
+    /// Copy the value of a function from src bits to dst bits, mapping it to a new element.
+    values: Vec<(Vec<usize>, Vec<usize>, Vec<Element>)>,
 }
 
-impl IsoStateSet {
-    fn new(indices: &Indices) -> IsoStateSet {
+impl<const W: usize> IsoStateSet<W> {
+    fn new(indices: &Indices, seen: SeenStates) -> IsoStateSet<W> {
         let sorts: Vec<_> = indices.universe.keys().sorted().collect();
         let orderings = sorts
             .iter()
@@ -953,45 +1578,90 @@ impl IsoStateSet {
             })
             // convert permutations to copy instructions
             .map(|permutation: HashMap<&str, Vec<usize>>| {
-                indices
-                    .iter()
-                    .flat_map(|(name, rest)| {
-                        rest.iter().map(|(elements, (i, _))| {
-                            let relation = indices.signature.relation_decl(name);
-                            assert_eq!(relation.args.len(), elements.len());
-                            // map each old element to a new element
-                            let mut new_elements = elements.clone();
-                            for i in 0..new_elements.len() {
-                                let x = elements[i];
-                                new_elements[i] = match &relation.args[i] {
-                                    Sort::Uninterpreted(s) => permutation[s.as_str()][x],
-                                    Sort::Bool => x,
+                let mut copies = vec![];
+                let mut values = vec![];
+                for (name, rest) in indices.iter() {
+                    let relation = indices.signature.relation_decl(name);
+                    // map each old element to a new element
+                    let permute = |elements: &[Element]| {
+                        elements
+                            .iter()
+                            .zip(&relation.args)
+                            .map(|(x, sort)| match sort {
+                                Sort::Uninterpreted(s) => permutation[s.as_str()][*x],
+                                Sort::Bool => *x,
+                            })
+                            .collect::<Vec<_>>()
+                    };
+                    match &relation.sort {
+                        Sort::Bool => {
+                            for (elements, (i, _)) in rest {
+                                assert_eq!(relation.args.len(), elements.len());
+                                // look up the index to precompute the dst
+                                let dst = indices.get(&relation.name, 0, &permute(elements));
+                                if *i != dst {
+                                    copies.push((*i, dst));
+                                }
+                            }
+                        }
+                        Sort::Uninterpreted(sort) => {
+                            let map = &permutation[sort.as_str()];
+                            let width = value_bits(map.len());
+                            // visit each argument tuple once, through its lowest bit
+                            for elements in
+                                rest.keys().filter(|elements| elements.last() == Some(&0))
+                            {
+                                let args = &elements[..relation.args.len()];
+                                let bits = |args: Vec<Element>| {
+                                    (0..width)
+                                        .map(|bit| {
+                                            let mut elements = args.clone();
+                                            elements.push(bit);
+                                            indices.get(&relation.name, 0, &elements)
+                                        })
+                                        .collect::<Vec<_>>()
                                 };
+                                let (src, dst) = (bits(args.to_vec()), bits(permute(args)));
+                                if src != dst || map.iter().enumerate().any(|(x, y)| x != *y) {
+                                    values.push((src, dst, map.clone()));
+                                }
                             }
-                            // look up the index to precompute the dst
-                            (*i, indices.get(&relation.name, 0, &new_elements))
-                        })
-                    })
-                    .filter(|(src, dst)| src != dst)
-                    .collect()
+                        }
+                    }
+                }
+                Permutation { copies, values }
             })
             .collect();
 
         IsoStateSet {
-            set: HashSet::default(),
+            set: StateSet::new(seen),
             orderings,
         }
     }
 
-    fn insert(&mut self, x: &BoundedState) -> bool {
+    fn contains(&self, x: &BoundedState<W>) -> bool {
+        self.set.contains(x)
+    }
+
+    fn insert(&mut self, x: &BoundedState<W>) -> bool {
         if self.set.contains(x) {
             false
         } else {
             for ordering in &self.orderings {
                 let mut y = *x;
-                for (src, dst) in ordering {
+                for (src, dst) in &ordering.copies {
                     y.set(*dst, x.get(*src));
                 }
+                for (src, dst, map) in &ordering.values {
+                    let value: usize = src
+                        .iter()
+                        .enumerate()
+                        .map(|(bit, i)| usize::from(x.get(*i)) << bit)
+                        .sum();
+                    for (bit, i) in dst.iter().enumerate() {
+                        y.set(*i, map[value] >> bit & 1 == 1);
+                    }
+                }
                 self.set.insert(y);
             }
             true
@@ -1033,9 +1703,10 @@ mod tests {
             guards: guards.into_iter().sorted().collect(),
             updates: updates.into_iter().sorted().collect(),
             slow_guard: Formula::always_true(),
+            post_guard: Formula::always_true(),
         }
     }
-    fn state(iter: impl IntoIterator<Item = u8>) -> BoundedState {
+    fn state(iter: impl IntoIterator<Item = u8>) -> BoundedState<2> {
         let mut out = BoundedState::ZERO;
         for (i, x) in iter.into_iter().enumerate() {
             out.set(i, x == 1);
@@ -1106,8 +1777,24 @@ mod tests {
                 value: false,
             }),
         };
-        let result0 = interpret(&program, Some(0), TraceCompression::No, false, &indices);
-        let result1 = interpret(&program, Some(1), TraceCompression::No, false, &indices);
+        let result0 = interpret(
+            &program,
+            Some(0),
+            TraceCompression::No,
+            false,
+            &indices,
+            &SearchConfig::default(),
+        )
+        .unwrap();
+        let result1 = interpret(
+            &program,
+            Some(1),
+            TraceCompression::No,
+            false,
+            &indices,
+            &SearchConfig::default(),
+        )
+        .unwrap();
         assert_eq!(result0, InterpreterResult::Unknown);
         let mut expected1 = Trace::new(state([0]), TraceCompression::No);
         expected1.push(state([1]));
@@ -1221,11 +1908,51 @@ mod tests {
                 value: false,
             }),
         };
-        let result1 = interpret(&program, Some(0), TraceCompression::No, false, &indices);
-        let result2 = interpret(&program, Some(1), TraceCompression::No, false, &indices);
-        let result3 = interpret(&program, Some(2), TraceCompression::No, false, &indices);
-        let result4 = interpret(&program, Some(3), TraceCompression::No, false, &indices);
-        let result5 = interpret(&program, Some(4), TraceCompression::No, false, &indices);
+        let result1 = interpret(
+            &program,
+            Some(0),
+            TraceCompression::No,
+            false,
+            &indices,
+            &SearchConfig::default(),
+        )
+        .unwrap();
+        let result2 = interpret(
+            &program,
+            Some(1),
+            TraceCompression::No,
+            false,
+            &indices,
+            &SearchConfig::default(),
+        )
+        .unwrap();
+        let result3 = interpret(
+            &program,
+            Some(2),
+            TraceCompression::No,
+            false,
+            &indices,
+            &SearchConfig::default(),
+        )
+        .unwrap();
+        let result4 = interpret(
+            &program,
+            Some(3),
+            TraceCompression::No,
+            false,
+            &indices,
+            &SearchConfig::default(),
+        )
+        .unwrap();
+        let result5 = interpret(
+            &program,
+            Some(4),
+            TraceCompression::No,
+            false,
+            &indices,
+            &SearchConfig::default(),
+        )
+        .unwrap();
         assert_eq!(result1, InterpreterResult::Unknown);
         assert_eq!(result2, InterpreterResult::Unknown);
         assert_eq!(result3, InterpreterResult::Unknown);
@@ -1328,7 +2055,7 @@ mod tests {
             ]),
         };
 
-        let (target, _) = translate(&m, &universe, false)?;
+        let (target, _) = translate::<2>(&m, &universe, false)?;
         assert_eq!(target.inits, expected.inits);
         assert_eq!(target.safe, expected.safe);
         assert_eq!(
@@ -1336,7 +2063,14 @@ mod tests {
             target.trs.iter().sorted().collect::<Vec<_>>(),
         );
 
-        let output = interpret(&target, None, TraceCompression::No, false, &indices);
+        let output = interpret(
+            &target,
+            None,
+            TraceCompression::No,
+            false,
+            &indices,
+            &SearchConfig::default(),
+        )?;
         assert_eq!(output, InterpreterResult::Convergence);
 
         Ok(())
@@ -1349,21 +2083,142 @@ mod tests {
         let mut m = fly::parser::parse(source).unwrap();
         sort_check_module(&mut m).unwrap();
         let universe = std::collections::HashMap::from([("node".to_string(), 2)]);
-        let (target, indices) = translate(&m, &universe, false)?;
-
-        let bug = interpret(&target, Some(12), TraceCompression::No, false, &indices);
+        let (target, indices) = translate::<2>(&m, &universe, false)?;
+
+        let bug = interpret(
+            &target,
+            Some(12),
+            TraceCompression::No,
+            false,
+            &indices,
+            &SearchConfig::default(),
+        )?;
         if let InterpreterResult::Counterexample(trace) = &bug {
             assert_eq!(trace.depth(), 12);
         } else {
             assert!(matches!(bug, InterpreterResult::Counterexample(_)));
         }
 
-        let too_short = interpret(&target, Some(11), TraceCompression::No, false, &indices);
+        let too_short = interpret(
+            &target,
+            Some(11),
+            TraceCompression::No,
+            false,
+            &indices,
+            &SearchConfig::default(),
+        )?;
         assert_eq!(too_short, InterpreterResult::Unknown);
 
         Ok(())
     }
 
+    #[test]
+    fn checker_set_reachable() -> Result<(), CheckerError> {
+        let source = include_str!("../../temporal-verifier/examples/lockserver.fly");
+
+        let mut m = fly::parser::parse(source).unwrap();
+        sort_check_module(&mut m).unwrap();
+        let universe = std::collections::HashMap::from([("node".to_string(), 2)]);
+
+        let states = reachable(&m, &universe, None, false)?;
+        let safety = &extract(&m).unwrap().proofs[0].safety.x;
+        assert!(states.iter().all(|state| state.eval(safety) == 1));
+        assert_eq!(states.len(), 15);
+        assert_eq!(reachable(&m, &universe, Some(0), false)?.len(), 1);
+        assert!(reachable(&m, &universe, Some(2), false)?.len() < states.len());
+
+        Ok(())
+    }
+
+    #[test]
+    fn checker_set_search_config() -> Result<(), CheckerError> {
+        let source = include_str!("../../temporal-verifier/tests/examples/lockserver_buggy.fly");
+
+        let mut m = fly::parser::parse(source).unwrap();
+        sort_check_module(&mut m).unwrap();
+        let universe = std::collections::HashMap::from([("node".to_string(), 2)]);
+        let (target, indices) = translate::<2>(&m, &universe, false)?;
+        let dir = std::env::temp_dir().join(format!("flyvy-spill-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        for compression in [TraceCompression::No, TraceCompression::Yes] {
+            let expected = interpret(
+                &target,
+                Some(12),
+                compression,
+                false,
+                &indices,
+                &SearchConfig::default(),
+            )?;
+            assert!(matches!(expected, InterpreterResult::Counterexample(_)));
+            for seen in [
+                SeenStates::Full,
+                SeenStates::Fingerprint64,
+                SeenStates::Fingerprint128,
+            ] {
+                let config = SearchConfig {
+                    seen,
+                    threads: None,
+                    spill_dir: Some(dir.clone()),
+                    spill_after: 3,
+                };
+                let result = rayon::ThreadPoolBuilder::new()
+                    .num_threads(4)
+                    .build()
+                    .unwrap()
+                    .install(|| {
+                        interpret(&target, Some(12), compression, false, &indices, &config)
+                    })?;
+                assert_eq!(result, expected);
+                // the remaining spill files are removed
+                assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+            }
+        }
+
+        std::fs::remove_dir(&dir).unwrap();
+        Ok(())
+    }
+
+    #[test]
+    fn checker_set_walk_lockserver_buggy() -> Result<(), CheckerError> {
+        let source = include_str!("../../temporal-verifier/tests/examples/lockserver_buggy.fly");
+
+        let mut m = fly::parser::parse(source).unwrap();
+        sort_check_module(&mut m).unwrap();
+        let universe = std::collections::HashMap::from([("node".to_string(), 2)]);
+        let (target, _) = translate::<2>(&m, &universe, false)?;
+
+        let config = WalkConfig {
+            walks: 200,
+            depth: 30,
+            seed: 0,
+            seen_limit: 1000,
+        };
+        let bug = walk_interpret(&target, &config, false);
+        let InterpreterResult::Counterexample(Trace::Trace(trace)) = &bug else {
+            panic!("expected a counterexample but got {bug:?}");
+        };
+        // no walk can beat breadth-first search
+        assert!(trace.len() > 12);
+        assert!(!target.safe.evaluate(trace.last().unwrap()));
+        assert!(trace[..trace.len() - 1]
+            .iter()
+            .all(|state| target.safe.evaluate(state)));
+        // the answer only depends on the seed
+        assert_eq!(walk_interpret(&target, &config, false), bug);
+
+        let too_short = WalkConfig {
+            depth: 11,
+            ..config
+        };
+        assert_eq!(
+            walk_interpret(&target, &too_short, false),
+            InterpreterResult::Unknown
+        );
+
+        Ok(())
+    }
+
     #[test]
     fn checker_set_consensus() -> Result<(), CheckerError> {
         let source = include_str!("../../temporal-verifier/examples/consensus.fly");
@@ -1387,7 +2242,6 @@ mod tests {
 
         let mut m = fly::parser::parse(source).unwrap();
         sort_check_module(&mut m).unwrap();
-        let _ = m.convert_non_bool_relations().unwrap();
         let universe = std::collections::HashMap::from([
             ("node".to_string(), 2),
             ("quorum".to_string(), 2),
@@ -1399,6 +2253,129 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn checker_set_consensus_forall_functions() -> Result<(), CheckerError> {
+        let source = include_str!("../../temporal-verifier/examples/consensus_forall.fly");
+
+        let mut m = fly::parser::parse(source).unwrap();
+        sort_check_module(&mut m).unwrap();
+        // voting_quorum is encoded with two bits, one value of which is out of range
+        let universe = std::collections::HashMap::from([
+            ("node".to_string(), 2),
+            ("quorum".to_string(), 3),
+            ("value".to_string(), 2),
+        ]);
+        let (program, _) = translate::<2>(&m, &universe, false)?;
+        let indices = Indices::new(&m.signature, &universe, 1);
+        let quorum = fly::parser::term("voting_quorum");
+        assert!(!program.inits.is_empty());
+        for init in &program.inits {
+            let model = indices.model(0, |i| init.get(i) as Element);
+            assert!(matches!(model.eval(&quorum), 0..=2));
+        }
+        let output = check(&m, &universe, Some(10), TraceCompression::No, false)?;
+        assert_eq!(output, CheckerAnswer::Unknown);
+
+        Ok(())
+    }
+
+    #[test]
+    fn checker_set_functions() -> Result<(), CheckerError> {
+        let source = include_str!("../../temporal-verifier/tests/examples/ring_functions.fly");
+        let mut m = fly::parser::parse(source).unwrap();
+        sort_check_module(&mut m).unwrap();
+        let universe = std::collections::HashMap::from([("node".to_string(), 3)]);
+
+        // every successor function and owner is an initial state, but none out of range
+        let (program, _) = translate::<2>(&m, &universe, false)?;
+        assert_eq!(81, program.inits.len());
+
+        let output = check(&m, &universe, Some(1), TraceCompression::No, false)?;
+        assert_eq!(output, CheckerAnswer::Unknown);
+        let CheckerAnswer::Counterexample(models) =
+            check(&m, &universe, None, TraceCompression::No, false)?
+        else {
+            panic!("expected a counterexample")
+        };
+        assert_eq!(3, models.len());
+        let owner = fly::parser::term("owner");
+        let next_owner = fly::parser::term("succ(owner)");
+        for (pre, post) in models.iter().zip(&models[1..]) {
+            assert_eq!(pre.eval(&next_owner), post.eval(&owner));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn checker_set_multiple_transitions() -> Result<(), CheckerError> {
+        let source = include_str!("../../temporal-verifier/tests/examples/token_passing.fly");
+        let mut m = fly::parser::parse(source).unwrap();
+        sort_check_module(&mut m).unwrap();
+        let universe = std::collections::HashMap::from([("node".to_string(), 2)]);
+
+        // one initial state per token holder, and one transition per move of the token,
+        // since the disjunctive initial condition and both transition axioms are enumerated
+        let (program, _) = translate::<2>(&m, &universe, false)?;
+        assert_eq!(2, program.inits.len());
+        assert_eq!(4, program.trs.len());
+
+        // the last axiom is only known to hold after evaluating the post-state
+        let output = check(&m, &universe, None, TraceCompression::No, false)?;
+        assert_eq!(output, CheckerAnswer::Convergence(()));
+
+        // without it, both nodes see the token after it is passed twice
+        m.statements.remove(3);
+        let CheckerAnswer::Counterexample(models) =
+            check(&m, &universe, None, TraceCompression::No, false)?
+        else {
+            panic!("expected a counterexample")
+        };
+        // the nodes in a unary relation, given by its index in the signature
+        let nodes = |model: &Model, relation: usize| {
+            (0..2)
+                .filter(|n| model.interp[relation].data[*n] == 1)
+                .collect::<Vec<_>>()
+        };
+        let token = |model: &Model| nodes(model, 0);
+        let seen = |model: &Model| nodes(model, 1);
+        assert_eq!(3, models.len());
+        assert_eq!(seen(&models[0]), Vec::<usize>::new());
+        assert_eq!(seen(&models[1]), token(&models[0]));
+        assert_ne!(token(&models[1]), token(&models[0]));
+        assert_eq!(seen(&models[2]), vec![0, 1]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn checker_set_wide_states() -> Result<(), CheckerError> {
+        let source = "
+sort s
+mutable r(s, s, s): bool
+
+assume forall x:s, y:s, z:s. !r(x, y, z)
+assume always exists a:s, b:s, c:s. forall x:s, y:s, z:s.
+    r'(x, y, z) <-> r(x, y, z) | (x = a & y = b & z = c)
+assert always forall x:s. !r(x, x, x)
+        ";
+        let mut m = fly::parser::parse(source).unwrap();
+        sort_check_module(&mut m).unwrap();
+
+        // 216 bits
+        let universe = std::collections::HashMap::from([("s".to_string(), 6)]);
+        let answer = check(&m, &universe, Some(1), TraceCompression::No, false)?;
+        assert!(matches!(answer, CheckerAnswer::Counterexample(models) if models.len() == 2));
+
+        let universe = std::collections::HashMap::from([("s".to_string(), 26)]);
+        assert_eq!(
+            check(&m, &universe, Some(1), TraceCompression::No, false),
+            Err(CheckerError::StateTooLarge(26 * 26 * 26, MAX_STATE_LEN))
+        );
+
+        Ok(())
+    }
+
     #[test]
     fn checker_set_immutability() {
         let source =
@@ -1425,7 +2402,7 @@ assume always forall a:x. ((g'(a) <-> f(a)) & (f'(a) <-> f(a)))
         let mut m = fly::parser::parse(source).unwrap();
         sort_check_module(&mut m).unwrap();
         let universe = std::collections::HashMap::from([("x".to_string(), 5)]);
-        let (target, _) = translate(&m, &universe, false)?;
+        let (target, _) = translate::<2>(&m, &universe, false)?;
         assert_eq!(1, target.trs.len());
         Ok(())
     }
@@ -1439,7 +2416,7 @@ immutable f(s): bool
         let m = fly::parser::parse(source).unwrap();
         let universe = std::collections::HashMap::from([("s".to_string(), 2)]);
         let indices = Indices::new(&m.signature, &universe, 1);
-        let mut set = IsoStateSet::new(&indices);
+        let mut set = IsoStateSet::new(&indices, SeenStates::Full);
 
         assert!(set.insert(&state([0, 0])));
         assert!(!set.insert(&state([0, 0])));
@@ -1456,7 +2433,7 @@ immutable f(a, b): bool
         let universe =
             std::collections::HashMap::from([("a".to_string(), 3), ("b".to_string(), 3)]);
         let indices = Indices::new(&m.signature, &universe, 1);
-        let mut set = IsoStateSet::new(&indices);
+        let mut set = IsoStateSet::new(&indices, SeenStates::Full);
 
         // b: 0 -> 2, 2 -> 1, 1 -> 0
         assert!(set.insert(&state([1, 1, 1, 0, 0, 1, 0, 1, 1])));
@@ -1471,8 +2448,8 @@ immutable f(s, t, s): bool
         let universe =
             std::collections::HashMap::from([("s".to_string(), 3), ("t".to_string(), 2)]);
         let indices = Indices::new(&m.signature, &universe, 1);
-        let mut set = IsoStateSet::new(&indices);
-        let state = |vec: Vec<(usize, usize, usize)>| -> BoundedState {
+        let mut set = IsoStateSet::new(&indices, SeenStates::Full);
+        let state = |vec: Vec<(usize, usize, usize)>| -> BoundedState<2> {
             let mut out = BoundedState::ZERO;
             for (x, y, z) in vec {
                 out.set(indices.get("f", 0, &[x, y, z]), true);
@@ -1506,8 +2483,8 @@ immutable f(s, bool, s): bool
         let m = fly::parser::parse(source).unwrap();
         let universe = std::collections::HashMap::from([("s".to_string(), 3)]);
         let indices = Indices::new(&m.signature, &universe, 1);
-        let mut set = IsoStateSet::new(&indices);
-        let state = |vec: Vec<(usize, usize, usize)>| -> BoundedState {
+        let mut set = IsoStateSet::new(&indices, SeenStates::Full);
+        let state = |vec: Vec<(usize, usize, usize)>| -> BoundedState<2> {
             let mut out = BoundedState::ZERO;
             for (x, y, z) in vec {
                 out.set(indices.get("f", 0, &[x, y, z]), true);
@@ -0,0 +1,475 @@
+// Copyright 2022-2023 VMware, Inc.
+// SPDX-License-Identifier: BSD-2-Clause
+
+//! Step-by-step simulation of a flyvy module over a bounded universe.
+//!
+//! The transition relation is split into actions, one for each top-level
+//! disjunct, where the existentially quantified variables around a disjunct
+//! become the parameters of its action. If there are several transition
+//! assumptions, each action combines one disjunct of every assumption. Each instantiation of the parameters
+//! is translated to a BDD over the current and next state, so the enabled
+//! instances and their successors can be found by restricting the BDD to the
+//! current state. This is intended for interactive exploration, so it makes no
+//! attempt to be as fast as the checkers.
+
+use crate::{checker::*, indices::*, quant_enum::*};
+use biodivine_lib_bdd::*;
+use fly::{ouritertools::OurItertools, semantics::*, syntax::*, transitions::*};
+use itertools::Itertools;
+use rand::Rng;
+use std::collections::HashMap;
+
+/// One disjunct of the transition relation, `exists binders. body`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Action {
+    /// The parameters of the action
+    pub binders: Vec<Binder>,
+    /// The body of the action, which may refer to the parameters
+    pub body: Term,
+}
+
+/// An action with concrete values for its parameters.
+pub struct Instance {
+    /// The index of the action in [`Simulator::actions`]
+    pub action: usize,
+    /// The value of each of the action's parameters
+    pub args: Vec<Element>,
+    /// The transition relation of this instance, over two copies of the state
+    tr: Bdd,
+}
+
+/// A state of the bounded system, as the value of each index of a single copy of
+/// the signature.
+///
+/// A state is displayed as a string of bits, which [`Simulator::parse_state`]
+/// reads back.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct State(Vec<bool>);
+
+impl std::fmt::Display for State {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for bit in &self.0 {
+            write!(f, "{}", *bit as usize)?;
+        }
+        Ok(())
+    }
+}
+
+/// Simulates a module by stepping through individual transitions.
+pub struct Simulator<'a> {
+    /// The actions that make up the transition relation
+    pub actions: Vec<Action>,
+    /// All instantiations of the actions
+    pub instances: Vec<Instance>,
+    /// Indices for states
+    one: Indices<'a>,
+    /// Indices for transitions, where a state is mapped to the unprimed copy
+    two: Indices<'a>,
+    /// The initial states (including the axioms)
+    inits: Bdd,
+    /// The safety properties
+    safeties: Vec<Term>,
+}
+
+/// Split a transition relation into its actions.
+fn actions(term: &Term, binders: &[Binder], out: &mut Vec<Action>) {
+    match term {
+        Term::NAryOp(NOp::Or, terms) => {
+            for term in terms {
+                actions(term, binders, out);
+            }
+        }
+        Term::Quantified {
+            quantifier: Quantifier::Exists,
+            binders: inner,
+            body,
+        } => {
+            let binders = binders.iter().chain(inner).cloned().collect::<Vec<_>>();
+            actions(body, &binders, out);
+        }
+        _ => out.push(Action {
+            binders: binders.to_vec(),
+            body: term.clone(),
+        }),
+    }
+}
+
+/// Rename the free occurrences of the variable `from` in a term to `to`, which must not occur
+/// in the term.
+fn rename(term: &Term, from: &str, to: &str) -> Term {
+    let go = |term: &Term| Box::new(rename(term, from, to));
+    match term {
+        Term::Id(name) if name == from => Term::Id(to.to_string()),
+        Term::Literal(_) | Term::Id(_) => term.clone(),
+        Term::App(f, primes, args) => Term::App(
+            f.clone(),
+            *primes,
+            args.iter().map(|arg| *go(arg)).collect(),
+        ),
+        Term::UnaryOp(op, x) => Term::UnaryOp(*op, go(x)),
+        Term::BinOp(op, x, y) => Term::BinOp(*op, go(x), go(y)),
+        Term::NAryOp(op, xs) => Term::NAryOp(*op, xs.iter().map(|x| *go(x)).collect()),
+        Term::Ite { cond, then, else_ } => Term::Ite {
+            cond: go(cond),
+            then: go(then),
+            else_: go(else_),
+        },
+        // `from` is shadowed
+        Term::Quantified { binders, .. } if binders.iter().any(|b| b.name == from) => term.clone(),
+        Term::Quantified {
+            quantifier,
+            binders,
+            body,
+        } => Term::Quantified {
+            quantifier: *quantifier,
+            binders: binders.clone(),
+            body: go(body),
+        },
+    }
+}
+
+/// Combine one action of each transition assumption into a single action, renaming the
+/// parameters that are already used by an earlier action.
+fn combine(parts: &[&Action]) -> Action {
+    if let [action] = parts {
+        return (*action).clone();
+    }
+    let text = parts.iter().map(|part| part.body.to_string()).join(" ");
+    let mut binders: Vec<Binder> = vec![];
+    let mut bodies = vec![];
+    for part in parts {
+        let taken = binders.len();
+        let mut body = part.body.clone();
+        for binder in &part.binders {
+            let mut binder = binder.clone();
+            if binders[..taken].iter().any(|b| b.name == binder.name) {
+                let name = (1..)
+                    .map(|k| format!("{}_{k}", binder.name))
+                    .find(|name| {
+                        !text.contains(name.as_str())
+                            && !binders.iter().chain(&part.binders).any(|b| &b.name == name)
+                    })
+                    .unwrap();
+                body = rename(&body, &binder.name, &name);
+                binder.name = name;
+            }
+            binders.push(binder);
+        }
+        bodies.push(body);
+    }
+    Action {
+        binders,
+        body: Term::and(bodies),
+    }
+}
+
+impl<'a> Simulator<'a> {
+    /// Prepare to simulate a module, which should be sort checked, have its
+    /// definitions inlined, and have only boolean relations.
+    pub fn new(
+        module: &'a Module,
+        universe: &'a UniverseBounds,
+    ) -> Result<Simulator<'a>, CheckerError> {
+        for relation in &module.signature.relations {
+            if relation.sort != Sort::Bool {
+                panic!("non-bool relations in checker (use Module::convert_non_bool_relations)")
+            }
+        }
+        for sort in &module.signature.sorts {
+            if !universe.contains_key(sort) {
+                return Err(CheckerError::UnknownSort(sort.clone(), universe.clone()));
+            }
+        }
+        if !module.defs.is_empty() {
+            panic!("definitions in checker (use Module::inline_defs)")
+        }
+
+        let d = extract(module).map_err(CheckerError::ExtractionError)?;
+        let one = Indices::new(&module.signature, universe, 1);
+        let two = Indices::new(&module.signature, universe, 2);
+        let enumerate = |term: &Term, assignments: &HashMap<String, Element>| {
+            enumerate_quantifiers_with(term, &module.signature, universe, assignments)
+                .map_err(CheckerError::EnumerationError)
+        };
+
+        let inits = Term::and(d.inits.iter().chain(&d.axioms));
+        let inits = one.bdd_from_enumerated(enumerate(&inits, &HashMap::new())?);
+
+        // the transition relation is the conjunction of the transition assumptions
+        let parts = d
+            .transitions
+            .iter()
+            .map(|tr| {
+                let mut acts = vec![];
+                actions(tr, &[], &mut acts);
+                acts
+            })
+            .collect::<Vec<_>>();
+        let acts = match parts.is_empty() {
+            true => vec![],
+            false => parts
+                .iter()
+                .map(|acts| acts.iter())
+                .multi_cartesian_product()
+                .map(|parts| combine(&parts))
+                .collect(),
+        };
+
+        // the mutable axioms must also hold in the post-state
+        let axioms = Term::and(d.mutable_axioms(&module.signature.relations));
+        let axioms = two.bdd_from_enumerated(enumerate(&axioms, &HashMap::new())?.prime(1));
+        let mut instances = vec![];
+        for (i, action) in acts.iter().enumerate() {
+            let all_args = action
+                .binders
+                .iter()
+                .map(|b| (0..cardinality(universe, &b.sort)).collect::<Vec<Element>>())
+                .multi_cartesian_product_fixed();
+            for args in all_args {
+                let assignments = (action.binders.iter())
+                    .map(|b| b.name.clone())
+                    .zip_eq(args.iter().copied())
+                    .collect();
+                let tr = two.bdd_from_enumerated(enumerate(&action.body, &assignments)?);
+                instances.push(Instance {
+                    action: i,
+                    args,
+                    tr: tr.and(&axioms),
+                });
+            }
+        }
+
+        Ok(Simulator {
+            actions: acts,
+            instances,
+            one,
+            two,
+            inits,
+            safeties: d.proofs.into_iter().map(|proof| proof.safety.x).collect(),
+        })
+    }
+
+    /// The number of initial states, as a decimal string since it may be very
+    /// large.
+    pub fn num_initial(&self) -> String {
+        self.inits.exact_cardinality().to_string()
+    }
+
+    fn state(&self, valuation: &BddValuation) -> State {
+        State(
+            (0..self.one.num_vars)
+                .map(|i| valuation.value(self.one.bdd_variables[i]))
+                .collect(),
+        )
+    }
+
+    /// The `n`th initial state, in a fixed order.
+    pub fn initial(&self, n: usize) -> Option<State> {
+        self.inits
+            .sat_valuations()
+            .nth(n)
+            .map(|valuation| self.state(&valuation))
+    }
+
+    /// A random initial state, or `None` if there are no initial states.
+    pub fn random_initial(&self, rng: &mut impl Rng) -> Option<State> {
+        self.inits
+            .random_valuation(rng)
+            .map(|valuation| self.state(&valuation))
+    }
+
+    /// Read a state written by its `Display` implementation, checking that it
+    /// is an initial state.
+    pub fn parse_state(&self, s: &str) -> Option<State> {
+        let bits = (s.chars())
+            .map(|c| match c {
+                '0' => Some(false),
+                '1' => Some(true),
+                _ => None,
+            })
+            .collect::<Option<Vec<_>>>()?;
+        if bits.len() != self.one.num_vars {
+            return None;
+        }
+        let valuation = BddValuation::new(bits.clone());
+        if !self.inits.eval_in(&valuation) {
+            return None;
+        }
+        Some(State(bits))
+    }
+
+    /// The index in `self.two` of each index in `self.one`.
+    fn unprimed(&self, i: usize) -> usize {
+        if i < self.one.num_mutables {
+            i
+        } else {
+            i + self.one.num_mutables
+        }
+    }
+
+    /// The successors of a state under one instance, in a fixed order. The
+    /// instance is enabled if this is nonempty.
+    pub fn successors(&self, state: &State, instance: &Instance) -> Vec<State> {
+        let current = (state.0.iter().enumerate())
+            .map(|(i, value)| (self.two.bdd_variables[self.unprimed(i)], *value))
+            .collect::<Vec<_>>();
+        let current = self
+            .two
+            .bdd_context
+            .mk_conjunctive_clause(&BddPartialValuation::from_values(&current));
+        let nm = self.one.num_mutables;
+        instance
+            .tr
+            .and(&current)
+            .sat_valuations()
+            .map(|valuation| {
+                let mut next = state.clone();
+                for (i, value) in next.0.iter_mut().enumerate().take(nm) {
+                    *value = valuation.value(self.two.bdd_variables[i + nm]);
+                }
+                next
+            })
+            .collect()
+    }
+
+    /// The enabled instances from a state, given as indices into
+    /// [`Simulator::instances`] together with the instance's successors.
+    pub fn enabled(&self, state: &State) -> Vec<(usize, Vec<State>)> {
+        (self.instances.iter().enumerate())
+            .map(|(i, instance)| (i, self.successors(state, instance)))
+            .filter(|(_, successors)| !successors.is_empty())
+            .collect()
+    }
+
+    /// Find the instance of an action with the given arguments.
+    pub fn instance(&self, action: usize, args: &[Element]) -> Option<usize> {
+        self.instances
+            .iter()
+            .position(|instance| instance.action == action && instance.args == args)
+    }
+
+    /// Convert a state to a model of the module's signature.
+    pub fn model(&self, state: &State) -> Model {
+        self.one.model(0, |i| state.0[i] as Element)
+    }
+
+    /// The indices of the safety properties that do not hold in a state.
+    pub fn violations(&self, state: &State) -> Vec<usize> {
+        let model = self.model(state);
+        (self.safeties.iter().enumerate())
+            .filter(|(_, safe)| model.eval(safe) == 0)
+            .map(|(i, _)| i)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fly::sorts::sort_check_module;
+
+    #[test]
+    fn simulate_lockserver() -> Result<(), CheckerError> {
+        let source = include_str!("../../temporal-verifier/examples/lockserver.fly");
+        let mut m = fly::parser::parse(source).unwrap();
+        sort_check_module(&mut m).unwrap();
+        let universe = std::collections::HashMap::from([("node".to_string(), 2)]);
+        let sim = Simulator::new(&m, &universe)?;
+
+        assert_eq!(sim.actions.len(), 5);
+        assert_eq!(sim.instances.len(), 10);
+        assert_eq!(sim.num_initial(), "1");
+        let init = sim.initial(0).unwrap();
+        assert_eq!(sim.initial(1), None);
+
+        // only the nodes can send lock messages at first
+        let enabled = sim.enabled(&init);
+        let enabled_actions = (enabled.iter())
+            .map(|(i, _)| (sim.instances[*i].action, sim.instances[*i].args.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(enabled_actions, vec![(0, vec![0]), (0, vec![1])]);
+        assert!(enabled.iter().all(|(_, successors)| successors.len() == 1));
+
+        // follow node 1 through acquiring and releasing the lock
+        let held = fly::parser::term("exists n:node. holds_lock(n)");
+        let mut state = init.clone();
+        for action in 0..5 {
+            let i = sim.instance(action, &[1]).unwrap();
+            let successors = sim.successors(&state, &sim.instances[i]);
+            assert_eq!(successors.len(), 1, "action {action} should be enabled");
+            state = successors[0].clone();
+            let holds_lock = sim.model(&state).eval(&held);
+            assert_eq!(holds_lock, (action == 2) as Element);
+        }
+        assert_eq!(state, init);
+        assert_eq!(sim.violations(&state), Vec::<usize>::new());
+        assert_eq!(sim.parse_state(&init.to_string()), Some(init.clone()));
+        let mut bad = init.clone();
+        bad.0[0] = !bad.0[0];
+        assert_eq!(sim.parse_state(&bad.to_string()), None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn simulate_nondeterministic() -> Result<(), CheckerError> {
+        let source = "
+sort s
+mutable p(s): bool
+
+assume forall x:s. !p(x)
+assume always (exists x:s. p'(x)) | (forall x:s. p'(x) <-> p(x))
+        ";
+        let mut m = fly::parser::parse(source).unwrap();
+        sort_check_module(&mut m).unwrap();
+        let universe = std::collections::HashMap::from([("s".to_string(), 2)]);
+        let sim = Simulator::new(&m, &universe)?;
+
+        assert_eq!(sim.actions.len(), 2);
+        let init = sim.initial(0).unwrap();
+        let mut rng = rand::thread_rng();
+        assert_eq!(sim.random_initial(&mut rng), Some(init.clone()));
+        // p can be set to anything that includes x
+        let successors = sim.successors(&init, &sim.instances[0]);
+        assert_eq!(successors.len(), 2);
+        let stutter = sim.instance(1, &[]).unwrap();
+        assert_eq!(sim.successors(&init, &sim.instances[stutter]), vec![init]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn simulate_multiple_transitions() -> Result<(), CheckerError> {
+        let source = "
+sort s
+mutable p(s): bool
+mutable q(s): bool
+
+assume forall x:s. !p(x) & !q(x)
+assume always (exists x:s. forall y:s. p'(y) <-> p(y) | y = x) | (forall y:s. p'(y) <-> p(y))
+assume always exists x:s. forall y:s. q'(y) <-> q(y) | y = x
+        ";
+        let mut m = fly::parser::parse(source).unwrap();
+        sort_check_module(&mut m).unwrap();
+        let universe = std::collections::HashMap::from([("s".to_string(), 2)]);
+        let sim = Simulator::new(&m, &universe)?;
+
+        // each action takes one disjunct of each transition assumption, and the parameter
+        // of the second one is renamed
+        assert_eq!(sim.actions.len(), 2);
+        let names = |action: &Action| action.binders.iter().map(|b| b.name.clone()).collect_vec();
+        assert_eq!(names(&sim.actions[0]), vec!["x", "x_1"]);
+        assert_eq!(names(&sim.actions[1]), vec!["x"]);
+        assert_eq!(sim.instances.len(), 6);
+
+        let init = sim.initial(0).unwrap();
+        let i = sim.instance(0, &[0, 1]).unwrap();
+        let successors = sim.successors(&init, &sim.instances[i]);
+        assert_eq!(successors.len(), 1);
+        // p and q are set for different elements
+        let different = fly::parser::term("exists x:s, y:s. p(x) & !p(y) & q(y) & !q(x)");
+        assert_eq!(sim.model(&successors[0]).eval(&different), 1);
+
+        Ok(())
+    }
+}
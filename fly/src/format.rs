@@ -0,0 +1,430 @@
+// Copyright 2022-2023 VMware, Inc.
+// SPDX-License-Identifier: BSD-2-Clause
+
+//! Comment-preserving formatter for the flyvy language.
+//!
+//! Unlike [`printer::fmt`], which prints a [`Module`](crate::syntax::Module)
+//! from its AST, the formatter works on source text. Each top-level item is
+//! re-printed, with long terms broken across lines (one disjunct or conjunct
+//! per line), while comments and blank lines between items are kept. An item
+//! with a comment inside it is kept as written unless the comments are between
+//! the invariants of a proof, where they can be re-attached.
+
+use itertools::Itertools;
+use peg::{error::ParseError, str::LineCol};
+
+use crate::parser::{self, item_starts, parse_item, Item};
+use crate::printer::{self, left_associative, precedence, right_associative};
+use crate::syntax::*;
+
+/// The line width the formatter tries to stay within.
+pub const MAX_WIDTH: usize = 100;
+
+/// Indentation added for each level of nesting.
+const INDENT: usize = 4;
+
+/// Indentation of definition bodies, matching [`printer::fmt`].
+const DEF_INDENT: usize = 2;
+
+fn spaces(n: usize) -> String {
+    " ".repeat(n)
+}
+
+/// Lay out a child of some term, adding parentheses if needed.
+fn child(arg: &Term, add_parens: bool, indent: usize, col: usize, tail: usize) -> String {
+    if add_parens {
+        format!("({})", layout(arg, indent, col + 1, tail + 1))
+    } else {
+        layout(arg, indent, col, tail)
+    }
+}
+
+/// Lay out a term whose first line starts at column `col` and whose last line
+/// will be followed by `tail` more characters. If the term does not fit within
+/// [`MAX_WIDTH`], it is broken across lines; continuation lines are indented
+/// relative to `indent`.
+///
+/// Only `&`/`|` chains, `->`/`<->`, `always`/`eventually` and quantifiers are
+/// broken; other terms are printed on one line regardless of their length.
+fn layout(t: &Term, indent: usize, col: usize, tail: usize) -> String {
+    let flat = printer::term(t);
+    if col + flat.len() + tail <= MAX_WIDTH {
+        return flat;
+    }
+    let inner = indent + INDENT;
+    match t {
+        Term::NAryOp(op, args) if args.len() > 1 => {
+            let op = match op {
+                NOp::And => "&",
+                NOp::Or => "|",
+            };
+            let last = args.len() - 1;
+            args.iter()
+                .enumerate()
+                .map(|(i, arg)| {
+                    let col = if i == 0 { col } else { indent };
+                    let tail = if i == last { tail } else { op.len() + 1 };
+                    child(arg, precedence(t) > precedence(arg), indent, col, tail)
+                })
+                .join(&format!(" {op}\n{}", spaces(indent)))
+        }
+        Term::UnaryOp(op @ (UOp::Always | UOp::Eventually), arg) => {
+            let op = match op {
+                UOp::Always => "always",
+                _ => "eventually",
+            };
+            let arg = child(arg, precedence(t) > precedence(arg), inner, inner, tail);
+            format!("{op}\n{}{arg}", spaces(inner))
+        }
+        Term::BinOp(op @ (BinOp::Implies | BinOp::Iff), arg1, arg2) => {
+            let use_left_paren = precedence(t) > precedence(arg1)
+                || (precedence(t) == precedence(arg1) && right_associative(op));
+            let use_right_paren = precedence(t) > precedence(arg2)
+                || (precedence(t) == precedence(arg2) && left_associative(op));
+            let op = match op {
+                BinOp::Implies => "->",
+                _ => "<->",
+            };
+            let left = child(arg1, use_left_paren, indent, col, op.len() + 1);
+            let right = child(arg2, use_right_paren, inner, inner, tail);
+            format!("{left} {op}\n{}{right}", spaces(inner))
+        }
+        Term::Quantified {
+            quantifier,
+            binders,
+            body,
+        } => {
+            let quantifier = match quantifier {
+                Quantifier::Forall => "forall",
+                Quantifier::Exists => "exists",
+            };
+            let binders = binders.iter().map(printer::binder).join(", ");
+            let body = layout(body, inner, inner, tail);
+            format!("{quantifier} {binders}.\n{}{body}", spaces(inner))
+        }
+        _ => flat,
+    }
+}
+
+/// Print a term after some keyword (e.g., `assume`) which starts at column
+/// `indent`. A chain of `&`/`|` or an implication which needs to be broken
+/// starts on the next line, so that its operands are all indented the same.
+fn keyword_term(keyword: &str, t: &Term, indent: usize) -> String {
+    let col = indent + keyword.len() + 1;
+    let flat = printer::term(t);
+    let starts_new_line = matches!(t, Term::NAryOp(..) | Term::BinOp(..));
+    if col + flat.len() > MAX_WIDTH && starts_new_line {
+        let inner = indent + INDENT;
+        let t = layout(t, inner, inner, 0);
+        format!("{}{keyword}\n{}{t}", spaces(indent), spaces(inner))
+    } else {
+        format!("{}{keyword} {}", spaces(indent), layout(t, indent, col, 0))
+    }
+}
+
+/// Comments inside an `assert` item, grouped by where they are re-attached.
+struct ProofComments<'a> {
+    /// Comments between the assertion and the `proof` keyword
+    before_proof: Vec<&'a str>,
+    /// Comments preceding each invariant
+    before_invariant: Vec<Vec<&'a str>>,
+    /// Comments after the last invariant
+    before_close: Vec<&'a str>,
+}
+
+impl<'a> ProofComments<'a> {
+    /// Assign each comment (given with its offset in `text`) to a position in
+    /// the proof. Returns `None` if some comment is inside a term and thus
+    /// cannot be re-attached.
+    fn new(text: &str, proof: &Proof, comments: &[(usize, &'a str)]) -> Option<Self> {
+        let mut pc = ProofComments {
+            before_proof: vec![],
+            before_invariant: vec![vec![]; proof.invariants.len()],
+            before_close: vec![],
+        };
+        if comments.is_empty() {
+            return Some(pc);
+        }
+        if proof.invariants.is_empty() {
+            return None;
+        }
+        let assert_span = proof.assert.span?;
+        let proof_start = assert_span.end + text[assert_span.end..].find("proof")?;
+        let inv_spans = proof
+            .invariants
+            .iter()
+            .map(|inv| inv.span)
+            .collect::<Option<Vec<_>>>()?;
+
+        for &(offset, comment) in comments {
+            if offset < proof_start {
+                if offset < assert_span.end {
+                    return None;
+                }
+                pc.before_proof.push(comment);
+                continue;
+            }
+            if inv_spans
+                .iter()
+                .any(|span| span.start <= offset && offset < span.end)
+            {
+                return None;
+            }
+            match inv_spans.iter().position(|span| offset < span.start) {
+                Some(i) => pc.before_invariant[i].push(comment),
+                None => pc.before_close.push(comment),
+            }
+        }
+        Some(pc)
+    }
+}
+
+fn format_def(def: &Definition) -> String {
+    let binders = def.binders.iter().map(printer::def_binder).join(", ");
+    let body = layout(&def.body, DEF_INDENT, DEF_INDENT, 0);
+    format!(
+        "def {}({binders}) -> {} {{\n{}{body}\n}}",
+        def.name,
+        printer::sort(&def.ret_sort),
+        spaces(DEF_INDENT)
+    )
+}
+
+fn format_proof(proof: &Proof, comments: &ProofComments) -> String {
+    let mut lines = vec![keyword_term("assert", &proof.assert.x, 0)];
+    if proof.invariants.is_empty() {
+        return lines.join("\n");
+    }
+    let comment = |c: &&str| format!("{}{c}", spaces(INDENT));
+    lines.extend(comments.before_proof.iter().map(|c| c.to_string()));
+    lines.push("proof {".to_string());
+    for (inv, inv_comments) in proof.invariants.iter().zip(&comments.before_invariant) {
+        lines.extend(inv_comments.iter().map(comment));
+        lines.push(keyword_term("invariant", &inv.x, INDENT));
+    }
+    lines.extend(comments.before_close.iter().map(comment));
+    lines.push("}".to_string());
+    lines.join("\n")
+}
+
+/// Format a single item, given its source text (from its first to its last
+/// non-comment line). Falls back to the original text if it contains comments
+/// that cannot be re-attached.
+fn format_item(text: &str) -> String {
+    let verbatim = || text.lines().map(str::trim_end).join("\n");
+
+    let mut comments = vec![];
+    let mut offset = 0;
+    for line in text.split_inclusive('\n') {
+        let trimmed = line.trim();
+        if trimmed.starts_with('#') {
+            comments.push((offset, trimmed));
+        } else if trimmed.contains('#') {
+            // a comment at the end of a line of code
+            return verbatim();
+        }
+        offset += line.len();
+    }
+
+    let item = parse_item(text).expect("item of a parsed module should parse");
+    match item {
+        Item::Stmt(ThmStmt::Assert(proof)) => match ProofComments::new(text, &proof, &comments) {
+            Some(pc) => format_proof(&proof, &pc),
+            None => verbatim(),
+        },
+        _ if !comments.is_empty() => verbatim(),
+        Item::Sort(sort) => format!("sort {sort}"),
+        Item::Relation(decl) => printer::relation_decl(&decl),
+        Item::Def(def) => format_def(&def),
+        Item::Stmt(ThmStmt::Assume(t)) => keyword_term("assume", &t, 0),
+    }
+}
+
+/// Accumulates formatted output, collapsing runs of blank lines.
+#[derive(Default)]
+struct Output {
+    lines: Vec<String>,
+}
+
+impl Output {
+    fn blank(&mut self) {
+        if self.lines.last().is_some_and(|l| !l.is_empty()) {
+            self.lines.push(String::new());
+        }
+    }
+
+    fn push(&mut self, s: String) {
+        self.lines.extend(s.lines().map(|l| l.to_string()));
+    }
+
+    /// Copy comments and blank lines that appear between items.
+    fn between(&mut self, lines: &[&str]) {
+        for line in lines {
+            let line = line.trim();
+            if line.is_empty() {
+                self.blank();
+            } else {
+                self.lines.push(line.to_string());
+            }
+        }
+    }
+
+    fn finish(mut self) -> String {
+        while self.lines.last().is_some_and(|l| l.is_empty()) {
+            self.lines.pop();
+        }
+        let mut s = self.lines.join("\n");
+        s.push('\n');
+        s
+    }
+}
+
+/// Format the source of a fly module.
+///
+/// Returns an error if the module does not parse.
+pub fn format(source: &str) -> Result<String, ParseError<LineCol>> {
+    parser::parse(source)?;
+
+    let mut out = Output::default();
+    let starts = item_starts(source);
+    let ends = starts.iter().skip(1).copied().chain([source.len()]);
+    for (start, end) in starts.iter().copied().zip(ends) {
+        let lines = source[start..end].split_inclusive('\n').collect::<Vec<_>>();
+        let is_code = |line: &&str| {
+            let line = line.trim();
+            !line.is_empty() && !line.starts_with('#')
+        };
+        let Some(first) = lines.iter().position(is_code) else {
+            out.between(&lines);
+            continue;
+        };
+        let last = lines.iter().rposition(is_code).unwrap();
+        out.between(&lines[..first]);
+        out.push(format_item(&lines[first..=last].concat()));
+        out.between(&lines[last + 1..]);
+    }
+
+    Ok(out.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+    use std::fs;
+
+    fn clear_spans(m: &mut Module) {
+        for stmt in &mut m.statements {
+            if let ThmStmt::Assert(proof) = stmt {
+                proof.assert.span = None;
+                for inv in &mut proof.invariants {
+                    inv.span = None;
+                }
+            }
+        }
+    }
+
+    /// Check that formatting preserves the module and is idempotent.
+    fn check_format(source: &str) -> String {
+        let formatted = format(source).expect("test module should parse");
+        let mut m1 = parse(source).unwrap();
+        let mut m2 = parse(&formatted).expect("formatted module should parse");
+        clear_spans(&mut m1);
+        clear_spans(&mut m2);
+        assert_eq!(m1, m2, "formatting changed the module");
+        assert_eq!(format(&formatted).unwrap(), formatted, "not idempotent");
+        formatted
+    }
+
+    #[test]
+    fn test_format_comments() {
+        let source = r"# header comment
+
+sort node
+mutable p(node):   bool
+
+
+# the initial state
+assume   forall n:node. !p(n)
+assume always
+  # frame condition
+  (forall n:node. p'(n) <-> p(n))   
+assert always forall n:node. !p(n)
+proof {
+  # the only invariant
+  invariant forall n:node. !p(n)
+  # invariant false
+}
+";
+        insta::assert_snapshot!(check_format(source), @r###"
+        # header comment
+
+        sort node
+        mutable p(node): bool
+
+        # the initial state
+        assume forall n:node. !p(n)
+        assume always
+          # frame condition
+          (forall n:node. p'(n) <-> p(n))
+        assert always (forall n:node. !p(n))
+        proof {
+            # the only invariant
+            invariant forall n:node. !p(n)
+            # invariant false
+        }
+        "###);
+    }
+
+    #[test]
+    fn test_format_wrapping() {
+        let source = r"sort node
+mutable lock_msg(node): bool
+mutable grant_msg(node): bool
+mutable holds_lock(node): bool
+mutable server_holds_lock: bool
+
+assume always (exists n:node. (forall N:node. lock_msg'(N) <-> lock_msg(N) | N = n) & (forall N:node. grant_msg'(N) <-> grant_msg(N)) & (forall N:node. holds_lock'(N) <-> holds_lock(N)) & (server_holds_lock' <-> server_holds_lock)) | (exists n:node. (forall N:node. server_holds_lock & lock_msg(n) & !server_holds_lock' & (lock_msg'(N) <-> lock_msg(N) & N != n) & (grant_msg'(N) <-> grant_msg(N) | N = n)) & (forall N:node. holds_lock'(N) <-> holds_lock(N)))
+";
+        insta::assert_snapshot!(check_format(source), @r###"
+        sort node
+        mutable lock_msg(node): bool
+        mutable grant_msg(node): bool
+        mutable holds_lock(node): bool
+        mutable server_holds_lock: bool
+
+        assume always
+            (exists n:node.
+                (forall N:node. lock_msg'(N) <-> lock_msg(N) | N = n) &
+                (forall N:node. grant_msg'(N) <-> grant_msg(N)) &
+                (forall N:node. holds_lock'(N) <-> holds_lock(N)) &
+                (server_holds_lock' <-> server_holds_lock)) |
+            (exists n:node.
+                (forall N:node.
+                    server_holds_lock &
+                    lock_msg(n) &
+                    !server_holds_lock' &
+                    (lock_msg'(N) <-> lock_msg(N) & N != n) &
+                    (grant_msg'(N) <-> grant_msg(N) | N = n)) &
+                (forall N:node. holds_lock'(N) <-> holds_lock(N)))
+        "###);
+    }
+
+    #[test]
+    fn test_format_examples() {
+        for dir in [
+            "../temporal-verifier/examples",
+            "../temporal-verifier/tests/examples",
+        ] {
+            for entry in fs::read_dir(dir).expect("could not read examples") {
+                let path = entry.unwrap().path();
+                if path.extension().is_some_and(|ext| ext == "fly") {
+                    let source = fs::read_to_string(&path).unwrap();
+                    if parse(&source).is_ok() {
+                        check_format(&source);
+                    }
+                }
+            }
+        }
+    }
+}
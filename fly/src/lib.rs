@@ -16,6 +16,8 @@
 #![deny(rustdoc::broken_intra_doc_links)]
 
 pub mod defs;
+pub mod format;
+pub mod mypyvy;
 pub mod ouritertools;
 pub mod parser;
 pub mod printer;
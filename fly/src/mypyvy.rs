@@ -0,0 +1,480 @@
+// Copyright 2022-2023 VMware, Inc.
+// SPDX-License-Identifier: BSD-2-Clause
+
+//! Import models written in [mypyvy](https://github.com/wilcoxjay/mypyvy)
+//! syntax (`.pyv` files).
+//!
+//! The translation follows mypyvy's own fly printer: axioms become an
+//! `assume always`, the initial conditions an `assume`, and each `transition`
+//! becomes one disjunct of an `assume always`, with its parameters
+//! existentially quantified and with mypyvy's implicit frame conditions (every
+//! mutable symbol not listed in `modifies` keeps its value) made explicit.
+//! Safety properties are conjoined into a single `assert always`, and the
+//! safety properties followed by the invariants form its proof.
+//!
+//! As in mypyvy, free variables (identifiers starting with an uppercase letter
+//! that are not otherwise bound) are implicitly universally quantified, inside
+//! a transition's parameters. Their sorts are left for sort inference to fill
+//! in.
+//!
+//! Theorems and `sat trace`/`unsat trace` blocks are checks rather than part
+//! of the transition system, so they are parsed and ignored. Definitions,
+//! derived relations, and automata are not supported.
+
+use crate::parser::parse_error_diagnostic;
+use crate::syntax::*;
+use codespan_reporting::diagnostic::{Diagnostic, Label};
+use itertools::Itertools;
+use peg::{error::ParseError, str::LineCol};
+use thiserror::Error;
+
+/// A top-level mypyvy declaration.
+enum Decl {
+    Sort(String),
+    Symbol(RelationDecl),
+    Axiom(Term),
+    Init(Term),
+    Transition {
+        name: String,
+        params: Vec<Binder>,
+        modifies: Vec<Spanned<String>>,
+        body: Term,
+    },
+    Safety(Spanned<Term>),
+    Invariant(Spanned<Term>),
+    /// A declaration that does not contribute to the module (a theorem or a
+    /// trace)
+    Ignored,
+}
+
+peg::parser! {
+
+grammar mypyvy() for str {
+    use UOp::*;
+    use BinOp::*;
+    use Quantifier::*;
+
+    rule ident_start() = ['a'..='z' | 'A'..='Z' | '_']
+    rule ident_char() = ident_start() / ['0'..='9']
+    rule word_boundary() = !ident_char()
+
+    rule keyword() = ("forall" / "exists" / "if" / "then" / "else" / "new" /
+                      "true" / "false" / "distinct" / "modifies" / "sort" /
+                      "mutable" / "immutable" / "relation" / "function" /
+                      "constant" / "axiom" / "init" / "transition" / "safety" /
+                      "invariant" / "theorem" / "zerostate" / "onestate" /
+                      "twostate" / "sat" / "unsat" / "trace") word_boundary()
+
+    rule ident() -> String
+    = s:$(quiet!{!keyword() ident_start() ident_char()*} / expected!("identifier"))
+    { s.to_string() }
+
+    rule comment() = "#" [^'\n' | '\r']*
+    rule _ = quiet!{ ([' ' | '\t' | '\n' | '\r'] / comment())* }
+    rule __ = word_boundary() _
+
+    rule sort() -> Sort
+    = ("bool" word_boundary() { Sort::Bool }) /
+      s:ident() { Sort::Uninterpreted(s) }
+
+    rule binder() -> Binder
+    = name:ident() sort:(_ ":" _ s:sort() { s })? { Binder {
+        name,
+        sort: sort.unwrap_or(Sort::unknown()),
+    } }
+
+    rule args() -> Vec<Term>
+    = "(" _ args:(expr() ** (_ "," _)) _ ")" { args }
+
+    rule atom() -> Term
+    = q:("forall" { Forall } / "exists" { Exists }) __
+        binders:(binder() ** (_ "," _)) _ "." _ body:expr()
+      { Term::Quantified { quantifier: q, binders, body: Box::new(body) } } /
+      "if" __ cond:expr() _ "then" __ then:expr() _ "else" __ else_:expr()
+      { Term::ite(cond, then, else_) } /
+      "new" _ "(" _ t:expr() _ ")" { Term::prime(t) } /
+      "distinct" _ args:args() {
+          Term::and(args.iter().tuple_combinations().map(|(x, y)| Term::not_equals(x, y)))
+      } /
+      "true" word_boundary() { Term::true_() } /
+      "false" word_boundary() { Term::false_() } /
+      f:ident() _ args:args() {
+          if args.is_empty() { Term::Id(f) } else { Term::App(f, 0, args) }
+      } /
+      s:ident() { Term::Id(s) } /
+      "(" _ t:expr() _ ")" { t }
+
+    // mypyvy's precedence, from loosest to tightest; unlike in fly, <-> binds
+    // more loosely than -> and is not associative, and negation binds more
+    // loosely than equality
+    pub(super) rule expr() -> Term
+    = x:implication() y:(_ "<->" _ y:implication() { y })?
+      nested_iff()?
+      { match y {
+          Some(y) => Term::BinOp(Iff, Box::new(x), Box::new(y)),
+          None => x,
+      } }
+
+    rule nested_iff() = _ "<->" {? Err("parentheses around a nested <->") }
+
+    rule implication() -> Term = precedence!{
+        x:@ _ "->" _ y:(@) { Term::BinOp(Implies, Box::new(x), Box::new(y)) }
+        --
+        x:(@) _ "|" _ y:@ { Term::or([x, y]) }
+        // a leading | (or &) is allowed to make long formulas line up
+        "|" _ x:@ { x }
+        --
+        x:(@) _ "&" _ y:@ { Term::and([x, y]) }
+        "&" _ x:@ { x }
+        --
+        "!" _ x:@ { Term::UnaryOp(Not, Box::new(x)) }
+        --
+        x:(@) _ "=" _ y:@ { Term::BinOp(Equals, Box::new(x), Box::new(y)) }
+        x:(@) _ "!=" _ y:@ { Term::BinOp(NotEquals, Box::new(x), Box::new(y)) }
+        --
+        t:atom() { t }
+    }
+
+    rule mutability() -> bool
+    = "mutable" word_boundary() { true } /
+      "immutable" word_boundary() { false }
+
+    // argument sorts, optionally named
+    rule symbol_args() -> Vec<Sort>
+    = "(" _ ss:(((ident() _ ":" _)? s:sort() { s }) ** (_ "," _)) _ ")" { ss }
+
+    rule symbol() -> RelationDecl
+    = mutable:mutability() _ "relation" __ name:ident() _ args:symbol_args()?
+      { RelationDecl { mutable, name, args: args.unwrap_or_default(), sort: Sort::Bool } } /
+      mutable:mutability() _ "function" __ name:ident() _ args:symbol_args() _ ":" _ sort:sort()
+      { RelationDecl { mutable, name, args, sort } } /
+      mutable:mutability() _ "constant" __ name:ident() _ ":" _ sort:sort()
+      { RelationDecl { mutable, name, args: vec![], sort } }
+
+    rule decl_name() = "[" _ ident() _ "]"
+
+    // a declaration keyword followed by an optionally named formula
+    rule named_expr(keyword: rule<()>) -> Term
+    = keyword() __ (decl_name() _)? t:expr() { t }
+
+    rule params() -> Vec<Binder>
+    = "(" _ ps:((name:ident() _ ":" _ sort:sort() { Binder { name, sort } }) ** (_ "," _)) _ ")"
+      { ps }
+
+    rule modifies() -> Vec<Spanned<String>>
+    = "modifies" __ names:(spanned(<ident()>) ** (_ "," _)) { names }
+
+    rule block() = "{" ([^'{' | '}'] / block())* "}"
+
+    rule decl() -> Decl
+    = "sort" __ s:ident() { Decl::Sort(s) } /
+      r:symbol() { Decl::Symbol(r) } /
+      t:named_expr(<"axiom">) { Decl::Axiom(t) } /
+      t:named_expr(<"init">) { Decl::Init(t) } /
+      "transition" __ name:ident() _ params:params() _ modifies:(m:modifies() _ { m })?
+        body:expr()
+      { Decl::Transition { name, params, modifies: modifies.unwrap_or_default(), body } } /
+      t:spanned(<named_expr(<"safety">)>) { Decl::Safety(t) } /
+      t:spanned(<named_expr(<"invariant">)>) { Decl::Invariant(t) } /
+      (("zerostate" / "onestate" / "twostate") __)? named_expr(<"theorem">) { Decl::Ignored } /
+      ("sat" / "unsat") __ "trace" _ block() { Decl::Ignored }
+
+    rule spanned<T>(e: rule<T>) -> Spanned<T>
+    = start:position!() x:e() end:position!()
+      { Spanned { x, span: Some(Span { start, end }) } }
+
+    pub(super) rule program() -> Vec<Decl>
+    = _ ds:(decl() ** _) _ { ds }
+}
+}
+
+/// An error importing a mypyvy program.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum ImportError {
+    /// The program did not parse.
+    #[error("expected {}", .0.expected)]
+    Parse(ParseError<LineCol>),
+    /// A transition listed a name in `modifies` that is not a mutable symbol.
+    #[error("transition {transition} modifies {name}, which is not a mutable symbol")]
+    NotMutable {
+        /// The name of the transition
+        transition: String,
+        /// The modified name
+        name: String,
+        /// The location of the name in the `modifies` clause
+        span: Option<Span>,
+    },
+}
+
+/// Universally quantify the free variables of `t` (with unknown sorts), where
+/// `bound` are the names already bound around `t`.
+fn close(t: Term, bound: &[String], sig: &Signature) -> Term {
+    fn go(t: &Term, bound: &mut Vec<String>, sig: &Signature, free: &mut Vec<String>) {
+        match t {
+            Term::Literal(_) => {}
+            Term::Id(name) => {
+                if name.starts_with(|c: char| c.is_ascii_uppercase())
+                    && !bound.contains(name)
+                    && !sig.contains_relation(name)
+                    && !free.contains(name)
+                {
+                    free.push(name.clone());
+                }
+            }
+            Term::App(_, _, args) | Term::NAryOp(_, args) => {
+                for arg in args {
+                    go(arg, bound, sig, free);
+                }
+            }
+            Term::UnaryOp(_, t) => go(t, bound, sig, free),
+            Term::BinOp(_, lhs, rhs) => {
+                go(lhs, bound, sig, free);
+                go(rhs, bound, sig, free);
+            }
+            Term::Ite { cond, then, else_ } => {
+                go(cond, bound, sig, free);
+                go(then, bound, sig, free);
+                go(else_, bound, sig, free);
+            }
+            Term::Quantified { binders, body, .. } => {
+                let n = bound.len();
+                bound.extend(binders.iter().map(|b| b.name.clone()));
+                go(body, bound, sig, free);
+                bound.truncate(n);
+            }
+        }
+    }
+
+    let mut free = vec![];
+    go(&t, &mut bound.to_vec(), sig, &mut free);
+    Term::forall(
+        free.iter().map(|name| Binder::new(name, Sort::unknown())),
+        t,
+    )
+}
+
+/// The frame condition `forall x0, ... . r'(x0, ...) = r(x0, ...)` stating that
+/// the relation `r` is unchanged.
+fn unchanged(r: &RelationDecl) -> Term {
+    let binders = (r.args.iter().enumerate())
+        .map(|(i, sort)| Binder::new(&format!("x{i}"), sort))
+        .collect_vec();
+    let current = if binders.is_empty() {
+        Term::id(&r.name)
+    } else {
+        Term::app(&r.name, 0, binders.iter().map(|b| Term::id(&b.name)))
+    };
+    Term::forall(binders, Term::equals(Term::prime(&current), &current))
+}
+
+/// Translate parsed declarations to a fly module.
+fn translate(decls: Vec<Decl>) -> Result<Module, ImportError> {
+    let mut signature = Signature {
+        sorts: vec![],
+        relations: vec![],
+    };
+    for decl in &decls {
+        match decl {
+            Decl::Sort(s) => signature.sorts.push(s.clone()),
+            Decl::Symbol(r) => signature.relations.push(r.clone()),
+            _ => {}
+        }
+    }
+
+    let mut axioms = vec![];
+    let mut inits = vec![];
+    let mut transitions = vec![];
+    let mut safeties = vec![];
+    let mut invariants = vec![];
+    for decl in decls {
+        match decl {
+            Decl::Axiom(t) => axioms.push(close(t, &[], &signature)),
+            Decl::Init(t) => inits.push(close(t, &[], &signature)),
+            Decl::Transition {
+                name,
+                params,
+                modifies,
+                body,
+            } => {
+                for m in &modifies {
+                    if !signature
+                        .relations
+                        .iter()
+                        .any(|r| r.mutable && r.name == m.x)
+                    {
+                        return Err(ImportError::NotMutable {
+                            transition: name,
+                            name: m.x.clone(),
+                            span: m.span,
+                        });
+                    }
+                }
+                let param_names = params.iter().map(|b| b.name.clone()).collect_vec();
+                let frame = (signature.relations.iter())
+                    .filter(|r| r.mutable && modifies.iter().all(|m| m.x != r.name))
+                    .map(unchanged);
+                let body = close(body, &param_names, &signature);
+                transitions.push(Term::exists(
+                    params,
+                    Term::and([body].into_iter().chain(frame)),
+                ));
+            }
+            Decl::Safety(t) => safeties.push(Spanned {
+                x: close(t.x, &[], &signature),
+                span: t.span,
+            }),
+            Decl::Invariant(t) => invariants.push(Spanned {
+                x: close(t.x, &[], &signature),
+                span: t.span,
+            }),
+            Decl::Sort(_) | Decl::Symbol(_) | Decl::Ignored => {}
+        }
+    }
+
+    let mut statements = vec![];
+    if !axioms.is_empty() {
+        statements.push(ThmStmt::Assume(Term::always(Term::and(axioms))));
+    }
+    if !inits.is_empty() {
+        statements.push(ThmStmt::Assume(Term::and(inits)));
+    }
+    if !transitions.is_empty() {
+        statements.push(ThmStmt::Assume(Term::always(Term::or(transitions))));
+    }
+    // without safety properties, the invariants themselves are checked
+    let properties = if safeties.is_empty() {
+        &invariants
+    } else {
+        &safeties
+    };
+    if !properties.is_empty() {
+        let assert = Spanned {
+            x: Term::always(Term::and(properties.iter().map(|p| &p.x))),
+            span: properties[0].span,
+        };
+        safeties.extend(invariants);
+        statements.push(ThmStmt::Assert(Proof {
+            assert,
+            invariants: safeties,
+        }));
+    }
+
+    Ok(Module {
+        signature,
+        defs: vec![],
+        statements,
+    })
+}
+
+/// Import a mypyvy program as a fly module. The result still needs to be sort
+/// checked, which also infers the sorts of implicitly quantified variables.
+pub fn parse(s: &str) -> Result<Module, ImportError> {
+    let decls = mypyvy::program(s).map_err(ImportError::Parse)?;
+    translate(decls)
+}
+
+/// Convert an opaque FileId and import error to a readable `Diagnostic`
+pub fn import_error_diagnostic<FileId>(file_id: FileId, e: &ImportError) -> Diagnostic<FileId> {
+    match e {
+        ImportError::Parse(e) => parse_error_diagnostic(file_id, e),
+        ImportError::NotMutable { span, .. } => {
+            let diagnostic = Diagnostic::error().with_message(format!("{e}"));
+            match span {
+                Some(span) => {
+                    diagnostic.with_labels(vec![Label::primary(file_id, span.start..span.end)])
+                }
+                None => diagnostic,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{mypyvy, parse, ImportError};
+    use crate::{parser, sorts::sort_check_module, syntax::*};
+
+    fn clear_spans(m: &mut Module) {
+        for stmt in &mut m.statements {
+            if let ThmStmt::Assert(proof) = stmt {
+                proof.assert.span = None;
+                for inv in &mut proof.invariants {
+                    inv.span = None;
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_mypyvy_precedence() {
+        assert_eq!(
+            mypyvy::expr("!x = y & p | q").unwrap(),
+            parser::term("(!(x = y) & p) | q")
+        );
+        assert_eq!(
+            mypyvy::expr("p -> q -> r <-> s").unwrap(),
+            parser::term("(p -> (q -> r)) <-> s")
+        );
+        assert_eq!(
+            mypyvy::expr("p <-> q -> r | s").unwrap(),
+            parser::term("p <-> (q -> (r | s))")
+        );
+        // <-> is not associative
+        assert_eq!(
+            mypyvy::expr("p <-> q <-> r").unwrap_err().to_string(),
+            "error at 1:12: expected parentheses around a nested <->"
+        );
+        assert_eq!(
+            mypyvy::expr("(p <-> q) <-> r").unwrap(),
+            parser::term("(p <-> q) <-> r")
+        );
+        assert_eq!(
+            mypyvy::expr("& p & forall X. q(X) | r").unwrap(),
+            parser::term("p & (forall X. q(X) | r)")
+        );
+        assert_eq!(
+            mypyvy::expr("distinct(a, b, c)").unwrap(),
+            parser::term("a != b & a != c & b != c")
+        );
+    }
+
+    #[test]
+    fn test_mypyvy_lockserv() {
+        let mut imported = parse(include_str!(
+            "../../temporal-verifier/tests/examples/mypyvy/lockserv.pyv"
+        ))
+        .expect("lockserv.pyv should import");
+        sort_check_module(&mut imported).expect("imported module should sort check");
+        clear_spans(&mut imported);
+
+        // compare against mypyvy's own translation
+        let mut expected = parser::parse(include_str!(
+            "../../temporal-verifier/examples/fol/lockserv.fly"
+        ))
+        .unwrap();
+        sort_check_module(&mut expected).unwrap();
+        clear_spans(&mut expected);
+        assert_eq!(imported, expected);
+    }
+
+    #[test]
+    fn test_mypyvy_errors() {
+        let r = parse(
+            "
+sort node
+immutable relation r(node)
+transition t(n: node)
+  modifies r
+  new(r(n))
+",
+        );
+        assert!(
+            matches!(&r, Err(ImportError::NotMutable { name, .. }) if name == "r"),
+            "unexpected result {r:?}"
+        );
+        assert!(matches!(
+            parse("sort node\nderived relation r(node)"),
+            Err(ImportError::Parse(_))
+        ));
+    }
+}
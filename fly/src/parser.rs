@@ -5,13 +5,43 @@
 
 use crate::syntax::*;
 use codespan_reporting::diagnostic::{Diagnostic, Label};
-use peg::{error::ParseError, str::LineCol};
+use peg::{error::ParseError, str::LineCol, Parse};
+use thiserror::Error;
 
 // TODO(oded): Use smart constructors in this module. In principle, no Term
 // should be constructed directly by a (non-smart) constructor. I expect this
 // will simplify the code, and if there are exceptions then we can revisit the
 // design of the smart consturctors.
 
+/// A top-level item of a module, parsed independently during error recovery
+/// and formatting.
+pub(crate) enum Item {
+    Sort(String),
+    Relation(RelationDecl),
+    Def(Definition),
+    Stmt(ThmStmt),
+}
+
+impl Item {
+    /// The position of this kind of item in the module layout (sorts, then
+    /// relations, then definitions, then statements).
+    fn section(&self) -> usize {
+        match self {
+            Item::Sort(_) => 0,
+            Item::Relation(_) => 1,
+            Item::Def(_) => 2,
+            Item::Stmt(_) => 3,
+        }
+    }
+}
+
+const SECTION_NAMES: [&str; 4] = [
+    "sort declarations",
+    "relation declarations",
+    "definitions",
+    "statements",
+];
+
 peg::parser! {
 
 grammar parser() for str {
@@ -163,6 +193,16 @@ grammar parser() for str {
       pub(super) rule stmt() -> ThmStmt
       = assume_stmt() / assert_stmt()
 
+     // a single top-level item, used for error recovery
+     pub(super) rule item() -> Item
+     = _ i:(s:sort_decl() { Item::Sort(s) } /
+            r:relation_decl() { Item::Relation(r) } /
+            d:def() { Item::Def(d) } /
+            s:stmt() { Item::Stmt(s) }) _
+       { i }
+
+     pub(super) rule blank() = _
+
      rule stmts() -> Vec<ThmStmt>
      = newline_separated(<stmt()>)
 
@@ -194,7 +234,7 @@ grammar parser() for str {
 
 #[cfg(test)]
 mod tests {
-    use super::parser;
+    use super::{parse, parse_recovering, parser, SyntaxError};
     use crate::syntax::*;
 
     fn ident(s: &str) -> String {
@@ -304,6 +344,90 @@ proof {
         }
     }
 
+    #[test]
+    fn test_parse_recovering() {
+        let source = r"sort s
+mutable p(s): bool
+mutable q: bool &
+
+assume forall x:s. !p(x)
+assert always q ! q
+proof {
+    invariant !q
+}
+assert always !q
+proof {
+    invariant !q
+}
+";
+        let (m, errors) = parse_recovering(source);
+        assert_eq!(errors.len(), 2, "expected two syntax errors: {errors:?}");
+        let lines = errors
+            .iter()
+            .map(|e| match e {
+                SyntaxError::Parse(e) => e.location.line,
+                _ => panic!("unexpected error {e}"),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(lines, vec![3, 6]);
+
+        assert_eq!(m.signature.sorts, vec!["s".to_string()]);
+        assert_eq!(m.signature.relations.len(), 1);
+        assert_eq!(m.statements.len(), 2);
+        match &m.statements[1] {
+            ThmStmt::Assert(pf) => {
+                let span = pf.assert.span.unwrap();
+                assert_eq!(&source[span.start..span.end], "assert always !q");
+                let span = pf.invariants[0].span.unwrap();
+                assert_eq!(&source[span.start..span.end], "invariant !q");
+            }
+            _ => panic!("incorrect 2nd statement"),
+        }
+    }
+
+    #[test]
+    fn test_parse_recovering_misplaced() {
+        let source = r"mutable p: bool
+assume !p
+sort s
+";
+        let (m, errors) = parse_recovering(source);
+        assert!(parse(source).is_err());
+        assert_eq!(m.signature.sorts, vec!["s".to_string()]);
+        assert_eq!(
+            errors,
+            vec![SyntaxError::Misplaced {
+                span: Span { start: 26, end: 32 },
+                item: "sort declarations",
+                preceded_by: "statements",
+            }]
+        );
+    }
+
+    #[test]
+    fn test_parse_recovering_matches_parse() {
+        let source = r"# a comment
+sort s
+
+mutable p(s): bool
+immutable c: s
+
+def f(x: s) -> bool {
+  p(x) & x = c
+}
+
+assume forall x:s. !p(x)
+assume always forall x:s. p'(x) <-> p(x) | x = c
+assert always p(c) -> f(c)
+proof {
+    invariant forall x:s. p(x) -> x = c
+}
+";
+        let (m, errors) = parse_recovering(source);
+        assert!(errors.is_empty());
+        assert_eq!(m, parse(source).expect("test module should parse"));
+    }
+
     #[test]
     fn test_quantifiers() {
         term("forall x:t. x = y");
@@ -347,6 +471,11 @@ pub fn term(s: &str) -> Term {
     parser::term(s).expect("test term should parse")
 }
 
+/// Parse a single term, reporting a human-readable error on failure.
+pub fn parse_term(s: &str) -> Result<Term, ParseError<LineCol>> {
+    parser::term(s.trim())
+}
+
 /// Parse a signature.
 pub fn parse_signature(s: &str) -> Signature {
     parser::signature(s.trim()).expect("invalid signature in test")
@@ -370,3 +499,147 @@ pub fn parse_error_diagnostic<FileId>(
         )
         .with_message(format!("expected {}", e.expected))])
 }
+
+/// A syntax error found by [`parse_recovering`].
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum SyntaxError {
+    /// The parser failed at some location, expecting one of a set of tokens.
+    #[error("expected {}", .0.expected)]
+    Parse(ParseError<LineCol>),
+    /// A well-formed item appeared in the wrong part of the module.
+    #[error("{item} must appear before {preceded_by}")]
+    Misplaced {
+        /// The span of the misplaced item
+        span: Span,
+        /// The kind of the misplaced item
+        item: &'static str,
+        /// The kind of item it illegally follows
+        preceded_by: &'static str,
+    },
+}
+
+/// Keywords that start a top-level item, at which the recovering parser
+/// resynchronizes after an error.
+const ITEM_KEYWORDS: [&str; 6] = ["sort", "mutable", "immutable", "assume", "assert", "def"];
+
+/// Split the input into chunks, each starting at a line which begins with one
+/// of [`ITEM_KEYWORDS`]. Returns the start offset of each chunk.
+pub(crate) fn item_starts(s: &str) -> Vec<usize> {
+    let mut starts = vec![0];
+    let mut offset = 0;
+    for line in s.split_inclusive('\n') {
+        let trimmed = line.trim_start();
+        let starts_item = ITEM_KEYWORDS.iter().any(|kw| {
+            trimmed
+                .strip_prefix(kw)
+                .is_some_and(|rest| !rest.starts_with(|c: char| c.is_alphanumeric() || c == '_'))
+        });
+        if starts_item && offset > 0 {
+            starts.push(offset);
+        }
+        offset += line.len();
+    }
+    starts
+}
+
+/// Parse a single top-level item, surrounded by whitespace and comments.
+pub(crate) fn parse_item(s: &str) -> Result<Item, ParseError<LineCol>> {
+    parser::item(s)
+}
+
+fn shift_span(span: &mut Option<Span>, start: usize) {
+    if let Some(span) = span {
+        span.start += start;
+        span.end += start;
+    }
+}
+
+fn shift_stmt_spans(stmt: &mut ThmStmt, start: usize) {
+    if let ThmStmt::Assert(proof) = stmt {
+        shift_span(&mut proof.assert.span, start);
+        for inv in &mut proof.invariants {
+            shift_span(&mut inv.span, start);
+        }
+    }
+}
+
+/// Parse a fly module, recovering from syntax errors.
+///
+/// After an error the parser resynchronizes at the next line starting a
+/// top-level item (`sort`, `mutable`, `immutable`, `assume`, `assert`, or
+/// `def`). Returns a module built from all the well-formed items together with
+/// every syntax error encountered, in source order. If the errors are empty the
+/// returned module is the same as the one returned by [`parse`].
+pub fn parse_recovering(s: &str) -> (Module, Vec<SyntaxError>) {
+    let mut module = Module {
+        signature: Signature {
+            sorts: vec![],
+            relations: vec![],
+        },
+        defs: vec![],
+        statements: vec![],
+    };
+    let mut errors = vec![];
+    let mut last_section = 0;
+
+    let starts = item_starts(s);
+    let ends = starts.iter().skip(1).copied().chain([s.len()]);
+    for (start, end) in starts.iter().copied().zip(ends) {
+        let chunk = &s[start..end];
+        if parser::blank(chunk).is_ok() {
+            continue;
+        }
+        let item = match parser::item(chunk) {
+            Ok(item) => item,
+            Err(e) => {
+                // errors at the end of the chunk are reported right after its
+                // last token, rather than at the start of the next item
+                let offset = e.location.offset.min(chunk.trim_end().len());
+                errors.push(SyntaxError::Parse(ParseError {
+                    location: s.position_repr(start + offset),
+                    expected: e.expected,
+                }));
+                continue;
+            }
+        };
+
+        let section = item.section();
+        if section < last_section {
+            let leading = chunk.len() - chunk.trim_start().len();
+            let trailing = chunk.len() - chunk.trim_end().len();
+            errors.push(SyntaxError::Misplaced {
+                span: Span {
+                    start: start + leading,
+                    end: end - trailing,
+                },
+                item: SECTION_NAMES[section],
+                preceded_by: SECTION_NAMES[last_section],
+            });
+        }
+        last_section = last_section.max(section);
+
+        match item {
+            Item::Sort(sort) => module.signature.sorts.push(sort),
+            Item::Relation(relation) => module.signature.relations.push(relation),
+            Item::Def(def) => module.defs.push(def),
+            Item::Stmt(mut stmt) => {
+                shift_stmt_spans(&mut stmt, start);
+                module.statements.push(stmt);
+            }
+        }
+    }
+
+    (module, errors)
+}
+
+/// Convert an opaque FileId and a [`SyntaxError`] to a readable `Diagnostic`
+pub fn syntax_error_diagnostic<FileId>(file_id: FileId, e: &SyntaxError) -> Diagnostic<FileId> {
+    match e {
+        SyntaxError::Parse(e) => parse_error_diagnostic(file_id, e),
+        SyntaxError::Misplaced { span, .. } => Diagnostic::error()
+            .with_message("could not parse file")
+            .with_labels(vec![
+                Label::primary(file_id, span.start..span.end).with_message(format!("{e}"))
+            ]),
+    }
+}
@@ -7,7 +7,7 @@ use std::fmt;
 
 use crate::syntax::*;
 
-fn precedence(t: &Term) -> usize {
+pub(crate) fn precedence(t: &Term) -> usize {
     use crate::syntax::{BinOp::*, NOp::*, Quantifier::*, Term::*, UOp::*};
 
     match t {
@@ -29,7 +29,7 @@ fn precedence(t: &Term) -> usize {
     }
 }
 
-fn parens(add_parens: bool, s: String) -> String {
+pub(crate) fn parens(add_parens: bool, s: String) -> String {
     if add_parens {
         format!("({s})")
     } else {
@@ -37,15 +37,15 @@ fn parens(add_parens: bool, s: String) -> String {
     }
 }
 
-fn right_associative(op: &BinOp) -> bool {
+pub(crate) fn right_associative(op: &BinOp) -> bool {
     matches!(op, BinOp::Implies | BinOp::Since | BinOp::Until)
 }
 
-fn left_associative(_op: &BinOp) -> bool {
+pub(crate) fn left_associative(_op: &BinOp) -> bool {
     false
 }
 
-fn binder(b: &Binder) -> String {
+pub(crate) fn binder(b: &Binder) -> String {
     format!("{}:{}", b.name, sort(&b.sort))
 }
 
@@ -199,14 +199,14 @@ mod tests {
     }
 }
 
-fn sort(s: &Sort) -> String {
+pub(crate) fn sort(s: &Sort) -> String {
     match s {
         Sort::Bool => "bool".to_string(),
         Sort::Uninterpreted(i) => i.to_string(),
     }
 }
 
-fn relation_decl(decl: &RelationDecl) -> String {
+pub(crate) fn relation_decl(decl: &RelationDecl) -> String {
     let name = decl.name.to_string();
     let args = if decl.args.is_empty() {
         "".to_string()
@@ -240,7 +240,7 @@ fn signature(sig: &Signature) -> String {
     format!("{sorts}{relations}")
 }
 
-fn def_binder(binder: &Binder) -> String {
+pub(crate) fn def_binder(binder: &Binder) -> String {
     format!("{}: {}", &binder.name, sort(&binder.sort))
 }
 
@@ -5,7 +5,7 @@
 
 use crate::{ouritertools::OurItertools, syntax::*};
 use itertools::Itertools;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use std::borrow::Borrow;
 
 use BinOp::*;
@@ -26,7 +26,7 @@ pub type Assignment = im::HashMap<String, Element>;
 
 /// An interpretation gives the complete value of a function for a
 /// finite-cardinality universe.
-#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Interpretation {
     /// The type of this function, given as the cardinality first of all the
     /// inputs and finally the cardinality of the output.
@@ -76,7 +76,7 @@ impl Interpretation {
 /// A Model is a finite structure that includes a Signature, a Universe for the
 /// sorts in the signature, and an interpretation of all the functions in the
 /// signature.
-#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct Model {
     // TODO(oded): to optimize, make things Rc<_> (_ = Signature, Universe, and Interpretation)
     /// The signature this model is for
@@ -93,6 +93,20 @@ pub fn sort_check_module(module: &mut Module) -> Result<(), (SortError, Option<S
         .sort_check_module(module)
 }
 
+/// Sort check a module, reporting all errors rather than just the first one.
+///
+/// Each definition and each term of a statement (an assumption, invariant, or
+/// assertion) is checked independently, so an error in one of them does not
+/// prevent the others from being checked. This is intended for reporting as
+/// many errors as possible to the user; the module may be left only partially
+/// annotated.
+pub fn sort_check_module_recovering(module: &mut Module) -> Vec<(SortError, Option<Span>)> {
+    match Scope::new(&module.signature.clone()) {
+        Ok(mut scope) => scope.sort_check_module_recovering(module),
+        Err(e) => vec![(e, None)],
+    }
+}
+
 /// Sort check the term in the given signature, including inferring sorts for bound variables.
 pub fn sort_check_term(signature: &Signature, term: &mut Term) -> Result<Sort, SortError> {
     Scope::new(signature)?.sort_check_term(term)
@@ -458,6 +472,32 @@ impl Scope<'_> {
         Ok(())
     }
 
+    /// Sort check a [ThmStmt] in the current scope, checking each of its terms
+    /// independently and collecting their errors in `errors`.
+    fn sort_check_statement_recovering(
+        &mut self,
+        statement: &mut ThmStmt,
+        errors: &mut Vec<(SortError, Option<Span>)>,
+    ) {
+        match statement {
+            ThmStmt::Assume(term) => {
+                if let Err(e) = self.sort_check_term_bool(term) {
+                    errors.push((e, None));
+                }
+            }
+            ThmStmt::Assert(proof) => {
+                for invariant in &mut proof.invariants {
+                    if let Err(e) = self.sort_check_term_bool(&mut invariant.x) {
+                        errors.push((e, invariant.span));
+                    }
+                }
+                if let Err(e) = self.sort_check_term_bool(&mut proof.assert.x) {
+                    errors.push((e, proof.assert.span));
+                }
+            }
+        }
+    }
+
     /// Sort check all the [ThmStmt]s in the current scope.
     pub fn sort_check_statements(
         &mut self,
@@ -485,6 +525,32 @@ impl Scope<'_> {
         Ok(())
     }
 
+    /// Sort check the [Module] in the current scope, collecting all errors.
+    pub fn sort_check_module_recovering(
+        &mut self,
+        module: &mut Module,
+    ) -> Vec<(SortError, Option<Span>)> {
+        assert!(module.signature == *self.signature);
+
+        let mut errors = vec![];
+        for def in &mut module.defs {
+            if let Err(e) = self.sort_check_definition(def) {
+                errors.push((e, None));
+                // bring the definition into scope anyway, to avoid spurious
+                // errors about unknown functions later on
+                let _ = self.add_name_internal(
+                    def.name.clone(),
+                    RelationOrIndividual::definition(def),
+                    ShadowingConstraint::Disallow,
+                );
+            }
+        }
+        for statement in &mut module.statements {
+            self.sort_check_statement_recovering(statement, &mut errors);
+        }
+        errors
+    }
+
     fn get(&self, name: &str) -> Option<&RelationOrIndividual> {
         self.bound_names.get(name)
     }
@@ -6,13 +6,13 @@
 use itertools::Itertools;
 use std::fmt;
 
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
 use crate::ouritertools::OurItertools;
 
 /// A Sort represents a collection of values, which can be the built-in boolean
 /// sort or a named sort (coming from a Signature).
-#[derive(PartialEq, Eq, Clone, Debug, Hash, Serialize, PartialOrd, Ord)]
+#[derive(PartialEq, Eq, Clone, Debug, Hash, Serialize, Deserialize, PartialOrd, Ord)]
 pub enum Sort {
     /// Boolean sort
     Bool,
@@ -66,7 +66,7 @@ impl fmt::Display for Sort {
 }
 
 /// A binder is a variable name and a sort (used e.g. for a quantifier)
-#[derive(PartialEq, Eq, Clone, Debug, Hash, PartialOrd, Ord)]
+#[derive(PartialEq, Eq, Clone, Debug, Hash, PartialOrd, Ord, Serialize, Deserialize)]
 pub struct Binder {
     /// Bound name
     pub name: String,
@@ -88,7 +88,7 @@ impl Binder {
 }
 
 /// Unary operators
-#[derive(PartialEq, Eq, Clone, Copy, Debug, Hash, PartialOrd, Ord)]
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Hash, PartialOrd, Ord, Serialize, Deserialize)]
 pub enum UOp {
     /// Boolean negation
     Not,
@@ -106,7 +106,7 @@ pub enum UOp {
 
 /// Binary operators
 #[allow(missing_docs)]
-#[derive(PartialEq, Eq, Clone, Copy, Debug, Hash, PartialOrd, Ord)]
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Hash, PartialOrd, Ord, Serialize, Deserialize)]
 pub enum BinOp {
     Equals,
     NotEquals,
@@ -120,7 +120,7 @@ pub enum BinOp {
 
 /// N-ary logical operators
 #[allow(missing_docs)]
-#[derive(PartialEq, Eq, Clone, Copy, Debug, Hash, PartialOrd, Ord)]
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Hash, PartialOrd, Ord, Serialize, Deserialize)]
 pub enum NOp {
     And,
     Or,
@@ -128,7 +128,7 @@ pub enum NOp {
 
 /// A kind of quantifier (forall or exists)
 #[allow(missing_docs)]
-#[derive(PartialEq, Eq, Clone, Copy, Debug, Hash, PartialOrd, Ord)]
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Hash, PartialOrd, Ord, Serialize, Deserialize)]
 pub enum Quantifier {
     Forall,
     Exists,
@@ -148,7 +148,7 @@ pub enum Quantifier {
 ///
 /// The temporal operators supported are: Prime, Next, Prev, Until,
 /// Since, Always, Eventually (see [`UOp`] and [`BinOp`]).
-#[derive(PartialEq, Eq, Clone, Debug, Hash, PartialOrd, Ord)]
+#[derive(PartialEq, Eq, Clone, Debug, Hash, PartialOrd, Ord, Serialize, Deserialize)]
 pub enum Term {
     /// A constant true or false
     Literal(bool),
@@ -528,7 +528,7 @@ impl Term {
 // TODO(oded): rename Relation to Function
 
 /// The declaration of a single function as part of a Signature
-#[derive(PartialEq, Eq, Clone, Debug, Serialize)]
+#[derive(PartialEq, Eq, Clone, Debug, Serialize, Deserialize)]
 pub struct RelationDecl {
     /// If false, the relation is immutable with respect to time
     pub mutable: bool,
@@ -543,7 +543,7 @@ pub struct RelationDecl {
 /// A Signature defines a state space for an LTL Term, consisting of some number
 /// of uninterpreted sorts and declarations for functions using those sorts (or
 /// the built-in boolean sort).
-#[derive(PartialEq, Eq, Clone, Debug, Serialize)]
+#[derive(PartialEq, Eq, Clone, Debug, Serialize, Deserialize)]
 pub struct Signature {
     /// Names of uninterpreted sorts
     pub sorts: Vec<String>,
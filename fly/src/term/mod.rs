@@ -6,4 +6,5 @@
 pub mod cnf;
 pub mod fo;
 pub mod prime;
+pub mod simplify;
 pub mod subst;
@@ -0,0 +1,368 @@
+// Copyright 2022-2023 VMware, Inc.
+// SPDX-License-Identifier: BSD-2-Clause
+
+//! Rewrite quantified lemmas (such as inferred invariants) into a more readable,
+//! logically equivalent form, closer to how invariants are written by hand.
+//!
+//! For example, `forall node_1:node, node_2:node. !p(node_1) | !q(node_2) | node_1 = node_2`
+//! becomes `forall N1:node, N2:node. p(N1) & q(N2) -> N1 = N2`.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::syntax::{BinOp, Binder, NOp, Quantifier, Signature, Sort, Term, UOp};
+
+/// Split a term into its prefix of quantifiers and its body.
+fn split_prefix(term: &Term) -> (Vec<(Quantifier, Vec<Binder>)>, &Term) {
+    let mut prefix = vec![];
+    let mut body = term;
+    while let Term::Quantified {
+        quantifier,
+        binders,
+        body: inner,
+    } = body
+    {
+        prefix.push((*quantifier, binders.clone()));
+        body = inner;
+    }
+    (prefix, body)
+}
+
+/// Quantify the body with the given prefix. Consecutive quantifiers of the same kind
+/// are merged by [`Term::forall`] and [`Term::exists`] (see `Term::quantify`).
+fn with_prefix(prefix: Vec<(Quantifier, Vec<Binder>)>, body: Term) -> Term {
+    prefix
+        .into_iter()
+        .rev()
+        .fold(body, |body, (quantifier, binders)| match quantifier {
+            Quantifier::Forall => Term::forall(binders, body),
+            Quantifier::Exists => Term::exists(binders, body),
+        })
+}
+
+/// Return the disjuncts of the body of a lemma, under its quantifier prefix.
+pub fn disjuncts(term: &Term) -> Vec<Term> {
+    match split_prefix(term).1 {
+        Term::NAryOp(NOp::Or, ts) => ts.clone(),
+        body => vec![body.clone()],
+    }
+}
+
+/// Remove the disjunct at the given index (see [`disjuncts`]) from the body of a lemma,
+/// which results in a stronger lemma.
+pub fn remove_disjunct(term: &Term, index: usize) -> Term {
+    let (prefix, _) = split_prefix(term);
+    let mut ts = disjuncts(term);
+    ts.remove(index);
+    with_prefix(prefix, Term::or(ts))
+}
+
+/// If the term is a negative literal, return the atom it negates.
+fn negated_atom(term: &Term) -> Option<Term> {
+    match term {
+        Term::UnaryOp(UOp::Not, t) => Some(*t.clone()),
+        Term::BinOp(BinOp::NotEquals, lhs, rhs) => {
+            Some(Term::BinOp(BinOp::Equals, lhs.clone(), rhs.clone()))
+        }
+        _ => None,
+    }
+}
+
+/// Turn a disjunction with negative literals into an implication,
+/// e.g., `!p | !q | r` into `p & q -> r`, and `!p | !q` into `!(p & q)`.
+fn implication(body: &Term) -> Term {
+    let Term::NAryOp(NOp::Or, ts) = body else {
+        return body.clone();
+    };
+    let (negative, positive): (Vec<_>, Vec<_>) = ts.iter().partition(|t| negated_atom(t).is_some());
+    let hyps = negative.iter().map(|t| negated_atom(t).unwrap());
+    match (negative.len(), positive.len()) {
+        (0, _) | (1, 0) => body.clone(),
+        (_, 0) => Term::negate(Term::and(hyps)),
+        _ => Term::implies(Term::and(hyps), Term::or(positive.into_iter().cloned())),
+    }
+}
+
+/// Collect all identifiers in the term: variables, bound names, and applied functions.
+fn names(term: &Term, names_acc: &mut HashSet<String>) {
+    match term {
+        Term::Literal(_) => (),
+        Term::Id(name) => {
+            names_acc.insert(name.clone());
+        }
+        Term::App(f, _, args) => {
+            names_acc.insert(f.clone());
+            args.iter().for_each(|t| names(t, names_acc));
+        }
+        Term::UnaryOp(_, t) => names(t, names_acc),
+        Term::BinOp(_, lhs, rhs) => {
+            names(lhs, names_acc);
+            names(rhs, names_acc);
+        }
+        Term::NAryOp(_, ts) => ts.iter().for_each(|t| names(t, names_acc)),
+        Term::Ite { cond, then, else_ } => {
+            names(cond, names_acc);
+            names(then, names_acc);
+            names(else_, names_acc);
+        }
+        Term::Quantified { binders, body, .. } => {
+            names_acc.extend(binders.iter().map(|b| b.name.clone()));
+            names(body, names_acc);
+        }
+    }
+}
+
+/// Rename the free variables of the term according to the given renaming.
+fn rename(term: &Term, renaming: &HashMap<String, String>) -> Term {
+    match term {
+        Term::Literal(_) => term.clone(),
+        Term::Id(name) => Term::Id(renaming.get(name).unwrap_or(name).clone()),
+        Term::App(f, p, args) => Term::App(
+            f.clone(),
+            *p,
+            args.iter().map(|t| rename(t, renaming)).collect(),
+        ),
+        Term::UnaryOp(op, t) => Term::UnaryOp(*op, Box::new(rename(t, renaming))),
+        Term::BinOp(op, lhs, rhs) => Term::BinOp(
+            *op,
+            Box::new(rename(lhs, renaming)),
+            Box::new(rename(rhs, renaming)),
+        ),
+        Term::NAryOp(op, ts) => Term::NAryOp(*op, ts.iter().map(|t| rename(t, renaming)).collect()),
+        Term::Ite { cond, then, else_ } => Term::Ite {
+            cond: Box::new(rename(cond, renaming)),
+            then: Box::new(rename(then, renaming)),
+            else_: Box::new(rename(else_, renaming)),
+        },
+        Term::Quantified {
+            quantifier,
+            binders,
+            body,
+        } => {
+            // the binders shadow the renamed variables
+            let mut renaming = renaming.clone();
+            for binder in binders {
+                renaming.remove(&binder.name);
+            }
+            Term::Quantified {
+                quantifier: *quantifier,
+                binders: binders.clone(),
+                body: Box::new(rename(body, &renaming)),
+            }
+        }
+    }
+}
+
+fn sort_name(sort: &Sort) -> &str {
+    match sort {
+        Sort::Bool => "bool",
+        Sort::Uninterpreted(name) => name,
+    }
+}
+
+/// Name the variables of the prefix after their sorts, as in `forall N1:node, N2:node, V:value`.
+/// The first letter of the sort is used, unless several sorts start with the same letter,
+/// in which case the capitalized sort name is used. Names used in the body are avoided.
+fn sort_renaming(
+    prefix: &[(Quantifier, Vec<Binder>)],
+    reserved: &HashSet<String>,
+) -> HashMap<String, String> {
+    let binders = prefix.iter().flat_map(|(_, bs)| bs).collect::<Vec<_>>();
+    let mut sorts: Vec<&str> = vec![];
+    for b in &binders {
+        if !sorts.contains(&sort_name(&b.sort)) {
+            sorts.push(sort_name(&b.sort));
+        }
+    }
+    let initial = |sort: &str| sort.chars().next().unwrap().to_ascii_uppercase();
+    let base = |sort: &str| {
+        if sorts.iter().filter(|s| initial(s) == initial(sort)).count() > 1 {
+            let mut chars = sort.chars();
+            let first = chars.next().unwrap().to_ascii_uppercase();
+            std::iter::once(first).chain(chars).collect()
+        } else {
+            initial(sort).to_string()
+        }
+    };
+
+    let mut renaming = HashMap::new();
+    let mut used = reserved.clone();
+    for sort in &sorts {
+        let base = base(sort);
+        let of_sort = binders
+            .iter()
+            .filter(|b| sort_name(&b.sort) == *sort)
+            .collect::<Vec<_>>();
+        let mut index = if of_sort.len() == 1 { 0 } else { 1 };
+        for b in of_sort {
+            let name = loop {
+                let name = match index {
+                    0 => base.clone(),
+                    _ => format!("{base}{index}"),
+                };
+                index += 1;
+                if !used.contains(&name) {
+                    break name;
+                }
+            };
+            used.insert(name.clone());
+            renaming.insert(b.name.clone(), name);
+        }
+    }
+    renaming
+}
+
+/// Rewrite a lemma into a more readable, logically equivalent form: disjunctions with negative
+/// literals become implications, unused quantified variables are dropped, and the quantified
+/// variables are named after their sorts.
+pub fn simplify(term: &Term) -> Term {
+    let (prefix, body) = split_prefix(term);
+    let body = implication(body);
+
+    let mut body_names = HashSet::new();
+    names(&body, &mut body_names);
+    let prefix = prefix
+        .into_iter()
+        .map(|(quantifier, binders)| {
+            let binders = binders
+                .into_iter()
+                .filter(|b| body_names.contains(&b.name))
+                .collect::<Vec<_>>();
+            (quantifier, binders)
+        })
+        .filter(|(_, binders)| !binders.is_empty())
+        .collect::<Vec<_>>();
+
+    let mut reserved = body_names;
+    for (_, binders) in &prefix {
+        for b in binders {
+            reserved.remove(&b.name);
+        }
+    }
+    let renaming = sort_renaming(&prefix, &reserved);
+    let prefix = prefix
+        .into_iter()
+        .map(|(quantifier, binders)| {
+            let binders = binders
+                .into_iter()
+                .map(|b| Binder {
+                    name: renaming[&b.name].clone(),
+                    sort: b.sort,
+                })
+                .collect();
+            (quantifier, binders)
+        })
+        .collect();
+    with_prefix(prefix, rename(&body, &renaming))
+}
+
+/// Order lemmas by the relations they mention, in the order the relations are declared
+/// in the signature, so that lemmas about the same relations are grouped together.
+/// Lemmas mentioning the same relations are ordered by size.
+pub fn order_lemmas(sig: &Signature, lemmas: &mut [Term]) {
+    lemmas.sort_by_cached_key(|lemma| {
+        let mut lemma_names = HashSet::new();
+        names(lemma, &mut lemma_names);
+        let relations = sig
+            .relations
+            .iter()
+            .enumerate()
+            .filter(|(_, r)| lemma_names.contains(&r.name))
+            .map(|(i, _)| i)
+            .collect::<Vec<_>>();
+        (relations, lemma.to_string().len())
+    });
+}
+
+/// Simplify the given lemmas (see [`simplify`]) and order them (see [`order_lemmas`]).
+pub fn simplify_lemmas(sig: &Signature, lemmas: &[Term]) -> Vec<Term> {
+    let mut lemmas = lemmas.iter().map(simplify).collect::<Vec<_>>();
+    order_lemmas(sig, &mut lemmas);
+    lemmas
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::term;
+    use crate::syntax::RelationDecl;
+
+    #[test]
+    fn test_simplify() {
+        assert_eq!(
+            simplify(&term(
+                "forall node_1:node, node_2:node. !p(node_1) | !q(node_2) | node_1 = node_2"
+            )),
+            term("forall N1:node, N2:node. p(N1) & q(N2) -> N1 = N2"),
+        );
+        // unused variables are dropped, and consecutive quantifiers are merged
+        assert_eq!(
+            simplify(&term(
+                "forall node_1:node. forall value_1:value, value_2:value. \
+                 node_1 != node_1 | r(value_1) | (p(node_1) & !q(node_1))"
+            )),
+            term("forall N:node, V:value. N = N -> r(V) | (p(N) & !q(N))"),
+        );
+        assert_eq!(
+            simplify(&term("forall a:node, b:node. !p(a) | !p(b)")),
+            term("forall N1:node, N2:node. !(p(N1) & p(N2))"),
+        );
+        // sorts with the same initial, and names which are already taken
+        assert_eq!(
+            simplify(&term(
+                "exists a:node. forall b:nat. p(a, b) | q(N, Node, Nat)"
+            )),
+            term("exists Node1:node. forall Nat1:nat. p(Node1, Nat1) | q(N, Node, Nat)"),
+        );
+        // a single negative literal is left alone
+        assert_eq!(
+            simplify(&term("forall a:node. !p(a)")),
+            term("forall N:node. !p(N)"),
+        );
+    }
+
+    #[test]
+    fn test_remove_disjunct() {
+        let t = term("forall a:node, b:node. !p(a) | q(b) | a = b");
+        assert_eq!(disjuncts(&t).len(), 3);
+        assert_eq!(
+            remove_disjunct(&t, 1),
+            term("forall a:node, b:node. !p(a) | a = b")
+        );
+        assert_eq!(
+            remove_disjunct(&remove_disjunct(&t, 1), 1),
+            term("forall a:node, b:node. !p(a)")
+        );
+    }
+
+    #[test]
+    fn test_order_lemmas() {
+        let relation = |name: &str| RelationDecl {
+            mutable: true,
+            name: name.to_string(),
+            args: vec![],
+            sort: Sort::Bool,
+        };
+        let sig = Signature {
+            sorts: vec![],
+            relations: vec![relation("p"), relation("q"), relation("r")],
+        };
+        let mut lemmas = vec![
+            term("r"),
+            term("q | r"),
+            term("p | q"),
+            term("p"),
+            term("q"),
+        ];
+        order_lemmas(&sig, &mut lemmas);
+        assert_eq!(
+            lemmas,
+            vec![
+                term("p"),
+                term("p | q"),
+                term("q"),
+                term("q | r"),
+                term("r")
+            ]
+        );
+    }
+}
@@ -10,7 +10,8 @@ use std::{
     time::Instant,
 };
 
-use crate::quant::QuantifierConfig;
+use crate::{events::EventLog, quant::QuantifierConfig};
+use bounded::quant_enum::UniverseBounds;
 use fly::syntax::BinOp;
 use fly::syntax::Term::*;
 use fly::syntax::*;
@@ -31,21 +32,23 @@ pub enum CexResult {
 
 impl CexResult {
     /// Convert into an [`Option`], which either contains a counterexample or [`None`].
+    /// A `Canceled` query has no counterexample, since its answer is no longer needed.
+    /// If the query is `Unknown`, this panics.
     pub fn into_option(self) -> Option<Vec<Model>> {
         match self {
             CexResult::Cex(models) => Some(models),
-            CexResult::UnsatCore(_) => None,
-            _ => panic!("cex result is neither sat or unsat"),
+            CexResult::UnsatCore(_) | CexResult::Canceled => None,
+            CexResult::Unknown(_) => panic!("cex result is neither sat or unsat"),
         }
     }
 
-    /// Return `true` if the [`CexResult`] contains a counterexample, or `false` if it contains an UNSAT-core.
-    /// If the query is either `Canceled` or `Unknown`, this panics.
+    /// Return `true` if the [`CexResult`] contains a counterexample, or `false` if it contains an UNSAT-core
+    /// or the query was `Canceled`. If the query is `Unknown`, this panics.
     pub fn is_cex(&self) -> bool {
         match self {
             CexResult::Cex(_) => true,
-            CexResult::UnsatCore(_) => false,
-            _ => panic!("cex result is neither sat or unsat"),
+            CexResult::UnsatCore(_) | CexResult::Canceled => false,
+            CexResult::Unknown(_) => panic!("cex result is neither sat or unsat"),
         }
     }
 }
@@ -367,17 +370,16 @@ impl FOModule {
         let mut assertions = self.module.axioms.clone();
         assertions.push(Term::not(t));
         loop {
-            match solver
-                .check_sat(&query_conf, &assertions, &core.to_assumptions())
-                .expect("error in solver")
-            {
-                BasicSolverResp::Sat(models) => {
+            match solver.check_sat(&query_conf, &assertions, &core.to_assumptions()) {
+                Ok(BasicSolverResp::Sat(models)) => {
                     if !core.add_counter_model(models[0].clone()) {
                         return CexResult::Cex(models);
                     }
                 }
-                BasicSolverResp::Unsat(core) => return CexResult::UnsatCore(core),
-                BasicSolverResp::Unknown(reason) => return CexResult::Unknown(reason),
+                Ok(BasicSolverResp::Unsat(core)) => return CexResult::UnsatCore(core),
+                Ok(BasicSolverResp::Unknown(reason)) => return CexResult::Unknown(reason),
+                Err(SolverError::Killed) => return CexResult::Canceled,
+                Err(e) => panic!("error in solver: {e}"),
             }
         }
     }
@@ -591,16 +593,20 @@ impl FOModule {
     }
 }
 
+#[derive(Clone)]
 pub enum QfBody {
     CNF,
     PDnf,
     PDnfNaive,
 }
 
+#[derive(Clone)]
 pub struct InferenceConfig {
     pub fname: String,
 
     pub fallback: bool,
+    /// Answer the solver queries in this many worker processes, rather than in-process
+    pub workers: Option<usize>,
     pub cfg: QuantifierConfig,
     pub qf_body: QfBody,
 
@@ -624,10 +630,16 @@ pub struct InferenceConfig {
     pub extend_width: Option<usize>,
     pub extend_depth: Option<usize>,
 
+    pub sample_bound: Option<UniverseBounds>,
+    pub sample_depth: Option<usize>,
+
     pub until_safe: bool,
     pub abort_unsafe: bool,
+    pub auto_quant: bool,
     pub no_search: bool,
     pub growth_factor: Option<usize>,
+
+    pub events: EventLog,
 }
 
 pub fn parse_quantifier(
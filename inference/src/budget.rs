@@ -0,0 +1,171 @@
+// Copyright 2022-2023 VMware, Inc.
+// SPDX-License-Identifier: BSD-2-Clause
+
+//! Time and memory budgets for inference runs. Once a run exhausts its budget,
+//! its in-flight solver queries are canceled, and the run reports what it has
+//! learned so far instead of running to completion.
+
+use std::{
+    sync::{Condvar, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use solver::basics::BasicSolverCanceler;
+
+/// How often a run is checked against its budget.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Limits on the resources used by an inference run.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Budget {
+    /// The maximal running time
+    pub time: Option<Duration>,
+    /// The maximal memory usage of the process, in bytes
+    pub memory: Option<usize>,
+}
+
+impl Budget {
+    /// Return whether the budget has no limits at all.
+    pub fn is_unlimited(&self) -> bool {
+        self.time.is_none() && self.memory.is_none()
+    }
+
+    /// Return a description of the exhausted limit, if any, for a run started at `start`.
+    fn exhausted(&self, start: Instant) -> Option<String> {
+        if let Some(time) = self.time {
+            if start.elapsed() >= time {
+                return Some(format!("time limit of {:.1}s", time.as_secs_f64()));
+            }
+        }
+        if let Some(memory) = self.memory {
+            if memory_stats::memory_stats().is_some_and(|usage| usage.physical_mem >= memory) {
+                return Some(format!("memory limit of {} MiB", memory / (1024 * 1024)));
+            }
+        }
+        None
+    }
+
+    /// Run `f` while a watchdog thread cancels `canceler` once the budget is exhausted.
+    /// Return the result of `f`, and whether the budget was exhausted during the run.
+    ///
+    /// Note that `f` is responsible for noticing the cancellation and returning early,
+    /// typically because its queries are made through `canceler`.
+    pub fn enforce<C: BasicSolverCanceler, T>(
+        &self,
+        canceler: &C,
+        f: impl FnOnce() -> T,
+    ) -> (T, bool) {
+        if self.is_unlimited() {
+            return (f(), false);
+        }
+
+        let start = Instant::now();
+        let done = Mutex::new(false);
+        let wake = Condvar::new();
+        thread::scope(|s| {
+            let watchdog = s.spawn(|| {
+                let mut done = done.lock().unwrap();
+                while !*done {
+                    if let Some(limit) = self.exhausted(start) {
+                        println!("Reached the {limit}, canceling...");
+                        canceler.cancel();
+                        return true;
+                    }
+                    done = wake.wait_timeout(done, POLL_INTERVAL).unwrap().0;
+                }
+                false
+            });
+
+            // stop the watchdog even if `f` panics, since the scope waits for it
+            let res = {
+                let _done = Done {
+                    done: &done,
+                    wake: &wake,
+                };
+                f()
+            };
+            (res, watchdog.join().unwrap())
+        })
+    }
+}
+
+/// Tells the watchdog of [`Budget::enforce`] that the run is over when dropped.
+struct Done<'a> {
+    done: &'a Mutex<bool>,
+    wake: &'a Condvar,
+}
+
+impl Drop for Done<'_> {
+    fn drop(&mut self) {
+        *self
+            .done
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = true;
+        self.wake.notify_one();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solver::basics::SolverCancelers;
+    use std::{
+        panic::{self, AssertUnwindSafe},
+        sync::atomic::{AtomicBool, Ordering},
+    };
+
+    struct FlagCanceler<'a>(&'a AtomicBool);
+
+    impl BasicSolverCanceler for FlagCanceler<'_> {
+        fn cancel(&self) {
+            self.0.store(true, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn budget_time_limit() {
+        let budget = Budget {
+            time: Some(Duration::from_millis(200)),
+            memory: None,
+        };
+        let flag = AtomicBool::new(false);
+        let cancelers = SolverCancelers::new();
+        assert!(cancelers.add_canceler(FlagCanceler(&flag)));
+
+        // a run that waits for its cancellation
+        let (iterations, exhausted) = budget.enforce(&cancelers, || {
+            let mut iterations = 0;
+            while !cancelers.is_canceled() {
+                thread::sleep(Duration::from_millis(10));
+                iterations += 1;
+            }
+            iterations
+        });
+        assert!(exhausted);
+        assert!(iterations > 0);
+        assert!(flag.load(Ordering::SeqCst));
+
+        // a run that finishes in time
+        let cancelers: SolverCancelers<FlagCanceler> = SolverCancelers::new();
+        let (res, exhausted) = budget.enforce(&cancelers, || 1);
+        assert_eq!(res, 1);
+        assert!(!exhausted);
+        assert!(!cancelers.is_canceled());
+    }
+
+    #[test]
+    fn budget_panic() {
+        // the memory limit is never reached, so only the end of the run stops the watchdog
+        let budget = Budget {
+            time: None,
+            memory: Some(usize::MAX),
+        };
+        let cancelers: SolverCancelers<FlagCanceler> = SolverCancelers::new();
+        let res = panic::catch_unwind(AssertUnwindSafe(|| {
+            budget.enforce(&cancelers, || panic!("the run failed"))
+        }));
+        assert!(res.is_err());
+        assert!(!cancelers.is_canceled());
+    }
+}
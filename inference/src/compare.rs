@@ -0,0 +1,231 @@
+// Copyright 2022-2023 VMware, Inc.
+// SPDX-License-Identifier: BSD-2-Clause
+
+//! Compare two invariants (typically an inferred one and a handwritten one), explaining
+//! how each lemma of one relates to the lemmas of the other.
+
+use itertools::Itertools;
+use rayon::prelude::*;
+
+use crate::basics::{CexResult, FOModule};
+use fly::{
+    semantics::Model,
+    syntax::{Module, Term},
+};
+use solver::basics::BasicSolver;
+
+/// How a lemma relates to the lemmas of the other side of a comparison.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Relation {
+    /// The lemma is equivalent to the lemma at this index of the other side.
+    Equivalent(usize),
+    /// The lemma is implied by the other side. It is strictly stronger
+    /// than the lemmas at the given indices of the other side.
+    Implied { stronger_than: Vec<usize> },
+    /// The lemma is not implied by the other side: the model satisfies the other side
+    /// but violates the lemma. It is strictly stronger than the lemmas at the given
+    /// indices of the other side.
+    NotImplied {
+        stronger_than: Vec<usize>,
+        model: Model,
+    },
+    /// The solver couldn't determine whether the lemma is implied by the other side.
+    Unknown(String),
+}
+
+impl Relation {
+    /// Return whether the lemma is known to be implied by the other side.
+    pub fn is_implied(&self) -> bool {
+        matches!(self, Relation::Equivalent(_) | Relation::Implied { .. })
+    }
+}
+
+/// The relation of each inferred and handwritten lemma to the other side.
+pub struct Comparison {
+    pub inferred: Vec<Relation>,
+    pub handwritten: Vec<Relation>,
+}
+
+/// Determine the relation of a lemma to the other side, given whether the other side
+/// implies it, and which lemmas of the other side it implies and is implied by.
+fn classify(implied: CexResult, implies: &[bool], implied_by: &[bool]) -> Relation {
+    if let Some(j) = (0..implies.len()).find(|&j| implies[j] && implied_by[j]) {
+        return Relation::Equivalent(j);
+    }
+    let stronger_than = (0..implies.len())
+        .filter(|&j| implies[j] && !implied_by[j])
+        .collect_vec();
+    match implied {
+        CexResult::UnsatCore(_) => Relation::Implied { stronger_than },
+        CexResult::Cex(mut models) => Relation::NotImplied {
+            stronger_than,
+            model: models.swap_remove(0),
+        },
+        CexResult::Unknown(reason) => Relation::Unknown(reason),
+        CexResult::Canceled => Relation::Unknown("canceled".to_string()),
+    }
+}
+
+/// Compare the inferred and handwritten invariants of the given module. All queries
+/// (each lemma against the other side, and each pair of lemmas) are run in parallel.
+pub fn compare<S: BasicSolver>(
+    m: &Module,
+    solver: &S,
+    inferred: &[Term],
+    handwritten: &[Term],
+) -> Comparison {
+    let fo = FOModule::new(m, false, false, false);
+    let implies = |hyp: &Term, t: &Term| {
+        hyp == t
+            || matches!(
+                fo.implication_cex(solver, std::slice::from_ref(hyp), t),
+                CexResult::UnsatCore(_)
+            )
+    };
+
+    // inferred[i] => handwritten[j], and handwritten[j] => inferred[i]
+    let pairs = (0..inferred.len())
+        .cartesian_product(0..handwritten.len())
+        .collect_vec()
+        .into_par_iter()
+        .map(|(i, j)| {
+            (
+                implies(&inferred[i], &handwritten[j]),
+                implies(&handwritten[j], &inferred[i]),
+            )
+        })
+        .collect::<Vec<_>>();
+    let forward = |i: usize, j: usize| pairs[i * handwritten.len() + j].0;
+    let backward = |i: usize, j: usize| pairs[i * handwritten.len() + j].1;
+
+    let inferred_relations = inferred
+        .par_iter()
+        .enumerate()
+        .map(|(i, lemma)| {
+            let implies = (0..handwritten.len()).map(|j| forward(i, j)).collect_vec();
+            let implied_by = (0..handwritten.len()).map(|j| backward(i, j)).collect_vec();
+            let implied = fo.implication_cex(solver, handwritten, lemma);
+            classify(implied, &implies, &implied_by)
+        })
+        .collect();
+    let handwritten_relations = handwritten
+        .par_iter()
+        .enumerate()
+        .map(|(j, lemma)| {
+            let implies = (0..inferred.len()).map(|i| backward(i, j)).collect_vec();
+            let implied_by = (0..inferred.len()).map(|i| forward(i, j)).collect_vec();
+            let implied = fo.implication_cex(solver, inferred, lemma);
+            classify(implied, &implies, &implied_by)
+        })
+        .collect();
+
+    Comparison {
+        inferred: inferred_relations,
+        handwritten: handwritten_relations,
+    }
+}
+
+impl Comparison {
+    /// Print the relation of each lemma to the other side, and a summary. Distinguishing
+    /// models are printed for the handwritten lemmas which the inferred invariant doesn't imply.
+    pub fn report(&self, inferred: &[Term], handwritten: &[Term]) {
+        let indices = |side: &str, v: &[usize]| {
+            format!(
+                "{side} lemma{} {}",
+                if v.len() == 1 { "" } else { "s" },
+                v.iter().map(|j| format!("#{}", j + 1)).join(", ")
+            )
+        };
+        let print_side = |name: &str, other: &str, lemmas: &[Term], relations: &[Relation]| {
+            println!("# {name} lemmas");
+            for (i, (lemma, relation)) in lemmas.iter().zip(relations).enumerate() {
+                println!("#{} invariant {lemma}", i + 1);
+                let stronger = |stronger_than: &[usize]| match stronger_than {
+                    [] => String::new(),
+                    _ => format!(" (stronger than {})", indices(other, stronger_than)),
+                };
+                match relation {
+                    Relation::Equivalent(j) => {
+                        println!("  equivalent to {}", indices(other, &[*j]));
+                    }
+                    Relation::Implied { stronger_than } => {
+                        println!(
+                            "  implied by the {other} invariant{}",
+                            stronger(stronger_than)
+                        );
+                    }
+                    Relation::NotImplied {
+                        stronger_than,
+                        model,
+                    } => {
+                        println!(
+                            "  NOT implied by the {other} invariant{}",
+                            stronger(stronger_than)
+                        );
+                        if name == "handwritten" {
+                            println!(
+                                "  a state satisfying the {other} invariant but not this lemma:"
+                            );
+                            for line in model.to_string().lines() {
+                                println!("    {line}");
+                            }
+                        }
+                    }
+                    Relation::Unknown(reason) => println!("  unknown ({reason})"),
+                }
+            }
+        };
+
+        print_side("inferred", "handwritten", inferred, &self.inferred);
+        print_side("handwritten", "inferred", handwritten, &self.handwritten);
+
+        let count = |relations: &[Relation], f: fn(&Relation) -> bool| {
+            relations.iter().filter(|r| f(r)).count()
+        };
+        let equivalent = |r: &Relation| matches!(r, Relation::Equivalent(_));
+        println!(
+            "{} / {} inferred lemmas are implied by the handwritten invariant ({} equivalent to a handwritten lemma)",
+            count(&self.inferred, Relation::is_implied),
+            inferred.len(),
+            count(&self.inferred, equivalent),
+        );
+        println!(
+            "{} / {} handwritten lemmas are implied by the inferred invariant ({} equivalent to an inferred lemma)",
+            count(&self.handwritten, Relation::is_implied),
+            handwritten.len(),
+            count(&self.handwritten, equivalent),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_classify() {
+        let unsat = || CexResult::UnsatCore(HashSet::new());
+        assert_eq!(
+            classify(unsat(), &[false, true, true], &[false, false, true]),
+            Relation::Equivalent(2)
+        );
+        assert_eq!(
+            classify(unsat(), &[true, false, true], &[false, false, false]),
+            Relation::Implied {
+                stronger_than: vec![0, 2]
+            }
+        );
+        assert_eq!(
+            classify(CexResult::Unknown("timeout".to_string()), &[], &[]),
+            Relation::Unknown("timeout".to_string())
+        );
+        assert!(
+            !classify(CexResult::Unknown("timeout".to_string()), &[false], &[true]).is_implied()
+        );
+        assert_eq!(
+            classify(CexResult::Canceled, &[], &[]),
+            Relation::Unknown("canceled".to_string())
+        );
+    }
+}
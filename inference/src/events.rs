@@ -0,0 +1,204 @@
+// Copyright 2022-2023 VMware, Inc.
+// SPDX-License-Identifier: BSD-2-Clause
+
+//! A stream of structured progress events of qalpha runs. The events are written
+//! as JSON lines, each with the time since the stream was created, so that runs
+//! can be plotted (e.g., the frame size over time) and compared with each other.
+
+use serde::Serialize;
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+use fly::semantics::Model;
+
+/// The kind of a counterexample found while weakening a frame.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CtiKind {
+    /// An initial state violating the frame
+    Initial,
+    /// A post-state of a transition from the frame which violates it
+    Transition,
+    /// A state sampled by simulating transitions from previous counterexamples
+    Simulated,
+    /// A reachable state sampled by the bounded model checker
+    Sampled,
+}
+
+/// A progress event of a qalpha run.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    /// A new iteration of the qalpha algorithm starts, with larger lemma domains.
+    IterationStart {
+        iteration: usize,
+        domain_size: usize,
+    },
+    /// A lemma domain is activated for the coming iterations.
+    DomainActivated {
+        prefix: String,
+        atoms: usize,
+        lemma_qf: String,
+        size: usize,
+    },
+    /// A counterexample to the frame was found.
+    CtiFound { kind: CtiKind, models: Vec<String> },
+    /// The frame's lemmas were weakened using counterexamples of the given kind.
+    LemmasWeakened {
+        kind: CtiKind,
+        frame_size: usize,
+        weaken_size: usize,
+    },
+    /// The frame was checked to inductively imply the safety assertions.
+    SafetyCheck { safe: bool, frame_size: usize },
+    /// A fixpoint was reached (or not, if the run was canceled first).
+    FixpointReached {
+        reached: bool,
+        safe: bool,
+        size: usize,
+        minimized_size: Option<usize>,
+    },
+}
+
+impl Event {
+    /// Create a [`Event::CtiFound`] event with the given counterexample states.
+    pub fn cti_found(kind: CtiKind, models: &[Model]) -> Self {
+        Event::CtiFound {
+            kind,
+            models: models.iter().map(|model| model.to_string()).collect(),
+        }
+    }
+}
+
+/// A single line in the event stream.
+#[derive(Serialize)]
+struct Record<'a> {
+    time: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    run: Option<usize>,
+    #[serde(flatten)]
+    event: &'a Event,
+}
+
+/// A sink of [`Event`]'s, which is either disabled or writes them to a file.
+/// Clones of an [`EventLog`] write to the same file. If writing to the file fails,
+/// the failure is logged and the file is disabled for all clones.
+#[derive(Clone, Default)]
+pub struct EventLog {
+    writer: Option<(Instant, Arc<Mutex<Option<BufWriter<File>>>>)>,
+    /// The run the events belong to, when several runs share the stream.
+    run: Option<usize>,
+}
+
+impl EventLog {
+    /// Create an event log which writes the events to the file at the given path.
+    pub fn create(path: &Path) -> io::Result<Self> {
+        let file = File::create(path)?;
+        Ok(EventLog {
+            writer: Some((
+                Instant::now(),
+                Arc::new(Mutex::new(Some(BufWriter::new(file)))),
+            )),
+            run: None,
+        })
+    }
+
+    /// Return whether events are written anywhere.
+    pub fn is_enabled(&self) -> bool {
+        self.writer
+            .as_ref()
+            .is_some_and(|(_, writer)| writer.lock().unwrap().is_some())
+    }
+
+    /// Get an event log which writes to the same stream, marking each event with the given run.
+    pub fn for_run(&self, run: usize) -> Self {
+        EventLog {
+            writer: self.writer.clone(),
+            run: Some(run),
+        }
+    }
+
+    /// Write the event built by `event`, if the event log is enabled. The event is only
+    /// built if it is written, since some events (like [`Event::CtiFound`]) are costly to build.
+    pub fn emit(&self, event: impl FnOnce() -> Event) {
+        if let Some((start, writer)) = &self.writer {
+            let mut writer = writer.lock().unwrap();
+            let Some(w) = writer.as_mut() else {
+                return;
+            };
+            let event = event();
+            let record = Record {
+                time: start.elapsed().as_secs_f64(),
+                run: self.run,
+                event: &event,
+            };
+            // flush every line, so that the stream can be followed while the run is in progress
+            let res = serde_json::to_writer(&mut *w, &record)
+                .map_err(io::Error::from)
+                .and_then(|()| writeln!(w))
+                .and_then(|()| w.flush());
+            if let Err(err) = res {
+                log::error!("could not write event, disabling the event log: {err}");
+                *writer = None;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn events_json_lines() {
+        let path = std::env::temp_dir().join(format!("qalpha-events-{}.jsonl", std::process::id()));
+        let events = EventLog::create(&path).unwrap();
+        events.emit(|| Event::IterationStart {
+            iteration: 1,
+            domain_size: 100,
+        });
+        events.for_run(3).emit(|| Event::SafetyCheck {
+            safe: true,
+            frame_size: 7,
+        });
+        assert!(events.is_enabled());
+        assert!(!EventLog::default().is_enabled());
+        EventLog::default().emit(|| unreachable!("disabled event logs don't build events"));
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let lines: Vec<serde_json::Value> = contents
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["event"], "iteration_start");
+        assert_eq!(lines[0]["domain_size"], 100);
+        assert!(lines[0]["time"].is_f64());
+        assert!(lines[0].get("run").is_none());
+        assert_eq!(lines[1]["event"], "safety_check");
+        assert_eq!(lines[1]["run"], 3);
+        assert_eq!(lines[1]["frame_size"], 7);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn events_write_failure() {
+        // every write to /dev/full fails, which disables the log for all of its clones
+        let events = EventLog::create(Path::new("/dev/full")).unwrap();
+        let run = events.for_run(1);
+        assert!(run.is_enabled());
+        events.emit(|| Event::SafetyCheck {
+            safe: true,
+            frame_size: 1,
+        });
+        assert!(!events.is_enabled());
+        assert!(!run.is_enabled());
+        run.emit(|| unreachable!("disabled event logs don't build events"));
+    }
+}
@@ -0,0 +1,443 @@
+// Copyright 2022-2023 VMware, Inc.
+// SPDX-License-Identifier: BSD-2-Clause
+
+//! Learn quantified invariants from the reachable states of a finite instance,
+//! in the style of I4 and DistAI. The reachable states are computed exactly at
+//! small sort bounds, the strongest universally quantified clauses that hold in
+//! all of them become candidate lemmas, and these are then weakened until they
+//! are inductive at any size.
+
+use itertools::Itertools;
+use rayon::prelude::*;
+use thiserror::Error;
+
+use crate::{
+    atoms::{Literal, RestrictedAtoms},
+    hashmap::{HashMap, HashSet},
+    lemma::ids,
+    quant::{QuantifierConfig, QuantifierPrefix},
+    templates::{self, clause_to_term, KnownClauses},
+};
+use bounded::{bdd, checker::*, quant_enum::*};
+use fly::{
+    semantics::{models_to_string, Model},
+    syntax::{Module, Signature, Spanned, Term},
+    term::subst::Substitution,
+    transitions::{extract, DestructuredModule},
+};
+use smtlib::proc::SolverError;
+use solver::{basics::SolverCancelers, conf::SolverConf, SatResp, SmtPid};
+use verify::{
+    error::{AssertionFailure, FailureType, QueryError, SolveError},
+    safety::InvariantAssertion,
+};
+
+/// An error while learning invariants from a finite instance.
+#[derive(Error, Debug)]
+pub enum FiniteError {
+    /// The reachable states could not be computed
+    #[error("{0}")]
+    CheckerError(CheckerError),
+    /// A reachable state violates safety at the given bounds
+    #[error("the module is not safe at the given bounds:\n{}", models_to_string(.0))]
+    Unsafe(Vec<Model>),
+    /// Even the weakest set of lemmas is not enough to prove safety
+    #[error("the lemmas that hold at the given bounds do not prove safety")]
+    NotInductive(SolveError),
+    /// The SMT solver gave up on a query
+    #[error("smt solver returned unknown: {0}")]
+    SolverUnknown(String),
+    /// The budget was exhausted while computing the reachable states,
+    /// or the lemmas that hold in them
+    #[error("the budget was exhausted before the lemmas that hold at the given bounds were found")]
+    ReachableBudgetExhausted,
+    /// The budget was exhausted before the lemmas became inductive. Contains the lemmas
+    /// of the last round, which are not yet inductive, the ones among them which are
+    /// inductive relative to all of them, and the counterexamples of that round
+    #[error("the budget was exhausted before the lemmas became inductive")]
+    BudgetExhausted {
+        lemmas: Vec<Term>,
+        relatively_inductive: Vec<Term>,
+        ctis: Vec<Model>,
+    },
+}
+
+/// Find the strongest universally quantified clauses with at most `clause_size` literals
+/// that hold in all states that are reachable with the given sort bounds. Clauses that are
+/// implied by another one of the clauses are left out.
+///
+/// `canceled` is checked at every depth of the search for reachable states and before checking
+/// each clause, and once it returns `true` this stops with
+/// [`FiniteError::ReachableBudgetExhausted`].
+pub fn reachable_lemmas(
+    m: &Module,
+    universe: &UniverseBounds,
+    cfg: &QuantifierConfig,
+    atoms: &RestrictedAtoms,
+    clause_size: usize,
+    canceled: &dyn Fn() -> bool,
+) -> Result<Vec<Term>, FiniteError> {
+    let (reachable, indices) =
+        match bdd::check_cancelable(m, universe, None, false, false, canceled) {
+            Ok(CheckerAnswer::Convergence(answer)) => answer,
+            Ok(CheckerAnswer::Counterexample(models)) => return Err(FiniteError::Unsafe(models)),
+            // the search is unbounded, so it only stops early if it was canceled
+            Ok(CheckerAnswer::Unknown) => return Err(FiniteError::ReachableBudgetExhausted),
+            Err(err) => return Err(FiniteError::CheckerError(err)),
+        };
+
+    let prefix = cfg.as_universal();
+    // whether a clause holds in all reachable states, and whether it holds in all states
+    let holds = |clause: &[Literal]| -> Result<(bool, bool), FiniteError> {
+        let term = enumerate_quantifiers(
+            &clause_to_term(&prefix, atoms, clause),
+            &m.signature,
+            universe,
+        )
+        .map_err(|err| FiniteError::CheckerError(CheckerError::EnumerationError(err)))?;
+        let bdd = indices.bdd_from_enumerated(term);
+        Ok((reachable.and_not(&bdd).is_false(), bdd.is_true()))
+    };
+
+    // the clauses that hold, closed under renaming
+    let mut known = KnownClauses::new(&prefix, atoms);
+    let mut clauses = vec![];
+    for clause in templates::clauses(atoms, clause_size) {
+        if canceled() {
+            return Err(FiniteError::ReachableBudgetExhausted);
+        }
+        if known.subsumes(&clause) {
+            continue;
+        }
+        let (reachable, valid) = holds(&clause)?;
+        if !reachable {
+            continue;
+        }
+        known.insert(&clause);
+        // valid clauses (like `r(x) | !r(y) | x != y`) only rule out their supersets
+        if !valid {
+            clauses.push(clause);
+        }
+    }
+
+    // a clause is also implied by instances of larger clauses that merge variables,
+    // and those are found later, so filter from the back
+    let mut kept: Vec<(Vec<Literal>, Vec<Vec<Literal>>)> = vec![];
+    for clause in clauses.into_iter().rev() {
+        let implied = kept.iter().any(|(_, instances)| {
+            instances
+                .iter()
+                .any(|instance| instance.iter().all(|literal| clause.contains(literal)))
+        });
+        if !implied {
+            let instances = instances(&prefix, atoms, &clause);
+            kept.push((clause, instances));
+        }
+    }
+
+    Ok(kept
+        .iter()
+        .rev()
+        .map(|(clause, _)| clause_to_term(&prefix, atoms, clause))
+        .collect())
+}
+
+/// Substitute each variable in the clause with any variable of the same sort, in every way.
+fn instances(
+    prefix: &QuantifierPrefix,
+    atoms: &RestrictedAtoms,
+    clause: &[Literal],
+) -> Vec<Vec<Literal>> {
+    let vars: HashSet<String> = clause
+        .iter()
+        .flat_map(|(a, _)| ids(&atoms.atoms.to_term[*a]))
+        .collect();
+    let mut sorts: HashMap<usize, Vec<&String>> = HashMap::default();
+    for (sort, names) in prefix.sorts.iter().zip(&prefix.names) {
+        sorts.entry(*sort).or_default().extend(names);
+    }
+    let choices = prefix
+        .sorts
+        .iter()
+        .zip(&prefix.names)
+        .flat_map(|(sort, names)| names.iter().map(move |name| (sort, name)))
+        .filter(|(_, name)| vars.contains(*name))
+        .map(|(sort, name)| sorts[sort].iter().map(move |other| (name, *other)))
+        .collect_vec();
+
+    choices
+        .into_iter()
+        .multi_cartesian_product()
+        .filter_map(|map| {
+            let substitution: Substitution = map
+                .into_iter()
+                .map(|(name, other)| (name.clone(), Term::Id(other.clone())))
+                .collect();
+            clause
+                .iter()
+                .map(|(a, b)| atoms.substitute(*a, &substitution).map(|a| (a, *b)))
+                .collect()
+        })
+        .collect()
+}
+
+/// Check that the invariants of each proof are inductive and imply its safety property,
+/// like [`verify::module::verify_destructured_module`], but with solvers that are killed
+/// by canceling `cancelers`. Return the failures, and whether any check was canceled.
+fn check_proofs(
+    conf: &SolverConf,
+    d: &DestructuredModule,
+    sig: &Signature,
+    cancelers: &SolverCancelers<SmtPid>,
+) -> (SolveError, bool) {
+    // whether the term is valid, or `None` if the query was canceled
+    let check = |n_states: usize, t: Term| -> Option<Result<(), QueryError>> {
+        let mut solver = conf.solver(sig, n_states);
+        if !cancelers.add_canceler(solver.pid()) {
+            return None;
+        }
+        solver.assert(&Term::negate(t));
+        match solver.check_sat(std::collections::HashMap::new()) {
+            Ok(SatResp::Sat) => match solver.get_minimal_model() {
+                Ok(states) => Some(Err(QueryError::Sat(states))),
+                Err(SolverError::Killed) => None,
+                Err(err) => panic!("solver error while minimizing: {err}"),
+            },
+            Ok(SatResp::Unsat) => Some(Ok(())),
+            Ok(SatResp::Unknown(m)) => Some(Err(QueryError::Unknown(m))),
+            Err(SolverError::Killed) => None,
+            Err(err) => panic!("error in solver: {err}"),
+        }
+    };
+
+    let mut errors = SolveError::default();
+    let mut canceled = false;
+    // verified safety properties are assumed for later ones
+    let mut axioms = d.axioms.clone();
+    for proof in &d.proofs {
+        let Ok(assert) =
+            InvariantAssertion::for_assert(sig, &d.inits, &d.transitions, &axioms, proof)
+        else {
+            errors.push(AssertionFailure {
+                loc: proof.safety.span,
+                error: QueryError::Unknown("unsupported".to_string()),
+                reason: FailureType::Unsupported,
+            });
+            axioms.push(proof.safety.x.clone());
+            continue;
+        };
+        let initiation = (
+            assert.inv.span,
+            FailureType::InitInv,
+            1,
+            assert.initiation(),
+        );
+        let consecutions = assert
+            .consecutions()
+            .into_iter()
+            .map(|(span, t)| (span.or(assert.inv.span), FailureType::NotInductive, 2, t));
+        let results = [initiation]
+            .into_iter()
+            .chain(consecutions)
+            .collect_vec()
+            .into_par_iter()
+            .map(|(loc, reason, n_states, t)| (loc, reason, check(n_states, t.0)))
+            .collect::<Vec<_>>();
+        for (loc, reason, res) in results {
+            match res {
+                Some(Ok(())) => (),
+                Some(Err(error)) => errors.push(AssertionFailure { loc, reason, error }),
+                None => canceled = true,
+            }
+        }
+        axioms.push(proof.safety.x.clone());
+    }
+    (errors, canceled)
+}
+
+/// Weaken a set of lemmas until they are inductive and prove the safety properties of the
+/// module, by repeatedly dropping the lemmas that are violated by a counterexample to induction.
+///
+/// Canceling `cancelers` kills the in-flight queries, and then the weakening stops with
+/// [`FiniteError::BudgetExhausted`].
+pub fn inductive_lemmas(
+    conf: &SolverConf,
+    m: &Module,
+    mut lemmas: Vec<Term>,
+    cancelers: &SolverCancelers<SmtPid>,
+) -> Result<Vec<Term>, FiniteError> {
+    let mut d =
+        extract(m).map_err(|err| FiniteError::CheckerError(CheckerError::ExtractionError(err)))?;
+    loop {
+        for proof in &mut d.proofs {
+            proof.invariants = lemmas
+                .iter()
+                .map(|lemma| Spanned {
+                    x: lemma.clone(),
+                    span: None,
+                })
+                .collect();
+        }
+
+        let (err, canceled) = check_proofs(conf, &d, &m.signature, cancelers);
+        if err.fails.is_empty() && !canceled {
+            return Ok(lemmas);
+        }
+        if canceled || cancelers.is_canceled() {
+            // each lemma is checked separately, so if all checks are answered, the lemmas
+            // which hold after every counterexample to induction are inductive relative to all lemmas
+            let last_states = |consecution: bool| {
+                err.fails
+                    .iter()
+                    .filter(|fail| !consecution || matches!(fail.reason, FailureType::NotInductive))
+                    .filter_map(|fail| match &fail.error {
+                        QueryError::Sat(models) => models.last().cloned(),
+                        QueryError::Unknown(_) => None,
+                    })
+                    .collect_vec()
+            };
+            let relatively_inductive = match canceled
+                || err
+                    .fails
+                    .iter()
+                    .any(|fail| matches!(fail.error, QueryError::Unknown(_)))
+            {
+                true => vec![],
+                false => {
+                    let states = last_states(true);
+                    lemmas
+                        .iter()
+                        .filter(|lemma| states.iter().all(|state| state.eval(lemma) == 1))
+                        .cloned()
+                        .collect()
+                }
+            };
+            return Err(FiniteError::BudgetExhausted {
+                lemmas,
+                relatively_inductive,
+                ctis: last_states(false),
+            });
+        }
+        let before = lemmas.len();
+        for fail in &err.fails {
+            match &fail.error {
+                // the last state is the one that violates an invariant
+                QueryError::Sat(models) => {
+                    let state = models.last().unwrap();
+                    lemmas.retain(|lemma| state.eval(lemma) == 1);
+                }
+                QueryError::Unknown(m) => return Err(FiniteError::SolverUnknown(m.clone())),
+            }
+        }
+        if lemmas.len() == before {
+            return Err(FiniteError::NotInductive(err));
+        }
+        log::info!(
+            "dropped {} lemmas that are not inductive, {} remaining",
+            before - lemmas.len(),
+            lemmas.len()
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fly::sorts::sort_check_module;
+    use solver::{
+        backends::{GenericBackend, SolverType},
+        basics::BasicSolverCanceler,
+        solver_path,
+    };
+    use std::collections::HashMap;
+    use std::sync::Arc;
+
+    fn lockserver_lemmas(
+        bound: usize,
+        canceled: &dyn Fn() -> bool,
+    ) -> Result<Vec<Term>, FiniteError> {
+        let source = include_str!("../../temporal-verifier/examples/lockserver.fly");
+        let mut m = fly::parser::parse(source).unwrap();
+        sort_check_module(&mut m).unwrap();
+        let universe = HashMap::from([("node".to_string(), bound)]);
+        let sig = Arc::new(m.signature.clone());
+        let cfg = QuantifierConfig::new(sig, vec![None], vec![0], &[2]);
+        let atoms = templates::atoms(&cfg, None, true);
+        reachable_lemmas(&m, &universe, &cfg, &atoms, 3, canceled)
+    }
+
+    #[test]
+    fn finite_lockserver() {
+        let lemmas = lockserver_lemmas(2, &|| false).unwrap();
+        let lemmas = lemmas.iter().map(|lemma| lemma.to_string()).collect_vec();
+
+        // mutual exclusion holds, in only one of its equivalent forms
+        let mutex = "forall node_1:node, node_2:node. \
+            !holds_lock(node_1) | !holds_lock(node_2) | node_1 = node_2";
+        assert!(lemmas.iter().any(|lemma| lemma == mutex));
+        assert!(!lemmas
+            .iter()
+            .any(|lemma| lemma.contains("!holds_lock(node_2) | !holds_lock(node_1)")));
+        assert!(lemmas
+            .contains(&"forall node_1:node. !server_holds_lock | !holds_lock(node_1)".to_string()));
+        // clauses that are implied by an instance of another clause are left out
+        assert!(!lemmas
+            .contains(&"forall node_1:node. !grant_msg(node_1) | !unlock_msg(node_1)".to_string()));
+        // and so are clauses that hold in every state
+        assert!(!lemmas
+            .iter()
+            .any(|lemma| lemma.contains("!(node_1 = node_2)")));
+        assert_eq!(lemmas.len(), 9);
+    }
+
+    #[test]
+    fn finite_lockserver_canceled() {
+        // canceled during the search for reachable states
+        assert!(matches!(
+            lockserver_lemmas(2, &|| true),
+            Err(FiniteError::ReachableBudgetExhausted)
+        ));
+        // canceled after the search, while checking the clauses
+        let calls = std::cell::Cell::new(0);
+        let canceled = || {
+            calls.set(calls.get() + 1);
+            calls.get() > 100
+        };
+        assert!(matches!(
+            lockserver_lemmas(2, &canceled),
+            Err(FiniteError::ReachableBudgetExhausted)
+        ));
+    }
+
+    #[test]
+    fn finite_lockserver_inductive() {
+        let source = include_str!("../../temporal-verifier/examples/lockserver.fly");
+        let mut m = fly::parser::parse(source).unwrap();
+        sort_check_module(&mut m).unwrap();
+        let conf = SolverConf {
+            backend: GenericBackend::new(SolverType::Z3, &solver_path("z3")),
+            tee: None,
+        };
+        let lemmas = lockserver_lemmas(2, &|| false).unwrap();
+
+        let cancelers = SolverCancelers::new();
+        let invariant = inductive_lemmas(&conf, &m, lemmas.clone(), &cancelers).unwrap();
+        assert!(!invariant.is_empty());
+        assert!(invariant.iter().all(|lemma| lemmas.contains(lemma)));
+
+        // the queries of a canceled run are not answered
+        cancelers.cancel();
+        match inductive_lemmas(&conf, &m, lemmas.clone(), &cancelers) {
+            Err(FiniteError::BudgetExhausted {
+                lemmas: remaining,
+                relatively_inductive,
+                ctis,
+            }) => {
+                assert_eq!(remaining, lemmas);
+                assert!(relatively_inductive.is_empty());
+                assert!(ctis.is_empty());
+            }
+            res => panic!("expected the budget to be exhausted, got {res:?}"),
+        }
+    }
+}
@@ -5,24 +5,37 @@
 //! lemma domain.
 
 use itertools::Itertools;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 use std::{collections::VecDeque, fmt::Debug};
+use std::{env, io, process};
 
 use crate::basics::QfBody;
 use crate::{
     atoms::{restrict, restrict_by_prefix, Atoms, Literal, RestrictedAtoms},
-    basics::{FOModule, InferenceConfig},
+    basics::{CexResult, FOModule, InferenceConfig},
+    budget::Budget,
+    events::Event,
     lemma::InductionFrame,
+    quant::{alternation_graph, sort_orderings, QuantifierConfig},
     subsume::OrderSubsumption,
     weaken::{Domain, LemmaQf},
 };
 use crate::{lemma, subsume};
-use fly::syntax::{Module, Term, ThmStmt};
+use fly::{
+    semantics::{models_to_string, Model},
+    syntax::{Module, Signature, Term, ThmStmt},
+    term::simplify,
+    transitions::extract,
+};
 use solver::{
     backends::SolverType,
-    basics::{BasicSolver, FallbackSolvers, ParallelSolvers, SingleSolver},
+    basics::{
+        BasicSolver, BasicSolverCanceler, CancelableSolver, FallbackSolvers, ParallelSolvers,
+        SingleSolver, SolverCancelers,
+    },
     conf::SolverConf,
+    remote::{self, RemoteSolvers},
 };
 
 use rayon::prelude::*;
@@ -33,6 +46,7 @@ pub mod defaults {
     pub const DOMAIN_GROWTH_FACTOR: usize = 5;
     pub const MAX_QUANT: usize = 6;
     pub const MAX_SAME_SORT: usize = 3;
+    pub const MAX_AUTO_ORDERINGS: usize = 6;
     pub const QF_BODY: QfBody = QfBody::PDnf;
     pub const MAX_CLAUSES: Option<usize> = None;
     pub const MAX_CLAUSE_SIZE: Option<usize> = None;
@@ -67,6 +81,37 @@ fn invariant_cover<S: BasicSolver>(
     (covered, proof.invariants.len())
 }
 
+/// Make the given proof more readable. Each lemma is first strengthened by removing the
+/// disjuncts whose removal is implied by the lemma together with the other lemmas, so the
+/// conjunction of the lemmas stays equivalent; lemmas are handled one at a time, since each
+/// removal relies on the rest.
+/// Then the lemmas are rewritten and ordered (see [`simplify::simplify_lemmas`]).
+fn simplify_proof<S: BasicSolver>(
+    sig: &Signature,
+    solver: &S,
+    fo: &FOModule,
+    proof: &[Term],
+) -> Vec<Term> {
+    let mut lemmas = proof.to_vec();
+    for i in 0..lemmas.len() {
+        let mut j = 0;
+        loop {
+            let size = simplify::disjuncts(&lemmas[i]).len();
+            if size <= 1 || j >= size {
+                break;
+            }
+            let stronger = simplify::remove_disjunct(&lemmas[i], j);
+            // the lemma itself is among the hypotheses
+            match fo.implication_cex(solver, &lemmas, &stronger) {
+                CexResult::UnsatCore(_) => lemmas[i] = stronger,
+                _ => j += 1,
+            }
+        }
+    }
+
+    simplify::simplify_lemmas(sig, &lemmas)
+}
+
 /// An inductive fixpoint
 pub struct FoundFixpoint {
     /// The fixpoint term (the conjunction of these lemmas).
@@ -81,6 +126,13 @@ pub struct FoundFixpoint {
     /// Number of terms of handwritten invariant covered
     /// and total number of terms in the handwritten invariant
     covering: Option<(usize, usize)>,
+    /// Whether the fixpoint has been reached. If not, the run has exhausted its budget,
+    /// and the proof is the frame at that point, which is not yet inductive
+    reached: bool,
+    /// The lemmas of an unreached fixpoint which are inductive relative to it
+    relatively_inductive: Vec<Term>,
+    /// The CTI's of an unreached fixpoint which haven't been handled yet
+    ctis: Vec<Model>,
 }
 
 impl FoundFixpoint {
@@ -93,6 +145,11 @@ impl FoundFixpoint {
             println!("}}");
         };
 
+        if !self.reached {
+            self.report_partial(print_invariant);
+            return;
+        }
+
         if self.safe {
             println!("Fixpoint SAFE!");
         } else {
@@ -117,16 +174,45 @@ impl FoundFixpoint {
             }
         }
     }
+
+    fn report_partial(&self, print_invariant: bool) {
+        let proof = self.proof.as_ref().unwrap();
+        println!("Fixpoint NOT REACHED! (budget exhausted)");
+        println!("Frame size = {} (not yet inductive)", proof.len());
+        println!(
+            "Inductive relative to the frame = {}",
+            self.relatively_inductive.len()
+        );
+        println!("Outstanding CTI's = {}", self.ctis.len());
+
+        if print_invariant {
+            println!("Runtime = {:.2}s", self.time_taken.as_secs_f64());
+            println!("# frame");
+            println!("proof {{");
+            for lemma in proof {
+                println!("  invariant {lemma}");
+            }
+            println!("}}");
+            println!("# inductive relative to the frame");
+            for lemma in &self.relatively_inductive {
+                println!("  {lemma}");
+            }
+            if !self.ctis.is_empty() {
+                println!("# outstanding CTI's");
+                println!("{}", models_to_string(&self.ctis));
+            }
+        }
+    }
 }
 
-fn parallel_solver(infer_cfg: &InferenceConfig) -> impl BasicSolver {
+fn parallel_solver(fname: &String) -> impl BasicSolver {
     ParallelSolvers::new(vec![
-        SolverConf::new(SolverType::Z3, true, &infer_cfg.fname, 0, 0),
-        SolverConf::new(SolverType::Cvc5, true, &infer_cfg.fname, 0, 0),
+        SolverConf::new(SolverType::Z3, true, fname, 0, 0),
+        SolverConf::new(SolverType::Cvc5, true, fname, 0, 0),
     ])
 }
 
-fn fallback_solver(infer_cfg: &InferenceConfig) -> impl BasicSolver {
+fn fallback_solver(fname: &String) -> impl BasicSolver {
     // For the solvers in fallback fashion we alternate between Z3 and CVC5
     // with increasing timeouts and varying seeds, ending with a Z3 solver with
     // no timeout. The idea is to try both Z3 and CVC5 with some timeout to see if any
@@ -134,13 +220,13 @@ fn fallback_solver(infer_cfg: &InferenceConfig) -> impl BasicSolver {
     // ending with no timeout at all. The seed changes are meant to add some
     // variation vis-a-vis previous attempts.
     FallbackSolvers::new(vec![
-        SolverConf::new(SolverType::Z3, true, &infer_cfg.fname, 3, 0),
-        SolverConf::new(SolverType::Cvc5, true, &infer_cfg.fname, 3, 0),
-        SolverConf::new(SolverType::Z3, true, &infer_cfg.fname, 60, 1),
-        SolverConf::new(SolverType::Cvc5, true, &infer_cfg.fname, 60, 1),
-        SolverConf::new(SolverType::Z3, true, &infer_cfg.fname, 600, 2),
-        SolverConf::new(SolverType::Cvc5, true, &infer_cfg.fname, 600, 2),
-        SolverConf::new(SolverType::Z3, true, &infer_cfg.fname, 0, 3),
+        SolverConf::new(SolverType::Z3, true, fname, 3, 0),
+        SolverConf::new(SolverType::Cvc5, true, fname, 3, 0),
+        SolverConf::new(SolverType::Z3, true, fname, 60, 1),
+        SolverConf::new(SolverType::Cvc5, true, fname, 60, 1),
+        SolverConf::new(SolverType::Z3, true, fname, 600, 2),
+        SolverConf::new(SolverType::Cvc5, true, fname, 600, 2),
+        SolverConf::new(SolverType::Z3, true, fname, 0, 3),
     ])
 }
 
@@ -154,12 +240,43 @@ fn simulation_solver(infer_cfg: &InferenceConfig) -> impl BasicSolver {
     ))
 }
 
+/// Print a progress message of a qalpha run, or only log it if the run is racing other runs.
+macro_rules! progress {
+    ($verbose:expr, $($arg:tt)*) => {
+        if $verbose {
+            println!($($arg)*)
+        } else {
+            log::info!($($arg)*)
+        }
+    };
+}
+
+/// Sample the reachable states of the module at the configured sort bounds, if there are any.
+/// If they cannot be sampled (for example, if the set checker doesn't support the module),
+/// a warning is logged and inference continues without samples.
+fn sample_reachable(infer_cfg: &InferenceConfig, m: &Module) -> Vec<Model> {
+    match &infer_cfg.sample_bound {
+        None => vec![],
+        Some(universe) => {
+            log::debug!("Sampling reachable states...");
+            match bounded::set::reachable(m, universe, infer_cfg.sample_depth, false) {
+                Ok(states) => states,
+                Err(err) => {
+                    log::warn!("Could not sample reachable states, continuing without them: {err}");
+                    vec![]
+                }
+            }
+        }
+    }
+}
+
 pub fn qalpha<O, L, B, S1, S2>(
     infer_cfg: Arc<InferenceConfig>,
     m: &Module,
     main_solver: &S1,
     simulation_solver: &S2,
     print_invariant: bool,
+    budget: &Budget,
 ) where
     O: OrderSubsumption<Base = B>,
     L: LemmaQf<Base = B>,
@@ -167,6 +284,213 @@ pub fn qalpha<O, L, B, S1, S2>(
     S1: BasicSolver,
     S2: BasicSolver,
 {
+    if infer_cfg.auto_quant {
+        qalpha_auto::<O, L, B, S1, S2>(
+            infer_cfg,
+            m,
+            main_solver,
+            simulation_solver,
+            print_invariant,
+            budget,
+        );
+        return;
+    }
+
+    let samples = sample_reachable(&infer_cfg, m);
+    if budget.is_unlimited() {
+        search::<O, L, B, S1, S2>(
+            infer_cfg,
+            m,
+            main_solver,
+            simulation_solver,
+            &samples,
+            Some(print_invariant),
+            &|| false,
+        );
+        return;
+    }
+
+    // Once the budget is exhausted, all in-flight queries are canceled and the current frame is reported.
+    let main_cancelers = SolverCancelers::new();
+    let simulation_cancelers = SolverCancelers::new();
+    let main_solver = CancelableSolver::new(main_solver, main_cancelers.clone());
+    let simulation_solver = CancelableSolver::new(simulation_solver, simulation_cancelers.clone());
+    budget.enforce(&(main_cancelers.clone(), simulation_cancelers), || {
+        search::<O, L, B, _, _>(
+            infer_cfg,
+            m,
+            &main_solver,
+            &simulation_solver,
+            &samples,
+            Some(print_invariant),
+            &|| main_cancelers.is_canceled(),
+        )
+    });
+}
+
+/// Race qalpha runs with different quantifier configurations, and report the first one
+/// to find a safe fixpoint. The configurations use the sort orderings allowed by the module's
+/// quantifier alternation graph, with increasing numbers of variables per sort and existential
+/// quantifiers. All runs share the given solvers, and once a run succeeds the others are canceled.
+/// If the budget is exhausted before any run succeeds, the best partial frame is reported, which
+/// is the one with the most lemmas inductive relative to it (and then the fewest outstanding CTI's).
+fn qalpha_auto<O, L, B, S1, S2>(
+    infer_cfg: Arc<InferenceConfig>,
+    m: &Module,
+    main_solver: &S1,
+    simulation_solver: &S2,
+    print_invariant: bool,
+    budget: &Budget,
+) where
+    O: OrderSubsumption<Base = B>,
+    L: LemmaQf<Base = B>,
+    B: Clone + Debug + Send,
+    S1: BasicSolver,
+    S2: BasicSolver,
+{
+    let sig = Arc::new(m.signature.clone());
+    let edges = alternation_graph(&extract(m).unwrap(), &sig);
+    let orderings = sort_orderings(&sig, &edges);
+    let max_exist = infer_cfg.max_existentials.unwrap_or(1);
+    let configs = (1..=defaults::MAX_SAME_SORT)
+        .cartesian_product(0..=max_exist)
+        .cartesian_product(orderings.iter().take(defaults::MAX_AUTO_ORDERINGS))
+        .enumerate()
+        .map(|(i, ((count, exist), ordering))| {
+            let mut cfg = (*infer_cfg).clone();
+            cfg.cfg = QuantifierConfig::new(
+                sig.clone(),
+                vec![None; ordering.len()],
+                ordering.clone(),
+                &vec![count; ordering.len()],
+            );
+            cfg.max_existentials = Some(exist);
+            cfg.until_safe = true;
+            cfg.events = infer_cfg.events.for_run(i);
+            Arc::new(cfg)
+        })
+        .collect_vec();
+
+    println!(
+        "Racing {} quantifier configurations ({} sort orderings)...",
+        configs.len(),
+        orderings.len().min(defaults::MAX_AUTO_ORDERINGS)
+    );
+    let samples = sample_reachable(&infer_cfg, m);
+    let runs: SolverCancelers<SolverCancelers<SolverCancelers<S1::Canceler>>> =
+        SolverCancelers::new();
+    let winner = Mutex::new(None);
+    let partial = Mutex::new(vec![]);
+    let (_, exhausted) = budget.enforce(&runs, || {
+        configs
+            .par_iter()
+            .with_max_len(1)
+            .enumerate()
+            .for_each(|(i, cfg)| {
+                let cancelers = SolverCancelers::new();
+                if !runs.add_canceler(cancelers.clone()) {
+                    return;
+                }
+                log::info!("Starting run #{i}: {}", describe(cfg));
+                let solver = CancelableSolver::new(main_solver, cancelers.clone());
+                let fixpoint = search::<O, L, B, _, S2>(
+                    cfg.clone(),
+                    m,
+                    &solver,
+                    simulation_solver,
+                    &samples,
+                    None,
+                    &|| cancelers.is_canceled(),
+                );
+                match fixpoint {
+                    Some(fixpoint) if fixpoint.safe => {
+                        let mut winner = winner.lock().unwrap();
+                        if winner.is_none() {
+                            *winner = Some((i, fixpoint));
+                            runs.cancel();
+                        }
+                    }
+                    Some(fixpoint) if !fixpoint.reached => {
+                        partial.lock().unwrap().push((i, fixpoint));
+                    }
+                    _ => (),
+                }
+            })
+    });
+
+    match winner.into_inner().unwrap() {
+        Some((i, fixpoint)) => {
+            println!(
+                "Run #{i} found a safe fixpoint using {}",
+                describe(&configs[i])
+            );
+            fixpoint.report(print_invariant);
+        }
+        None if exhausted => {
+            println!("No quantifier configuration found a safe fixpoint within the budget");
+            let best = partial
+                .into_inner()
+                .unwrap()
+                .into_iter()
+                .max_by_key(|(_, fixpoint)| {
+                    (
+                        fixpoint.relatively_inductive.len(),
+                        std::cmp::Reverse(fixpoint.ctis.len()),
+                    )
+                });
+            if let Some((i, fixpoint)) = best {
+                println!(
+                    "Run #{i} has the best partial frame, using {}",
+                    describe(&configs[i])
+                );
+                fixpoint.report(print_invariant);
+            }
+        }
+        None => println!("No quantifier configuration found a safe fixpoint"),
+    }
+}
+
+/// Describe a quantifier configuration by the command-line arguments that select it.
+fn describe(infer_cfg: &InferenceConfig) -> String {
+    let cfg = &infer_cfg.cfg;
+    // the quantifiers are only used with --custom-quant
+    ["--custom-quant".to_string()]
+        .into_iter()
+        .chain(cfg.sorts.iter().zip(&cfg.names).map(|(sort, names)| {
+            format!(
+                "--quantifier \"* {} {}\"",
+                cfg.signature.sorts[*sort],
+                names.len()
+            )
+        }))
+        .chain([format!(
+            "--max-exist {}",
+            infer_cfg.max_existentials.unwrap_or(cfg.num_vars())
+        )])
+        .join(" ")
+}
+
+/// Run qalpha, growing the lemma domains until all of them are used, or until a safe fixpoint
+/// is found if `until_safe` is set. Return the last fixpoint found, which is unreached if the run was
+/// canceled while computing it, or `None` if the run was canceled between fixpoints.
+/// If `print_invariant` is `None`, progress is only logged and the fixpoints are not reported.
+fn search<O, L, B, S1, S2>(
+    infer_cfg: Arc<InferenceConfig>,
+    m: &Module,
+    main_solver: &S1,
+    simulation_solver: &S2,
+    samples: &[Model],
+    print_invariant: Option<bool>,
+    canceled: &(dyn Fn() -> bool + Sync),
+) -> Option<FoundFixpoint>
+where
+    O: OrderSubsumption<Base = B>,
+    L: LemmaQf<Base = B>,
+    B: Clone + Debug + Send,
+    S1: BasicSolver,
+    S2: BasicSolver,
+{
+    let verbose = print_invariant.is_some();
     let fo = FOModule::new(
         m,
         infer_cfg.disj,
@@ -236,31 +560,54 @@ pub fn qalpha<O, L, B, S1, S2>(
         active_domains = vec![];
     }
 
-    println!(
+    progress!(
+        verbose,
         "Number of individual domains: {}",
         domains.len() + active_domains.len()
     );
 
+    let activated = |(prefix, lemma_qf, atoms): &Domain<L>| {
+        infer_cfg.events.emit(|| Event::DomainActivated {
+            prefix: format!("{prefix:?}"),
+            atoms: atoms.len(),
+            lemma_qf: format!("{lemma_qf:?}"),
+            size: lemma_qf.approx_space_size(),
+        })
+    };
+    active_domains.iter().for_each(activated);
+
     let mut domain_size: usize = domain_size_of(&active_domains);
     let mut next_domain_size = defaults::MIN_DOMAIN_SIZE;
     let mut iteration: usize = 1;
     loop {
+        if canceled() {
+            return None;
+        }
         while !domains.is_empty() && domain_size < next_domain_size {
             let dom = domains.pop_front().unwrap();
+            activated(&dom);
             active_domains.retain(|d| !(dom.0.contains(&d.0) && dom.1.contains(&d.1)));
             active_domains.push(dom);
             domain_size = domain_size_of(&active_domains);
         }
+        infer_cfg.events.emit(|| Event::IterationStart {
+            iteration,
+            domain_size,
+        });
 
-        println!();
-        println!("({iteration}) Running qalpha algorithm...");
-        println!(
+        if verbose {
+            println!();
+        }
+        progress!(verbose, "({iteration}) Running qalpha algorithm...");
+        progress!(
+            verbose,
             "Approximate domain size: 10^{:.2} ({domain_size})",
             (domain_size as f64).log10()
         );
-        println!("Prefixes:");
+        progress!(verbose, "Prefixes:");
         for (prefix, lemma_qf, atoms) in &active_domains {
-            println!(
+            progress!(
+                verbose,
                 "    {:?} --- {} atoms --- {:?} ~ {}",
                 prefix,
                 atoms.len(),
@@ -278,12 +625,26 @@ pub fn qalpha<O, L, B, S1, S2>(
             unrestricted.clone(),
             active_domains.clone(),
             extend,
+            samples,
+            verbose,
+            canceled,
         );
 
-        fixpoint.report(print_invariant);
+        infer_cfg.events.emit(|| Event::FixpointReached {
+            reached: fixpoint.reached,
+            safe: fixpoint.safe,
+            size: fixpoint.proof.as_ref().map_or(0, |proof| proof.len()),
+            minimized_size: fixpoint.minimized_proof.as_ref().map(|proof| proof.len()),
+        });
+        if let Some(print_invariant) = print_invariant {
+            fixpoint.report(print_invariant);
+        }
+        if !fixpoint.reached {
+            return Some(fixpoint);
+        }
 
         if (fixpoint.safe && infer_cfg.until_safe) || domains.is_empty() {
-            break;
+            return Some(fixpoint);
         }
 
         iteration += 1;
@@ -294,58 +655,94 @@ pub fn qalpha<O, L, B, S1, S2>(
     }
 }
 
-pub fn qalpha_dynamic(infer_cfg: Arc<InferenceConfig>, m: &Module, print_invariant: bool) {
-    match (&infer_cfg.qf_body, infer_cfg.fallback) {
-        (QfBody::CNF, false) => qalpha::<subsume::Cnf<Literal>, lemma::LemmaCnf, _, _, _>(
+/// Run qalpha with the solvers selected by the configuration. Fails if the worker processes
+/// can't be started.
+pub fn qalpha_dynamic(
+    infer_cfg: Arc<InferenceConfig>,
+    m: &Module,
+    print_invariant: bool,
+    budget: &Budget,
+) -> io::Result<()> {
+    match (infer_cfg.workers, infer_cfg.fallback) {
+        (Some(workers), fallback) => {
+            let fname = infer_cfg.fname.clone();
+            let exe = env::current_exe()?;
+            let solver = RemoteSolvers::new(workers, move || {
+                let mut command = process::Command::new(&exe);
+                command.args(["infer", "qalpha-worker", &fname]);
+                if fallback {
+                    command.arg("--fallback");
+                }
+                command
+            })?;
+            qalpha_with_solver(infer_cfg, m, &solver, print_invariant, budget)
+        }
+        (None, false) => qalpha_with_solver(
             infer_cfg.clone(),
             m,
-            &parallel_solver(&infer_cfg),
-            &simulation_solver(&infer_cfg),
+            &parallel_solver(&infer_cfg.fname),
             print_invariant,
+            budget,
         ),
-        (QfBody::PDnf, false) => qalpha::<subsume::PDnf<Literal>, lemma::LemmaPDnf, _, _, _>(
+        (None, true) => qalpha_with_solver(
             infer_cfg.clone(),
             m,
-            &parallel_solver(&infer_cfg),
+            &fallback_solver(&infer_cfg.fname),
+            print_invariant,
+            budget,
+        ),
+    }
+    Ok(())
+}
+
+/// Run qalpha with the given main solver and the configured quantifier-free body.
+fn qalpha_with_solver<S: BasicSolver>(
+    infer_cfg: Arc<InferenceConfig>,
+    m: &Module,
+    main_solver: &S,
+    print_invariant: bool,
+    budget: &Budget,
+) {
+    match infer_cfg.qf_body {
+        QfBody::CNF => qalpha::<subsume::Cnf<Literal>, lemma::LemmaCnf, _, _, _>(
+            infer_cfg.clone(),
+            m,
+            main_solver,
             &simulation_solver(&infer_cfg),
             print_invariant,
+            budget,
         ),
-        (QfBody::PDnfNaive, false) => {
-            qalpha::<subsume::Dnf<Literal>, lemma::LemmaPDnfNaive, _, _, _>(
-                infer_cfg.clone(),
-                m,
-                &parallel_solver(&infer_cfg),
-                &simulation_solver(&infer_cfg),
-                print_invariant,
-            )
-        }
-        (QfBody::CNF, true) => qalpha::<subsume::Cnf<Literal>, lemma::LemmaCnf, _, _, _>(
+        QfBody::PDnf => qalpha::<subsume::PDnf<Literal>, lemma::LemmaPDnf, _, _, _>(
             infer_cfg.clone(),
             m,
-            &fallback_solver(&infer_cfg),
+            main_solver,
             &simulation_solver(&infer_cfg),
             print_invariant,
+            budget,
         ),
-        (QfBody::PDnf, true) => qalpha::<subsume::PDnf<Literal>, lemma::LemmaPDnf, _, _, _>(
+        QfBody::PDnfNaive => qalpha::<subsume::Dnf<Literal>, lemma::LemmaPDnfNaive, _, _, _>(
             infer_cfg.clone(),
             m,
-            &fallback_solver(&infer_cfg),
+            main_solver,
             &simulation_solver(&infer_cfg),
             print_invariant,
+            budget,
         ),
-        (QfBody::PDnfNaive, true) => {
-            qalpha::<subsume::Dnf<Literal>, lemma::LemmaPDnfNaive, _, _, _>(
-                infer_cfg.clone(),
-                m,
-                &fallback_solver(&infer_cfg),
-                &simulation_solver(&infer_cfg),
-                print_invariant,
-            )
-        }
     }
 }
 
-/// Run the qalpha algorithm on the configured lemma domains.
+/// The main loop of a qalpha worker process (see `InferenceConfig::workers`), which answers
+/// the queries it reads from stdin using the solvers of the given file, until stdin is closed.
+pub fn qalpha_worker(fname: &String, fallback: bool) -> io::Result<()> {
+    let (stdin, stdout) = (io::stdin().lock(), io::stdout().lock());
+    match fallback {
+        false => remote::serve(&parallel_solver(fname), stdin, stdout),
+        true => remote::serve(&fallback_solver(fname), stdin, stdout),
+    }
+}
+
+/// Run the qalpha algorithm on the configured lemma domains. If the run is canceled,
+/// return the frame at that point as an unreached fixpoint.
 #[allow(clippy::too_many_arguments)]
 fn run_qalpha<O, L, B, S1, S2>(
     infer_cfg: Arc<InferenceConfig>,
@@ -356,6 +753,9 @@ fn run_qalpha<O, L, B, S1, S2>(
     atoms: Arc<RestrictedAtoms>,
     domains: Vec<Domain<L>>,
     extend: Option<(usize, usize)>,
+    samples: &[Model],
+    verbose: bool,
+    canceled: &(dyn Fn() -> bool + Sync),
 ) -> FoundFixpoint
 where
     O: OrderSubsumption<Base = B>,
@@ -382,8 +782,37 @@ where
     let mut frame: InductionFrame<O, L, B> =
         InductionFrame::new(infer_cfg.clone(), atoms, domains, extend);
 
+    // Weaken the frame using reachable states sampled by the bounded checker.
+    if !samples.is_empty() {
+        let before = frame.len();
+        let pruned = frame.weaken_reachable(samples);
+        progress!(
+            verbose,
+            "Pruned {pruned} of {before} lemmas using {} sampled reachable states",
+            samples.len()
+        );
+    }
+
+    // If the run is canceled, return the current frame, which is not yet inductive.
+    // The lemmas are only rewritten in a readable form, since the solvers might be canceled.
+    let partial =
+        |frame: &InductionFrame<O, L, B>, relatively_inductive: Vec<Term>| FoundFixpoint {
+            proof: Some(simplify::simplify_lemmas(&m.signature, &frame.proof())),
+            minimized_proof: None,
+            safe: false,
+            time_taken: start.elapsed(),
+            covering: None,
+            reached: false,
+            relatively_inductive: simplify::simplify_lemmas(&m.signature, &relatively_inductive),
+            ctis: frame.outstanding_ctis(),
+        };
+
     // Begin by overapproximating the initial states.
     while frame.init_cycle(fo, main_solver) {}
+    if canceled() {
+        // Lemmas whose initiation check was canceled are not known to hold initially.
+        return partial(&frame, vec![]);
+    }
 
     // Handle transition CTI's.
     loop {
@@ -394,13 +823,16 @@ where
 
         if infer_cfg.abort_unsafe {
             frame.log_info("Checking safety...");
-            if !frame.is_safe(fo, main_solver) {
+            if !frame.is_safe(fo, main_solver) && !canceled() {
                 return FoundFixpoint {
                     proof: None,
                     minimized_proof: None,
                     safe: false,
                     time_taken: start.elapsed(),
                     covering: None,
+                    reached: true,
+                    relatively_inductive: vec![],
+                    ctis: vec![],
                 };
             }
         }
@@ -412,10 +844,16 @@ where
 
     frame.log_info("Checking safety...");
     let safe = frame.is_safe(fo, main_solver);
+    if canceled() {
+        return partial(&frame, frame.relatively_inductive());
+    }
     let time_taken = start.elapsed();
     let proof: Vec<Term> = frame.proof();
     let minimized_proof = frame.minimized_proof();
     let covering = Some(invariant_cover(m, main_solver, fo, &proof));
+    let proof = simplify_proof(&m.signature, main_solver, fo, &proof);
+    let minimized_proof =
+        minimized_proof.map(|lemmas| simplify_proof(&m.signature, main_solver, fo, &lemmas));
 
     FoundFixpoint {
         proof: Some(proof),
@@ -423,5 +861,71 @@ where
         safe,
         time_taken,
         covering,
+        reached: true,
+        relatively_inductive: vec![],
+        ctis: vec![],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fly::{parser::term, sorts::sort_check_module};
+
+    #[test]
+    fn test_simplify_proof() {
+        let source = r"
+sort node
+mutable p(node): bool
+mutable q(node): bool
+mutable r(node): bool
+
+assume forall N:node. p(N) & !q(N)
+assume always forall N:node. (p'(N) <-> p(N)) & (q'(N) <-> q(N)) & (r'(N) <-> r(N))
+";
+        let mut m = fly::parser::parse(source).unwrap();
+        sort_check_module(&mut m).unwrap();
+        let fo = FOModule::new(&m, false, false, false);
+        let solver = SingleSolver::new(SolverConf::new(
+            SolverType::Z3,
+            false,
+            &"test_simplify_proof".to_string(),
+            0,
+            0,
+        ));
+
+        let simplified = |proof: &[Term], strengthened: &[Term]| {
+            assert_eq!(
+                simplify_proof(&m.signature, &solver, &fo, proof),
+                simplify::simplify_lemmas(&m.signature, strengthened)
+            );
+        };
+
+        // none of the lemmas is redundant, but `r(node_1)` can be removed from the first one,
+        // since the second lemma rules it out
+        simplified(
+            &[
+                term("forall node_1:node. !p(node_1) | q(node_1) | r(node_1)"),
+                term("forall node_1:node. !r(node_1)"),
+            ],
+            &[
+                term("forall node_1:node. !p(node_1) | q(node_1)"),
+                term("forall node_1:node. !r(node_1)"),
+            ],
+        );
+        // with `p(node_1)`, the first lemma becomes `!q(node_1)`, and then the last lemma,
+        // which is redundant, becomes `!q(node_1)` as well
+        simplified(
+            &[
+                term("forall node_1:node. !p(node_1) | !q(node_1)"),
+                term("forall node_1:node. p(node_1)"),
+                term("forall node_1:node. !q(node_1) | r(node_1)"),
+            ],
+            &[
+                term("forall node_1:node. !q(node_1)"),
+                term("forall node_1:node. p(node_1)"),
+                term("forall node_1:node. !q(node_1)"),
+            ],
+        );
     }
 }
@@ -9,10 +9,17 @@ use std::{
     sync::Mutex,
 };
 
+use itertools::Itertools;
 use rayon::prelude::*;
 
-use fly::{syntax::*, term::prime::Next, transitions::*};
-use solver::{conf::SolverConf, SatResp};
+use crate::{
+    budget::Budget,
+    quant::QuantifierConfig,
+    templates::{self, clause_to_term, KnownClauses},
+};
+use fly::{semantics::Model, syntax::*, term::prime::Next, transitions::*};
+use smtlib::proc::SolverError;
+use solver::{basics::SolverCancelers, conf::SolverConf, SatResp, SmtPid};
 use verify::{
     error::{AssertionFailure, FailureType, QueryError, SolveError},
     safety::InvariantAssertion,
@@ -28,6 +35,8 @@ struct Houdini {
     sig: Signature,
     init: Term,
     next: Term,
+    /// Cancels the solvers of all queries once the budget is exhausted.
+    cancelers: SolverCancelers<SmtPid>,
     /// These are the candidate invariants remaining.
     // (this is the only mutable state)
     invs: Vec<Term>,
@@ -38,13 +47,42 @@ pub enum HoudiniError {
     InitInvUnknown(String),
     InductiveInvUnknown(String),
     NotInductive,
+    /// The budget was exhausted before reaching the fixed point. Contains the remaining
+    /// candidate invariants, which are not yet inductive, the ones among them which are
+    /// known to be inductive relative to all candidates, and the counterexamples that
+    /// were found but not yet used to prune the candidates.
+    BudgetExhausted {
+        invs: Vec<Term>,
+        relatively_inductive: Vec<Term>,
+        ctis: Vec<Model>,
+    },
+}
+
+/// The result of a single Houdini query.
+enum Check {
+    Sat(Vec<Model>),
+    Unsat,
+    Unknown(String),
+    Canceled,
 }
 
 impl Houdini {
-    fn new(conf: SolverConf, sig: &Signature, assert: InvariantAssertion) -> Self {
+    fn new(
+        conf: SolverConf,
+        sig: &Signature,
+        assert: InvariantAssertion,
+        candidates: &[Term],
+        cancelers: SolverCancelers<SmtPid>,
+    ) -> Self {
         let mut invs = vec![assert.inv.x.clone()];
-        // TODO: support customization of initial candidate invariants
         invs.extend(assert.proof_invs.iter().map(|inv| inv.x.clone()));
+        invs.extend(
+            candidates
+                .iter()
+                .filter(|c| !invs.contains(c))
+                .cloned()
+                .collect_vec(),
+        );
         log::info!("Running Houdini, candidate invariants are:");
         for p in &invs {
             log::info!("    {p}")
@@ -55,26 +93,46 @@ impl Houdini {
             sig: sig.clone(),
             init: assert.init,
             next: assert.next,
+            cancelers,
             invs,
         }
     }
 
+    /// Check whether the conjunction of the given assertions is satisfiable.
+    fn check<'a>(&self, n_states: usize, assertions: impl IntoIterator<Item = &'a Term>) -> Check {
+        let mut solver = self.conf.solver(&self.sig, n_states);
+        if !self.cancelers.add_canceler(solver.pid()) {
+            return Check::Canceled;
+        }
+        for assertion in assertions {
+            solver.assert(assertion);
+        }
+        match solver.check_sat(HashMap::new()) {
+            Ok(SatResp::Sat) => match solver.get_model() {
+                Ok(states) => Check::Sat(states),
+                Err(SolverError::Killed) => Check::Canceled,
+                Err(err) => panic!("could not get model: {err}"),
+            },
+            Ok(SatResp::Unsat) => Check::Unsat,
+            Ok(SatResp::Unknown(m)) => Check::Unknown(m),
+            Err(SolverError::Killed) => Check::Canceled,
+            Err(err) => panic!("error in solver: {err}"),
+        }
+    }
+
     fn initiation_filter(&mut self) -> Result<(), HoudiniError> {
         log::info!("Checking initiation:");
         let mut not_implied: HashSet<Term> = HashSet::new();
+        let mut ctis = vec![];
         for q in &self.invs {
             if not_implied.contains(q) {
                 continue;
             };
             log::info!("    Checking {q}");
-            let mut solver = self.conf.solver(&self.sig, 1);
-            solver.assert(&self.init);
-            solver.assert(&Term::negate(q.clone()));
-            let resp = solver.check_sat(HashMap::new()).expect("error in solver");
-            match resp {
-                SatResp::Sat => {
+            let negated = Term::negate(q.clone());
+            match self.check(1, [&self.init, &negated]) {
+                Check::Sat(states) => {
                     log::info!("        Got model");
-                    let states = solver.get_model().expect("could not get model");
                     assert_eq!(states.len(), 1);
                     // TODO(oded): make 0 and 1 special constants for this use
                     assert_eq!(states[0].eval(&self.init), 1);
@@ -85,11 +143,19 @@ impl Houdini {
                             not_implied.insert(qq.clone());
                         }
                     }
+                    ctis.extend(states);
                 }
-                SatResp::Unsat => (),
-                SatResp::Unknown(m) => {
+                Check::Unsat => (),
+                Check::Unknown(m) => {
                     return Err(HoudiniError::InitInvUnknown(m));
                 }
+                Check::Canceled => {
+                    return Err(HoudiniError::BudgetExhausted {
+                        invs: self.invs.clone(),
+                        relatively_inductive: vec![],
+                        ctis,
+                    })
+                }
             }
         }
         self.invs.retain(|q| !not_implied.contains(q));
@@ -114,16 +180,10 @@ impl Houdini {
                         return None;
                     }
                 }
-                let mut solver = self.conf.solver(&self.sig, 2);
-                for p in &self.invs {
-                    solver.assert(p);
-                }
-                solver.assert(&self.next);
-                solver.assert(&Term::negate(Next::new(&self.sig).prime(q)));
-                let resp = solver.check_sat(HashMap::new()).expect("error in solver");
-                if matches!(resp, SatResp::Sat) {
+                let negated = Term::negate(Next::new(&self.sig).prime(q));
+                let resp = self.check(2, self.invs.iter().chain([&self.next, &negated]));
+                if let Check::Sat(states) = &resp {
                     log::info!("        Got model");
-                    let states = solver.get_model().expect("could not get model");
                     assert_eq!(states.len(), 2);
                     // TODO(oded): make 0 and 1 special constants for their use as Booleans
                     assert_eq!(states[1].eval(q), 0);
@@ -135,15 +195,33 @@ impl Houdini {
                         }
                     }
                 }
-                Some(resp)
+                Some((q, resp))
             })
             .flatten()
             .collect::<Vec<_>>();
-        for resp in inv_checks {
+        if inv_checks
+            .iter()
+            .any(|(_, resp)| matches!(resp, Check::Canceled))
+        {
+            let mut relatively_inductive = vec![];
+            let mut ctis = vec![];
+            for (q, resp) in inv_checks {
+                match resp {
+                    Check::Sat(mut states) => ctis.push(states.pop().unwrap()),
+                    Check::Unsat => relatively_inductive.push(q.clone()),
+                    Check::Unknown(_) | Check::Canceled => (),
+                }
+            }
+            return Err(HoudiniError::BudgetExhausted {
+                invs: self.invs.clone(),
+                relatively_inductive,
+                ctis,
+            });
+        }
+        for (_, resp) in inv_checks {
             match resp {
-                SatResp::Sat => {}
-                SatResp::Unsat => (),
-                SatResp::Unknown(m) => return Err(HoudiniError::InductiveInvUnknown(m)),
+                Check::Sat(_) | Check::Unsat | Check::Canceled => (),
+                Check::Unknown(m) => return Err(HoudiniError::InductiveInvUnknown(m)),
             }
         }
         // We have a `&mut Mutex` here, which proves that no other thread has a
@@ -158,18 +236,98 @@ impl Houdini {
         self.invs.retain(|q| !not_implied.contains(q));
         Ok(false)
     }
+
+    /// Return whether the conjunction of the given invariants is preserved by transitions.
+    /// If the budget is exhausted, they are not known to be, so this returns `false`.
+    fn is_inductive(&self, invs: &[Term]) -> Result<bool, HoudiniError> {
+        let resps = invs
+            .par_iter()
+            .map(|q| {
+                let negated = Term::negate(Next::new(&self.sig).prime(q));
+                self.check(2, invs.iter().chain([&self.next, &negated]))
+            })
+            .collect::<Vec<_>>();
+        let mut inductive = true;
+        for resp in resps {
+            match resp {
+                Check::Sat(_) | Check::Canceled => inductive = false,
+                Check::Unsat => (),
+                Check::Unknown(m) => return Err(HoudiniError::InductiveInvUnknown(m)),
+            }
+        }
+        Ok(inductive)
+    }
+
+    /// Remove invariants from the inductive fixed point as long as it stays inductive,
+    /// starting from the last candidate. The assertion itself is always kept.
+    /// If the budget is exhausted, the invariants are only partially minimized.
+    fn minimize(&mut self) -> Result<(), HoudiniError> {
+        log::info!("Minimizing {} invariants:", self.invs.len());
+        for i in (1..self.invs.len()).rev() {
+            if self.cancelers.is_canceled() {
+                log::info!("Budget exhausted, stopping minimization");
+                break;
+            }
+            let removed = self.invs.remove(i);
+            if self.is_inductive(&self.invs)? {
+                log::info!("    Removed {removed}");
+            } else {
+                self.invs.insert(i, removed);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Generate candidate invariants from templates: all clauses with at most `clause_size` literals
+/// over the atoms of the quantifier configuration, universally quantified. Only one clause is
+/// generated out of clauses that are equal up to renaming variables, and clauses which are false
+/// in one of the given states (which should be reachable) are left out.
+pub fn template_candidates(
+    cfg: &QuantifierConfig,
+    nesting: Option<usize>,
+    include_eq: bool,
+    clause_size: usize,
+    states: &[Model],
+) -> Vec<Term> {
+    let atoms = templates::atoms(cfg, nesting, include_eq);
+    let prefix = cfg.as_universal();
+
+    let mut seen = KnownClauses::new(&prefix, &atoms);
+    let mut candidates = vec![];
+    for clause in templates::clauses(&atoms, clause_size) {
+        if seen.contains(&clause) {
+            continue;
+        }
+        seen.insert(&clause);
+        let term = clause_to_term(&prefix, &atoms, &clause);
+        if states.iter().all(|state| state.eval(&term) == 1) {
+            candidates.push(term);
+        }
+    }
+
+    candidates
 }
 
 /// Attempt to infer inductive invariants to prove `assert`.
 ///
 /// On success, returns a list of invariants which are together inductive and
 /// include `assert.inv`.
+///
+/// The candidate invariants are `assert.inv`, the invariants of the proof, and the given
+/// `candidates`. If there are any of the latter, the result is minimized to a subset which is
+/// still inductive.
+///
+/// Canceling `cancelers` kills all in-flight queries, and then the inference returns
+/// [`HoudiniError::BudgetExhausted`] with the candidates remaining at that point.
 pub fn infer(
     conf: &SolverConf,
     sig: &Signature,
     assert: &InvariantAssertion,
+    candidates: &[Term],
+    cancelers: SolverCancelers<SmtPid>,
 ) -> Result<Vec<Term>, HoudiniError> {
-    let mut state = Houdini::new(conf.clone(), sig, assert.clone());
+    let mut state = Houdini::new(conf.clone(), sig, assert.clone(), candidates, cancelers);
     state.initiation_filter()?;
 
     log::info!("Candidate invariants are:");
@@ -195,21 +353,43 @@ pub fn infer(
     if state.invs.is_empty() || state.invs[0] != assert.inv.x {
         return Err(HoudiniError::NotInductive);
     }
+    if !candidates.is_empty() {
+        state.minimize()?;
+    }
     Ok(state.invs)
 }
 
 /// Prove the assertions in a module using Houdini invariant inference.
 pub fn infer_module(conf: &SolverConf, m: &Module) -> Result<(), SolveError> {
+    infer_module_with_candidates(conf, m, &[], &Budget::default())
+}
+
+/// Like [`infer_module`], but also start from the given candidate invariants,
+/// such as the ones from [`template_candidates`], and stop once the budget is exhausted.
+pub fn infer_module_with_candidates(
+    conf: &SolverConf,
+    m: &Module,
+    candidates: &[Term],
+    budget: &Budget,
+) -> Result<(), SolveError> {
     // TODO: this is highly redundant with verify_module, some refactoring is
     // needed to separate the generic module processing with what kind of
     // inference/proof process we want for each assertion.
-    infer_destructured_module(conf, &extract(m).unwrap(), &m.signature)
+    let module = extract(m).unwrap();
+    let cancelers = SolverCancelers::new();
+    budget
+        .enforce(&cancelers, || {
+            infer_destructured_module(conf, &module, &m.signature, candidates, &cancelers)
+        })
+        .0
 }
 
 fn infer_destructured_module(
     conf: &SolverConf,
     module: &DestructuredModule,
     signature: &Signature,
+    candidates: &[Term],
+    cancelers: &SolverCancelers<SmtPid>,
 ) -> Result<(), SolveError> {
     let inits = &module.inits;
     let transitions = &module.transitions;
@@ -221,7 +401,7 @@ fn infer_destructured_module(
         if let Ok(assert) =
             InvariantAssertion::for_assert(signature, inits, transitions, &axioms, proof)
         {
-            let res = infer(conf, signature, &assert);
+            let res = infer(conf, signature, &assert, candidates, cancelers.clone());
             match res {
                 Ok(invs) => {
                     println!("# inferred invariant:");
@@ -243,6 +423,29 @@ fn infer_destructured_module(
                         reason: FailureType::NotInductive,
                         error: QueryError::Unknown(m),
                     },
+                    HoudiniError::BudgetExhausted {
+                        invs,
+                        relatively_inductive,
+                        ctis,
+                    } => {
+                        println!("# budget exhausted, the candidates are not yet inductive:");
+                        println!("assert always {}", &proof.safety.x);
+                        println!("proof {{");
+                        for inv in invs {
+                            println!("  invariant {inv}");
+                        }
+                        println!("}}");
+                        println!("# inductive relative to the candidates:");
+                        for inv in relatively_inductive {
+                            println!("#   {inv}");
+                        }
+                        println!("# outstanding counterexamples: {}", ctis.len());
+                        AssertionFailure {
+                            loc: proof.safety.span,
+                            reason: FailureType::NotInductive,
+                            error: QueryError::Unknown("budget exhausted".to_string()),
+                        }
+                    }
                     HoudiniError::NotInductive => AssertionFailure {
                         loc: proof.safety.span,
                         reason: FailureType::NotInductive,
@@ -267,3 +470,40 @@ fn infer_destructured_module(
         Err(errors)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fly::sorts::sort_check_module;
+    use std::sync::Arc;
+
+    #[test]
+    fn houdini_templates() {
+        let source = include_str!("../../temporal-verifier/examples/lockserver.fly");
+        let mut m = fly::parser::parse(source).unwrap();
+        sort_check_module(&mut m).unwrap();
+        let sig = Arc::new(m.signature.clone());
+        let cfg = QuantifierConfig::new(sig, vec![None], vec![0], &[2]);
+
+        let all = template_candidates(&cfg, None, true, 2, &[]);
+        let all = all.iter().map(|c| c.to_string()).collect_vec();
+        assert_eq!(all.len(), 108);
+        // clauses that are equal up to renaming are generated once
+        let renamed = [
+            "forall node_1:node, node_2:node. lock_msg(node_1) | !lock_msg(node_2)",
+            "forall node_1:node, node_2:node. !lock_msg(node_1) | lock_msg(node_2)",
+        ];
+        assert_eq!(
+            all.iter().filter(|c| renamed.contains(&c.as_str())).count(),
+            1
+        );
+
+        let universe = HashMap::from([("node".to_string(), 2)]);
+        let states = bounded::set::reachable(&m, &universe, None, false).unwrap();
+        let reachable = template_candidates(&cfg, None, true, 2, &states);
+        assert_eq!(reachable.len(), 9);
+        assert!(reachable.iter().any(
+            |c| c.to_string() == "forall node_1:node. !server_holds_lock | !holds_lock(node_1)"
+        ));
+    }
+}
@@ -18,6 +18,7 @@ use fly::term::subst::Substitution;
 use crate::{
     atoms::{Literal, RestrictedAtoms},
     basics::{CexResult, FOModule, InferenceConfig},
+    events::{CtiKind, Event, EventLog},
     hashmap::{HashMap, HashSet},
     subsume::OrderSubsumption,
     weaken::{Domain, LemmaQf, LemmaSet, WeakenLemmaSet},
@@ -827,6 +828,8 @@ where
     safety_core: Option<HashSet<usize>>,
     /// The time of creation of the frame (for logging purposes)
     start_time: Instant,
+    /// Where to report the progress of weakening the frame
+    events: EventLog,
 }
 
 impl<O, L, B> InductionFrame<O, L, B>
@@ -842,6 +845,7 @@ where
         domains: Vec<Domain<L>>,
         extend: Option<(usize, usize)>,
     ) -> Self {
+        let events = infer_cfg.events.clone();
         let mut weaken_lemmas: WeakenLemmaSet<O, L, B> =
             WeakenLemmaSet::new(Arc::new(infer_cfg.cfg.clone()), infer_cfg, atoms, domains);
         weaken_lemmas.init();
@@ -859,6 +863,7 @@ where
             ctis: VecDeque::new(),
             safety_core: None,
             start_time: Instant::now(),
+            events,
         }
     }
 
@@ -909,6 +914,21 @@ where
         )
     }
 
+    /// Get the lemmas in the frame which are known to be inductive relative to the frame,
+    /// i.e., which hold in any post-state of a transition from the frame.
+    pub fn relatively_inductive(&self) -> Vec<Term> {
+        self.lemmas
+            .as_iter()
+            .filter(|(prefix, body, _)| self.blocked.subsumes(prefix, body))
+            .map(|(_, _, id)| self.lemmas.id_to_term(&id))
+            .collect()
+    }
+
+    /// Get the CTI's which were found but whose traces haven't been extended yet.
+    pub fn outstanding_ctis(&self) -> Vec<Model> {
+        self.ctis.iter().cloned().collect()
+    }
+
     /// Add details about the frame to the given [`Display`].
     pub fn add_details<D: Display>(&self, d: D) -> String {
         format!(
@@ -930,6 +950,15 @@ where
         log::debug!("{}", self.add_details(d));
     }
 
+    /// Report that the frame was weakened using counterexamples of the given kind.
+    fn weakened(&self, kind: CtiKind) {
+        self.events.emit(|| Event::LemmasWeakened {
+            kind,
+            frame_size: self.len(),
+            weaken_size: self.weaken_len(),
+        });
+    }
+
     /// Get an initial state which violates one of the frame's lemmas.
     fn init_cex<S: BasicSolver>(&mut self, fo: &FOModule, solver: &S) -> Option<Model> {
         let blocked_lock = RwLock::new((
@@ -983,8 +1012,11 @@ where
         match self.init_cex(fo, solver) {
             Some(cti) => {
                 self.log_info("CTI found, type=initial");
+                self.events
+                    .emit(|| Event::cti_found(CtiKind::Initial, std::slice::from_ref(&cti)));
                 self.log_info("Weakening...");
                 self.weaken_lemmas.weaken(&cti);
+                self.weakened(CtiKind::Initial);
 
                 true
             }
@@ -1035,6 +1067,9 @@ where
                 .into_par_iter()
                 .find_first(|i| self.weaken_lemmas.unsat(&samples[*i]))
             {
+                self.events.emit(|| {
+                    Event::cti_found(CtiKind::Simulated, std::slice::from_ref(&samples[i]))
+                });
                 assert!(self.weaken_lemmas.weaken(&samples[i]));
                 self.log_debug(format!("Weakened ({} / {samples_len}).", i + 1));
                 new_ctis.push_back(samples[i].clone());
@@ -1046,6 +1081,34 @@ where
 
         self.log_info("Updating frame...");
         self.update();
+        self.weakened(CtiKind::Simulated);
+    }
+
+    /// Weaken the frame's lemmas using the given states, which are known to be reachable,
+    /// without making any SMT queries. Return the number of the frame's lemmas that were pruned.
+    pub fn weaken_reachable(&mut self, states: &[Model]) -> usize {
+        self.log_info(format!(
+            "Weakening using {} reachable states...",
+            states.len()
+        ));
+        let mut idx = 0;
+        while let Some(i) = (idx..states.len())
+            .into_par_iter()
+            .find_first(|i| self.weaken_lemmas.unsat(&states[*i]))
+        {
+            assert!(self.weaken_lemmas.weaken(&states[i]));
+            idx = i + 1;
+        }
+
+        let pruned = self
+            .lemmas
+            .as_iter()
+            .filter(|(prefix, body, _)| !self.weaken_lemmas.contains(prefix, body))
+            .count();
+        self.log_info("Updating frame...");
+        self.update();
+        self.weakened(CtiKind::Sampled);
+        pruned
     }
 
     /// Get an post-state of the frame which violates one of the frame's lemmas.
@@ -1156,10 +1219,13 @@ where
         match self.trans_cex(fo, solver) {
             Some(cti) => {
                 self.log_info("CTI found, type=transition");
+                self.events
+                    .emit(|| Event::cti_found(CtiKind::Transition, std::slice::from_ref(&cti)));
                 self.log_info("Weakening...");
                 self.weaken_lemmas.weaken(&cti);
                 self.log_info("Updating frame...");
                 self.update();
+                self.weakened(CtiKind::Transition);
 
                 true
             }
@@ -1179,14 +1245,20 @@ where
         }
 
         let (ids, terms): (Vec<usize>, Vec<Term>) = self.lemmas.to_terms_ids().unzip();
-        match fo.trans_safe_cex(solver, &terms) {
+        let safe = match fo.trans_safe_cex(solver, &terms) {
             CexResult::Cex(_) => false,
             CexResult::UnsatCore(core) => {
                 self.safety_core = Some(core.into_iter().map(|i| ids[i]).collect());
                 true
             }
-            _ => panic!("safety check failed"),
-        }
+            CexResult::Canceled => return false,
+            CexResult::Unknown(_) => panic!("safety check failed"),
+        };
+        self.events.emit(|| Event::SafetyCheck {
+            safe,
+            frame_size: self.len(),
+        });
+        safe
     }
 
     fn remove_lemma(&mut self, id: &usize) {
@@ -1267,3 +1339,82 @@ where
         advanced
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        atoms::{restrict, restrict_by_prefix},
+        basics::QfBody,
+        quant::QuantifierConfig,
+        subsume::Cnf,
+        templates,
+    };
+    use fly::sorts::sort_check_module;
+    use std::collections::HashMap;
+
+    #[test]
+    fn frame_weaken_reachable() {
+        let source = include_str!("../../temporal-verifier/examples/lockserver.fly");
+        let mut m = fly::parser::parse(source).unwrap();
+        sort_check_module(&mut m).unwrap();
+        let sig = Arc::new(m.signature.clone());
+        let infer_cfg = Arc::new(InferenceConfig {
+            fname: "lockserver.fly".to_string(),
+            fallback: false,
+            workers: None,
+            cfg: QuantifierConfig::new(sig, vec![None], vec![0], &[2]),
+            qf_body: QfBody::CNF,
+            max_size: 2,
+            max_existentials: Some(0),
+            clauses: Some(1),
+            clause_size: Some(3),
+            cubes: None,
+            cube_size: None,
+            non_unit: None,
+            nesting: None,
+            include_eq: true,
+            disj: true,
+            gradual_smt: false,
+            minimal_smt: false,
+            extend_width: None,
+            extend_depth: None,
+            sample_bound: None,
+            sample_depth: None,
+            until_safe: false,
+            abort_unsafe: false,
+            auto_quant: false,
+            no_search: true,
+            growth_factor: None,
+            events: EventLog::default(),
+        });
+
+        let atoms = templates::atoms(&infer_cfg.cfg, None, true).atoms;
+        let prefix = Arc::new(infer_cfg.cfg.as_universal());
+        let restricted = Arc::new(restrict_by_prefix(&atoms, &infer_cfg.cfg, &prefix));
+        let lemma_qf = Arc::new(LemmaCnf::new(
+            &infer_cfg,
+            restricted.clone(),
+            prefix.non_universal_vars(),
+        ));
+        let mut frame: InductionFrame<Cnf<Literal>, LemmaCnf, _> = InductionFrame::new(
+            infer_cfg.clone(),
+            Arc::new(restrict(&atoms, |_| true)),
+            vec![(prefix, lemma_qf, restricted)],
+            None,
+        );
+
+        let universe = HashMap::from([("node".to_string(), 2)]);
+        let states = bounded::set::reachable(&m, &universe, None, false).unwrap();
+        let before = frame.len();
+        let pruned = frame.weaken_reachable(&states);
+        assert!(0 < pruned && pruned <= before);
+        let proof = frame.proof();
+        assert!(states
+            .iter()
+            .all(|state| proof.iter().all(|lemma| state.eval(lemma) == 1)));
+
+        // the frame already holds in all of these states
+        assert_eq!(frame.weaken_reachable(&states), 0);
+    }
+}
@@ -21,12 +21,18 @@
 
 pub mod atoms;
 pub mod basics;
+pub mod budget;
+pub mod compare;
+pub mod events;
+pub mod finite;
 pub mod fixpoint;
 pub mod hashmap;
 pub mod houdini;
 pub mod lemma;
 pub mod marco;
+pub mod minimize;
 pub mod quant;
 pub mod subsume;
+pub mod templates;
 pub mod updr;
 pub mod weaken;
@@ -0,0 +1,266 @@
+// Copyright 2022-2023 VMware, Inc.
+// SPDX-License-Identifier: BSD-2-Clause
+
+//! Find the redundant invariants of handwritten proofs.
+//!
+//! A subset of a proof's invariants is a proof if, together with the safety property, it is
+//! inductive. This isn't monotone in the subset, so instead we check whether the safety property
+//! survives in the maximal inductive subset of the invariants and the safety property (computed
+//! as in Houdini). This is monotone, and its minimal subsets are exactly the minimal proofs,
+//! which are enumerated using [`crate::marco`].
+
+use std::collections::HashSet;
+use std::sync::Mutex;
+
+use itertools::Itertools;
+use rayon::prelude::*;
+
+use crate::basics::{CexResult, FOModule};
+use crate::marco::{marco, MssOrMus};
+use fly::{
+    syntax::{Module, Term},
+    transitions::extract,
+};
+use solver::basics::{BasicSolver, SolverCancelers};
+
+/// The analysis of the invariants of a single proof.
+pub struct ProofAnalysis {
+    /// The safety property proven
+    pub safety: Term,
+    /// The invariants of the proof
+    pub invariants: Vec<Term>,
+    /// The minimal subsets of the invariants which prove the safety property, given by indices
+    pub minimal_proofs: Vec<Vec<usize>>,
+    /// The invariants implied by the other invariants and the safety property
+    pub implied: Vec<usize>,
+}
+
+/// Checks whether subsets of a proof's invariants prove its safety property.
+struct SubsetChecker<'a, S: BasicSolver> {
+    /// The module, with the safety properties of previous proofs as axioms
+    fo: &'a FOModule,
+    solver: &'a S,
+    /// The invariants, followed by the safety property
+    lemmas: Vec<Term>,
+    /// For each lemma, sets of lemmas known to preserve it
+    cores: Vec<Mutex<Vec<HashSet<usize>>>>,
+    /// For each lemma, sets of lemmas known not to preserve it
+    failures: Vec<Mutex<Vec<HashSet<usize>>>>,
+}
+
+impl<S: BasicSolver> SubsetChecker<'_, S> {
+    /// Check whether the given lemmas are preserved by every transition,
+    /// for the lemma at `index`.
+    fn preserved(&self, index: usize, lemmas: &HashSet<usize>) -> bool {
+        if self.cores[index]
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|core| core.is_subset(lemmas))
+        {
+            return true;
+        }
+        if self.failures[index]
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|failure| lemmas.is_subset(failure))
+        {
+            return false;
+        }
+
+        let hyp_lemmas = lemmas.iter().copied().sorted().collect_vec();
+        let hyp = hyp_lemmas
+            .iter()
+            .map(|i| self.lemmas[*i].clone())
+            .collect_vec();
+        match self.fo.trans_cex(
+            self.solver,
+            &hyp,
+            &self.lemmas[index],
+            false,
+            None::<SolverCancelers<SolverCancelers<S::Canceler>>>,
+            false,
+        ) {
+            CexResult::UnsatCore(core) => {
+                let core = core.into_iter().map(|i| hyp_lemmas[i]).collect();
+                self.cores[index].lock().unwrap().push(core);
+                true
+            }
+            CexResult::Cex(_) => {
+                self.failures[index].lock().unwrap().push(lemmas.clone());
+                false
+            }
+            CexResult::Unknown(reason) => {
+                log::warn!(
+                    "could not check consecution of {}: {reason}",
+                    self.lemmas[index]
+                );
+                false
+            }
+            CexResult::Canceled => unreachable!("proof minimization queries are never canceled"),
+        }
+    }
+
+    /// Check whether the safety property survives in the maximal inductive subset
+    /// of the given invariants and the safety property.
+    fn proves_safety(&self, subset: &[bool]) -> bool {
+        let safety = self.lemmas.len() - 1;
+        let mut lemmas: HashSet<usize> = (0..subset.len())
+            .filter(|i| subset[*i])
+            .chain([safety])
+            .collect();
+        loop {
+            let failing = lemmas
+                .par_iter()
+                .filter(|i| !self.preserved(**i, &lemmas))
+                .copied()
+                .collect::<Vec<_>>();
+            if failing.contains(&safety) {
+                return false;
+            }
+            if failing.is_empty() {
+                return true;
+            }
+            for i in failing {
+                lemmas.remove(&i);
+            }
+        }
+    }
+}
+
+/// Find the minimal subsets of each proof's invariants which still prove its safety property,
+/// and the invariants implied by the others. The module is assumed to verify.
+pub fn minimize_proofs<S: BasicSolver>(m: &Module, solver: &S) -> Vec<ProofAnalysis> {
+    let proofs = extract(m).unwrap().proofs;
+    let mut assumed = vec![];
+    let mut analyses = vec![];
+    for proof in &proofs {
+        // as in verification, later proofs may assume the safety of earlier ones
+        // in all states, so they are added as axioms
+        let mut fo = FOModule::new(m, false, false, false);
+        fo.module.axioms.extend(assumed.iter().cloned());
+        let invariants = proof
+            .invariants
+            .iter()
+            .map(|inv| inv.x.clone())
+            .collect_vec();
+        let n = invariants.len();
+        let mut lemmas = invariants.clone();
+        lemmas.push(proof.safety.x.clone());
+        let checker = SubsetChecker {
+            fo: &fo,
+            solver,
+            cores: (0..lemmas.len()).map(|_| Mutex::new(vec![])).collect(),
+            failures: (0..lemmas.len()).map(|_| Mutex::new(vec![])).collect(),
+            lemmas,
+        };
+
+        let minimal_proofs = if checker.proves_safety(&vec![false; n]) {
+            vec![vec![]]
+        } else {
+            // marco expects a function which holds for small subsets, so we negate ours,
+            // and then its minimal unsatisfiable subsets are the minimal proofs
+            marco(|subset| !checker.proves_safety(subset), n)
+                .filter_map(|mss_or_mus| match mss_or_mus {
+                    MssOrMus::Mus(subset) => Some((0..n).filter(|i| subset[*i]).collect_vec()),
+                    MssOrMus::Mss(_) => None,
+                })
+                .sorted_by_key(|subset| (subset.len(), subset.clone()))
+                .collect()
+        };
+
+        let implied = (0..n)
+            .into_par_iter()
+            .filter(|i| {
+                let mut hyp = vec![proof.safety.x.clone()];
+                hyp.extend((0..n).filter(|j| j != i).map(|j| invariants[j].clone()));
+                matches!(
+                    fo.implication_cex(solver, &hyp, &invariants[*i]),
+                    CexResult::UnsatCore(_)
+                )
+            })
+            .collect();
+
+        analyses.push(ProofAnalysis {
+            safety: proof.safety.x.clone(),
+            invariants,
+            minimal_proofs,
+            implied,
+        });
+        assumed.push(proof.safety.x.clone());
+    }
+    analyses
+}
+
+impl ProofAnalysis {
+    /// Print the minimal proofs and the implied invariants.
+    pub fn report(&self) {
+        println!("# proof of {}", self.safety);
+        println!("Found {} minimal proof(s)", self.minimal_proofs.len());
+        for (i, proof) in self.minimal_proofs.iter().enumerate() {
+            println!(
+                "minimal proof {} ({} of {} invariants):",
+                i + 1,
+                proof.len(),
+                self.invariants.len()
+            );
+            println!("proof {{");
+            for j in proof {
+                println!("  invariant {}", self.invariants[*j]);
+            }
+            println!("}}");
+        }
+
+        let needed: HashSet<usize> = self.minimal_proofs.iter().flatten().copied().collect();
+        let unneeded = (0..self.invariants.len())
+            .filter(|i| !needed.contains(i))
+            .collect_vec();
+        if !unneeded.is_empty() {
+            println!("Invariants in no minimal proof:");
+            for i in unneeded {
+                println!("  invariant {}", self.invariants[i]);
+            }
+        }
+        if !self.implied.is_empty() {
+            println!("Invariants implied by the others and the safety property:");
+            for i in &self.implied {
+                println!("  invariant {}", self.invariants[*i]);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fly::sorts::sort_check_module;
+    use solver::{backends::SolverType, basics::SingleSolver, conf::SolverConf};
+    use std::fs;
+
+    #[test]
+    fn test_minimize_redundant_invariants() {
+        let file = fs::read_to_string(
+            "../temporal-verifier/tests/examples/success/redundant_invariants.fly",
+        )
+        .expect("could not read input");
+        let mut m = fly::parser::parse(&file).expect("parse error");
+        sort_check_module(&mut m).expect("sort checking failed");
+        let solver = SingleSolver::new(SolverConf::new(
+            SolverType::Z3,
+            false,
+            &"redundant_invariants.fly".to_string(),
+            0,
+            0,
+        ));
+
+        let analyses = minimize_proofs(&m, &solver);
+        assert_eq!(analyses.len(), 2);
+        // either y or z proves x, and w is in no minimal proof
+        assert_eq!(analyses[0].minimal_proofs, vec![vec![0], vec![1]]);
+        assert!(analyses[0].implied.is_empty());
+        // v is preserved only if x holds after the transition, so this relies on the
+        // first safety property being assumed in both states
+        assert_eq!(analyses[1].minimal_proofs, vec![Vec::<usize>::new()]);
+    }
+}
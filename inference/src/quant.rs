@@ -12,8 +12,9 @@ use std::fmt::Debug;
 use std::sync::Arc;
 
 use crate::basics::InferenceConfig;
-use fly::syntax::{Binder, Quantifier, Signature, Sort, Term};
+use fly::syntax::{BinOp, Binder, Quantifier, Signature, Sort, Term, UOp};
 use fly::term::subst::Substitution;
+use fly::transitions::DestructuredModule;
 
 /// Generate the variable names for this [`QuantifierSequence`]. The names are grouped
 /// and ordered based on their position in the sequence.
@@ -420,12 +421,140 @@ impl Ord for QuantifierPrefix {
     }
 }
 
+/// Compute the quantifier alternation graph of a module, as a set of edges between sort indices.
+/// There is an edge from sort `a` to sort `b` if a function maps elements of `a` to elements of `b`,
+/// or if an assumption or safety property of the module quantifies over `b` existentially
+/// in the scope of a universal quantifier over `a`.
+pub fn alternation_graph(
+    module: &DestructuredModule,
+    signature: &Signature,
+) -> HashSet<(usize, usize)> {
+    let mut edges = HashSet::default();
+    for relation in &signature.relations {
+        if let Sort::Uninterpreted(_) = relation.sort {
+            for arg in relation.args.iter().filter(|arg| **arg != Sort::Bool) {
+                edges.insert((signature.sort_idx(arg), signature.sort_idx(&relation.sort)));
+            }
+        }
+    }
+
+    let assumptions = module
+        .axioms
+        .iter()
+        .chain(&module.inits)
+        .chain(&module.transitions);
+    for term in assumptions {
+        alternation_edges(signature, term, Some(true), &mut vec![], &mut edges);
+    }
+    // Safety properties are both assumed and proven, so they appear with both polarities.
+    for proof in &module.proofs {
+        alternation_edges(signature, &proof.safety.x, None, &mut vec![], &mut edges);
+    }
+
+    edges.retain(|(a, b)| a != b);
+    edges
+}
+
+/// Add the alternation edges of a term to `edges`, where `polarity` is whether the term appears
+/// positively or negatively (or `None` for both), and `universals` are the sorts of the enclosing
+/// universal quantifiers.
+fn alternation_edges(
+    signature: &Signature,
+    term: &Term,
+    polarity: Option<bool>,
+    universals: &mut Vec<usize>,
+    edges: &mut HashSet<(usize, usize)>,
+) {
+    let flipped = polarity.map(|p| !p);
+    let subterms: Vec<(&Term, Option<bool>)> = match term {
+        Term::Literal(_) | Term::Id(_) => vec![],
+        Term::App(_, _, args) => args.iter().map(|arg| (arg, None)).collect(),
+        Term::UnaryOp(UOp::Not, t) => vec![(t, flipped)],
+        Term::UnaryOp(_, t) => vec![(t, polarity)],
+        Term::BinOp(BinOp::Implies, t1, t2) => vec![(t1, flipped), (t2, polarity)],
+        Term::BinOp(BinOp::Until | BinOp::Since, t1, t2) => vec![(t1, polarity), (t2, polarity)],
+        Term::BinOp(_, t1, t2) => vec![(t1, None), (t2, None)],
+        Term::NAryOp(_, ts) => ts.iter().map(|t| (t, polarity)).collect(),
+        Term::Ite { cond, then, else_ } => vec![(cond, None), (then, polarity), (else_, polarity)],
+        Term::Quantified {
+            quantifier,
+            binders,
+            body,
+        } => {
+            let sorts = binders
+                .iter()
+                .filter(|binder| binder.sort != Sort::Bool)
+                .map(|binder| signature.sort_idx(&binder.sort))
+                .collect_vec();
+            let (universal, existential) = match (quantifier, polarity) {
+                (_, None) => (true, true),
+                (Quantifier::Forall, Some(p)) => (p, !p),
+                (Quantifier::Exists, Some(p)) => (!p, p),
+            };
+            if existential {
+                for sort in &sorts {
+                    edges.extend(universals.iter().map(|u| (*u, *sort)));
+                }
+            }
+            let len = universals.len();
+            if universal {
+                universals.extend(&sorts);
+            }
+            alternation_edges(signature, body, polarity, universals, edges);
+            universals.truncate(len);
+            vec![]
+        }
+    };
+
+    for (t, polarity) in subterms {
+        alternation_edges(signature, t, polarity, universals, edges);
+    }
+}
+
+/// Return the orderings of the signature's sorts that respect the given alternation graph,
+/// i.e., where every edge goes from an earlier sort to a later one. If there are no such orderings
+/// because the graph has a cycle, return all orderings.
+pub fn sort_orderings(signature: &Signature, edges: &HashSet<(usize, usize)>) -> Vec<Vec<usize>> {
+    let n = signature.sorts.len();
+    let respects = |ordering: &Vec<usize>| {
+        let position = |sort| ordering.iter().position(|s| *s == sort).unwrap();
+        edges.iter().all(|(a, b)| position(*a) < position(*b))
+    };
+    let orderings = (0..n).permutations(n).filter(respects).collect_vec();
+    if orderings.is_empty() {
+        (0..n).permutations(n).collect()
+    } else {
+        orderings
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use fly::{parser, term::subst::Substitution};
+    use fly::{parser, sorts::sort_check_module, term::subst::Substitution, transitions::extract};
     use std::collections::HashMap;
 
+    #[test]
+    fn test_sort_orderings() {
+        let source = include_str!("../../temporal-verifier/examples/consensus_epr.fly");
+        let mut m = parser::parse(source).unwrap();
+        sort_check_module(&mut m).unwrap();
+        let d = extract(&m).unwrap();
+
+        // the quorum intersection axiom has an existential node in the scope of universal quorums
+        let (node, quorum, value) = (0, 1, 2);
+        let edges = alternation_graph(&d, &m.signature);
+        assert_eq!(edges, HashSet::from_iter([(quorum, node)]));
+        assert_eq!(
+            sort_orderings(&m.signature, &edges),
+            vec![
+                vec![quorum, node, value],
+                vec![quorum, value, node],
+                vec![value, quorum, node],
+            ]
+        );
+    }
+
     #[test]
     fn test_permutations() {
         let signature = parser::parse_signature(
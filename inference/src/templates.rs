@@ -0,0 +1,104 @@
+// Copyright 2022-2023 VMware, Inc.
+// SPDX-License-Identifier: BSD-2-Clause
+
+//! Clause templates: the universally quantified clauses over the atoms of a quantifier
+//! configuration, from which [`crate::houdini`] and [`crate::finite`] generate candidate
+//! invariants.
+
+use itertools::Itertools;
+use std::sync::Arc;
+
+use crate::{
+    atoms::{restrict, Atoms, Literal, RestrictedAtoms},
+    hashmap::HashSet,
+    quant::{QuantifierConfig, QuantifierPrefix},
+};
+use fly::{syntax::Term, term::subst::Substitution};
+
+/// Construct the atoms in the given quantifier configuration. Unlike [`Atoms::new`],
+/// this does not use a solver to remove atoms that are always true or always false.
+pub fn atoms(cfg: &QuantifierConfig, nesting: Option<usize>, include_eq: bool) -> RestrictedAtoms {
+    let to_term = cfg.atoms(nesting, include_eq);
+    let to_index = to_term
+        .iter()
+        .enumerate()
+        .map(|(index, term)| (term.clone(), index))
+        .collect();
+    restrict(&Arc::new(Atoms { to_term, to_index }), |_| true)
+}
+
+/// All clauses with at most `clause_size` literals over the allowed atoms, smallest first.
+pub fn clauses(atoms: &RestrictedAtoms, clause_size: usize) -> impl Iterator<Item = Vec<Literal>> {
+    let allowed = atoms.allowed.iter().copied().sorted().collect_vec();
+    (1..=clause_size).flat_map(move |size| {
+        allowed
+            .clone()
+            .into_iter()
+            .combinations(size)
+            .flat_map(move |combination| {
+                (0..size)
+                    .map(|_| [true, false])
+                    .multi_cartesian_product()
+                    .map(move |signs| combination.iter().copied().zip(signs).collect_vec())
+            })
+    })
+}
+
+/// Convert a clause to a term, universally quantified by the given prefix.
+pub fn clause_to_term(
+    prefix: &QuantifierPrefix,
+    atoms: &RestrictedAtoms,
+    clause: &[Literal],
+) -> Term {
+    prefix.quantify(Term::or(
+        clause.iter().map(|literal| atoms.to_term(literal).unwrap()),
+    ))
+}
+
+/// A set of clauses which is closed under renaming the variables of a quantifier prefix,
+/// used to generate only one clause out of clauses that are equal up to renaming.
+pub struct KnownClauses<'a> {
+    atoms: &'a RestrictedAtoms,
+    permutations: Vec<Substitution>,
+    clauses: HashSet<Vec<Literal>>,
+}
+
+impl<'a> KnownClauses<'a> {
+    /// Create an empty set of clauses over the given atoms.
+    pub fn new(prefix: &QuantifierPrefix, atoms: &'a RestrictedAtoms) -> Self {
+        Self {
+            atoms,
+            permutations: prefix.permutations(0, None),
+            clauses: HashSet::default(),
+        }
+    }
+
+    /// Add a clause, in every renaming of its variables.
+    pub fn insert(&mut self, clause: &[Literal]) {
+        for substitution in &self.permutations {
+            let renamed: Option<Vec<Literal>> = clause
+                .iter()
+                .map(|(a, b)| self.atoms.substitute(*a, substitution).map(|a| (a, *b)))
+                .collect();
+            if let Some(renamed) = renamed {
+                self.clauses.insert(renamed.into_iter().sorted().collect());
+            }
+        }
+    }
+
+    /// Whether the clause is equal to a known clause up to renaming.
+    pub fn contains(&self, clause: &[Literal]) -> bool {
+        self.clauses.contains(clause)
+    }
+
+    /// Whether some known clause is a subset of the clause, up to renaming.
+    pub fn subsumes(&self, clause: &[Literal]) -> bool {
+        (1..=clause.len()).any(|k| {
+            clause
+                .iter()
+                .copied()
+                .combinations(k)
+                .any(|sub| self.clauses.contains(&sub))
+        })
+    }
+}
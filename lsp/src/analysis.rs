@@ -0,0 +1,509 @@
+// Copyright 2022-2023 VMware, Inc.
+// SPDX-License-Identifier: BSD-2-Clause
+
+//! Analyses of a single fly document, independent of the LSP transport.
+//!
+//! Positions in LSP are (line, UTF-16 column) pairs while fly spans are byte
+//! offsets; [`position`] and [`offset`] convert between the two.
+//!
+//! The AST only records spans for assertions and invariants, so declarations
+//! are located by a lexical scan of the source (see [`declarations`]), which
+//! follows the same item structure as the recovering parser.
+
+use std::collections::BTreeSet;
+
+use lsp_types::{
+    Diagnostic, DiagnosticRelatedInformation, DiagnosticSeverity, DocumentSymbol, Location,
+    Position, Range, SymbolKind, Url,
+};
+
+use fly::{
+    parser::{parse, parse_recovering, SyntaxError},
+    sorts::{sort_check_module, sort_check_module_recovering},
+    syntax::{Binder, Module, Proof, Sort, Span, Term, ThmStmt},
+    transitions::extract,
+};
+use solver::conf::SolverConf;
+use verify::module::verify_destructured_module;
+
+/// The name used for diagnostics produced by this server.
+const SOURCE: &str = "flyvy";
+
+/// Convert a byte offset in `text` to an LSP position.
+pub fn position(text: &str, offset: usize) -> Position {
+    let offset = offset.min(text.len());
+    let before = &text[..offset];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    Position {
+        line: before.matches('\n').count() as u32,
+        character: before[line_start..].encode_utf16().count() as u32,
+    }
+}
+
+/// Convert an LSP position to a byte offset in `text`, clamping positions past
+/// the end of a line to the end of that line.
+pub fn offset(text: &str, pos: Position) -> usize {
+    let mut line_start = 0;
+    for _ in 0..pos.line {
+        match text[line_start..].find('\n') {
+            Some(i) => line_start += i + 1,
+            None => return text.len(),
+        }
+    }
+    let line = text[line_start..].split('\n').next().unwrap();
+    let mut units = 0;
+    for (i, c) in line.char_indices() {
+        if units >= pos.character as usize {
+            return line_start + i;
+        }
+        units += c.len_utf16();
+    }
+    line_start + line.len()
+}
+
+/// Convert a byte range in `text` to an LSP range.
+pub fn range(text: &str, start: usize, end: usize) -> Range {
+    Range {
+        start: position(text, start),
+        end: position(text, end),
+    }
+}
+
+fn span_range(text: &str, span: Option<Span>) -> Range {
+    match span {
+        Some(span) => range(text, span.start, span.end),
+        // errors without location information are reported at the start of
+        // the file
+        None => range(text, 0, 0),
+    }
+}
+
+fn error(range: Range, message: String) -> Diagnostic {
+    Diagnostic {
+        range,
+        severity: Some(DiagnosticSeverity::ERROR),
+        source: Some(SOURCE.to_string()),
+        message,
+        ..Default::default()
+    }
+}
+
+/// Parse and sort check a document, returning the (possibly partial) sort
+/// checked module along with all syntax and sort errors as diagnostics.
+pub fn check(text: &str) -> (Module, Vec<Diagnostic>) {
+    let (mut module, syntax_errors) = parse_recovering(text);
+    let mut diagnostics = vec![];
+    for e in &syntax_errors {
+        let r = match e {
+            SyntaxError::Parse(e) => range(text, e.location.offset, e.location.offset),
+            SyntaxError::Misplaced { span, .. } => range(text, span.start, span.end),
+        };
+        diagnostics.push(error(r, format!("{e}")));
+    }
+    for (e, span) in sort_check_module_recovering(&mut module) {
+        diagnostics.push(error(span_range(text, span), format!("{e}")));
+    }
+    (module, diagnostics)
+}
+
+/// Verify a document, returning a diagnostic for each failed assertion. The
+/// counterexample (or the reason the solver gave up) is attached as related
+/// information.
+///
+/// Documents with syntax or sort errors produce no diagnostics here, since
+/// those are already reported by [`check`].
+pub fn verify(conf: &SolverConf, uri: &Url, text: &str) -> Vec<Diagnostic> {
+    let Ok(mut m) = parse(text) else {
+        return vec![];
+    };
+    if sort_check_module(&mut m).is_err() {
+        return vec![];
+    }
+    m.inline_defs();
+    let d = match extract(&m) {
+        Ok(d) => d,
+        Err(e) => return vec![error(range(text, 0, 0), format!("{e}"))],
+    };
+    let Err(err) = verify_destructured_module(conf, &d, &m.signature) else {
+        return vec![];
+    };
+    err.fails
+        .iter()
+        .map(|fail| {
+            let r = span_range(text, fail.loc);
+            // reuse the wording of the command-line diagnostic, whose note
+            // describes the counterexample
+            let diagnostic = fail.diagnostic(());
+            Diagnostic {
+                related_information: Some(vec![DiagnosticRelatedInformation {
+                    location: Location {
+                        uri: uri.clone(),
+                        range: r,
+                    },
+                    message: diagnostic.notes.join("\n"),
+                }]),
+                ..error(r, diagnostic.message)
+            }
+        })
+        .collect()
+}
+
+fn is_ident_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+/// Find the identifier at (or immediately before) a byte offset, returning its
+/// start offset and text.
+pub fn ident_at(text: &str, offset: usize) -> Option<(usize, &str)> {
+    let offset = offset.min(text.len());
+    let start = (text[..offset].char_indices().rev())
+        .find(|&(_, c)| !is_ident_char(c))
+        .map_or(0, |(i, c)| i + c.len_utf8());
+    let end = text[offset..]
+        .find(|c| !is_ident_char(c))
+        .map_or(text.len(), |i| offset + i);
+    let ident = &text[start..end];
+    if ident.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+        Some((start, ident))
+    } else {
+        None
+    }
+}
+
+/// The kind of a top-level declaration.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeclKind {
+    /// `sort s`
+    Sort,
+    /// `mutable f(...): s` or `immutable f(...): s`
+    Relation,
+    /// `def f(...) -> s { ... }`
+    Def,
+}
+
+/// A top-level declaration found in the source.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Declaration {
+    #[allow(missing_docs)]
+    pub kind: DeclKind,
+    #[allow(missing_docs)]
+    pub name: String,
+    /// Byte range of the whole declaration
+    pub span: Span,
+    /// Byte range of the declared name
+    pub name_span: Span,
+}
+
+/// Remove a trailing comment from a line.
+fn strip_comment(line: &str) -> &str {
+    line.split('#').next().unwrap()
+}
+
+/// Find the end of a `def` starting at `start`, which is the closing brace
+/// matching the first opening brace.
+fn def_end(text: &str, start: usize) -> Option<usize> {
+    let mut depth = 0;
+    let mut in_comment = false;
+    for (i, c) in text[start..].char_indices() {
+        match c {
+            '#' => in_comment = true,
+            '\n' => in_comment = false,
+            _ if in_comment => {}
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(start + i + 1);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Find the sort, relation, and definition declarations in a document.
+pub fn declarations(text: &str) -> Vec<Declaration> {
+    let mut decls = vec![];
+    let mut line_start = 0;
+    for line in text.split_inclusive('\n') {
+        let indent = line.len() - line.trim_start().len();
+        let start = line_start + indent;
+        line_start += line.len();
+
+        let code = strip_comment(&line[indent..]);
+        let keyword_len = code.find(char::is_whitespace).unwrap_or(code.len());
+        let kind = match &code[..keyword_len] {
+            "sort" => DeclKind::Sort,
+            "mutable" | "immutable" => DeclKind::Relation,
+            "def" => DeclKind::Def,
+            _ => continue,
+        };
+        // the name is the identifier following the keyword
+        let rest = &code[keyword_len..];
+        let name_offset = keyword_len + rest.len() - rest.trim_start().len();
+        let word = &code[name_offset..];
+        let name_len = word.find(|c| !is_ident_char(c)).unwrap_or(word.len());
+        if name_len == 0 {
+            continue;
+        }
+        let name_start = start + name_offset;
+        let name_span = Span {
+            start: name_start,
+            end: name_start + name_len,
+        };
+        let line_end = start + code.trim_end().len();
+        let end = match kind {
+            DeclKind::Def => def_end(text, start).unwrap_or(line_end),
+            _ => line_end,
+        };
+        decls.push(Declaration {
+            kind,
+            name: word[..name_len].to_string(),
+            span: Span { start, end },
+            name_span,
+        });
+    }
+    decls
+}
+
+/// The declarations as an outline of the document.
+pub fn document_symbols(text: &str, m: &Module) -> Vec<DocumentSymbol> {
+    declarations(text)
+        .into_iter()
+        .map(|decl| {
+            let relation = m.signature.relations.iter().find(|r| r.name == decl.name);
+            let kind = match decl.kind {
+                DeclKind::Sort => SymbolKind::CLASS,
+                DeclKind::Def => SymbolKind::FUNCTION,
+                DeclKind::Relation => match relation {
+                    Some(r) if !r.args.is_empty() => SymbolKind::FUNCTION,
+                    Some(r) if !r.mutable => SymbolKind::CONSTANT,
+                    _ => SymbolKind::VARIABLE,
+                },
+            };
+            #[allow(deprecated)]
+            DocumentSymbol {
+                name: decl.name.clone(),
+                detail: describe(m, &decl.name),
+                kind,
+                tags: None,
+                deprecated: None,
+                range: range(text, decl.span.start, decl.span.end),
+                selection_range: range(text, decl.name_span.start, decl.name_span.end),
+                children: None,
+            }
+        })
+        .collect()
+}
+
+/// Find the declaration of the identifier at `offset`, if it is a sort,
+/// relation, or definition.
+pub fn definition(text: &str, offset: usize) -> Option<Range> {
+    let (_, ident) = ident_at(text, offset)?;
+    let decl = declarations(text).into_iter().find(|d| d.name == ident)?;
+    Some(range(text, decl.name_span.start, decl.name_span.end))
+}
+
+fn args(sorts: impl IntoIterator<Item = String>) -> String {
+    let sorts = sorts.into_iter().collect::<Vec<_>>();
+    if sorts.is_empty() {
+        "".to_string()
+    } else {
+        format!("({})", sorts.join(", "))
+    }
+}
+
+/// Describe a global name: its declaration if it is a relation or definition.
+fn describe(m: &Module, name: &str) -> Option<String> {
+    if let Some(r) = m.signature.relations.iter().find(|r| r.name == name) {
+        let mutable = if r.mutable { "mutable" } else { "immutable" };
+        let args = args(r.args.iter().map(|s| s.to_string()));
+        return Some(format!("{mutable} {name}{args}: {}", r.sort));
+    }
+    if let Some(def) = m.defs.iter().find(|d| d.name == name) {
+        let args = args(
+            def.binders
+                .iter()
+                .map(|b| format!("{}: {}", b.name, b.sort)),
+        );
+        return Some(format!("def {name}{args} -> {}", def.ret_sort));
+    }
+    None
+}
+
+fn collect_binders<'a>(t: &'a Term, binders: &mut Vec<&'a Binder>) {
+    match t {
+        Term::Literal(_) | Term::Id(_) => {}
+        Term::App(_, _, args) | Term::NAryOp(_, args) => {
+            for arg in args {
+                collect_binders(arg, binders);
+            }
+        }
+        Term::UnaryOp(_, t) => collect_binders(t, binders),
+        Term::BinOp(_, lhs, rhs) => {
+            collect_binders(lhs, binders);
+            collect_binders(rhs, binders);
+        }
+        Term::Ite { cond, then, else_ } => {
+            collect_binders(cond, binders);
+            collect_binders(then, binders);
+            collect_binders(else_, binders);
+        }
+        Term::Quantified {
+            binders: bs, body, ..
+        } => {
+            binders.extend(bs);
+            collect_binders(body, binders);
+        }
+    }
+}
+
+/// All the variables bound in the module, by quantifiers or as definition
+/// arguments.
+fn binders(m: &Module) -> Vec<&Binder> {
+    let mut binders = vec![];
+    for def in &m.defs {
+        binders.extend(&def.binders);
+        collect_binders(&def.body, &mut binders);
+    }
+    for stmt in &m.statements {
+        match stmt {
+            ThmStmt::Assume(t) => collect_binders(t, &mut binders),
+            ThmStmt::Assert(Proof { assert, invariants }) => {
+                collect_binders(&assert.x, &mut binders);
+                for inv in invariants {
+                    collect_binders(&inv.x, &mut binders);
+                }
+            }
+        }
+    }
+    binders
+}
+
+/// The hover text for the identifier at `offset`: the declaration of a
+/// relation or definition, or the sort of a bound variable.
+///
+/// Variables are not resolved to their binding site, so a variable name bound
+/// with different sorts in different places shows all of them.
+pub fn hover(text: &str, m: &Module, offset: usize) -> Option<(Range, String)> {
+    let (start, ident) = ident_at(text, offset)?;
+    let r = range(text, start, start + ident.len());
+    if let Some(description) = describe(m, ident) {
+        return Some((r, description));
+    }
+    if m.signature.sorts.iter().any(|s| s == ident) {
+        return Some((r, format!("sort {ident}")));
+    }
+    let sorts = binders(m)
+        .into_iter()
+        .filter(|b| b.name == ident && b.sort != Sort::unknown())
+        .map(|b| b.sort.to_string())
+        .collect::<BTreeSet<_>>();
+    if sorts.is_empty() {
+        return None;
+    }
+    let sorts = sorts.into_iter().collect::<Vec<_>>().join(" | ");
+    Some((r, format!("{ident}: {sorts}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEXT: &str = "sort node
+
+# the lock
+mutable lock(node): bool
+immutable leader: node
+
+def free(n: node) -> bool {
+  !lock(n)
+}
+
+assume forall n:node. free(n)
+assert always forall n. lock(n) -> n = leader
+";
+
+    #[test]
+    fn test_positions() {
+        let text = "ab\nc→d\n";
+        assert_eq!(position(text, 4), Position::new(1, 1));
+        // → is three bytes but one UTF-16 code unit
+        assert_eq!(position(text, 7), Position::new(1, 2));
+        for i in [0, 1, 3, 4, 7, 8, 9] {
+            assert_eq!(offset(text, position(text, i)), i);
+        }
+        assert_eq!(offset(text, Position::new(0, 10)), 2);
+        assert_eq!(offset(text, Position::new(5, 0)), text.len());
+    }
+
+    #[test]
+    fn test_check() {
+        let (_, diagnostics) = check(TEXT);
+        assert!(diagnostics.is_empty());
+
+        let text = "sort s\nmutable p: s\nassume p &\nassume q\n";
+        let (_, diagnostics) = check(text);
+        let errors = diagnostics
+            .iter()
+            .map(|d| (d.range.start.line, d.message.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[0].0, 2);
+        assert_eq!(errors[1], (0, "unknown variable/constant q"));
+    }
+
+    #[test]
+    fn test_declarations() {
+        let names = declarations(TEXT)
+            .into_iter()
+            .map(|d| (d.kind, d.name))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            vec![
+                (DeclKind::Sort, "node".to_string()),
+                (DeclKind::Relation, "lock".to_string()),
+                (DeclKind::Relation, "leader".to_string()),
+                (DeclKind::Def, "free".to_string()),
+            ]
+        );
+        let def = &declarations(TEXT)[3];
+        assert!(TEXT[def.span.start..def.span.end].ends_with("!lock(n)\n}"));
+
+        // the name is found after the keyword, even if the keyword contains it
+        let text = "sort s\nmutable  e : bool\n";
+        let spans = declarations(text)
+            .into_iter()
+            .map(|d| d.name_span.start..d.name_span.end)
+            .collect::<Vec<_>>();
+        assert_eq!(spans, vec![5..6, 16..17]);
+    }
+
+    #[test]
+    fn test_definition_and_hover() {
+        let (m, _) = check(TEXT);
+        let use_of = |s: &str| TEXT.find(s).unwrap();
+
+        let lock = definition(TEXT, use_of("lock(n) ->")).unwrap();
+        assert_eq!(lock, range(TEXT, use_of("lock(node)"), use_of("(node)")));
+        let free = definition(TEXT, use_of("free(n)\n") + 2).unwrap();
+        assert_eq!(free.start, position(TEXT, use_of("free(n: node)")));
+        assert_eq!(definition(TEXT, use_of("forall")), None);
+
+        let hover_at = |offset| hover(TEXT, &m, offset).map(|(_, s)| s);
+        assert_eq!(
+            hover_at(use_of("lock(n) ->")).as_deref(),
+            Some("mutable lock(node): bool")
+        );
+        assert_eq!(
+            hover_at(use_of("free(n)\n")).as_deref(),
+            Some("def free(n: node) -> bool")
+        );
+        assert_eq!(hover_at(use_of("node\n")).as_deref(), Some("sort node"));
+        // n is only annotated in the assume, but sort inference fills in the
+        // other binders
+        assert_eq!(hover_at(use_of("n = leader")).as_deref(), Some("n: node"));
+    }
+}
@@ -0,0 +1,15 @@
+// Copyright 2022-2023 VMware, Inc.
+// SPDX-License-Identifier: BSD-2-Clause
+
+use std::process;
+
+use lsp_server::Connection;
+
+fn main() {
+    let (connection, io_threads) = Connection::stdio();
+    if let Err(e) = flyvy_lsp::server::run(connection) {
+        eprintln!("flyvy-lsp: {e}");
+        process::exit(1);
+    }
+    io_threads.join().expect("could not shut down I/O threads");
+}
@@ -0,0 +1,19 @@
+// Copyright 2022-2023 VMware, Inc.
+// SPDX-License-Identifier: BSD-2-Clause
+
+//! A language server for fly.
+//!
+//! The server publishes syntax and sort errors as diagnostics, and supports
+//! hover, go-to-definition, and document symbols. Verification on save is
+//! opt-in, since it launches an SMT solver; see [`server::Settings`].
+
+#![deny(missing_docs)]
+// configure clippy
+#![allow(clippy::needless_return)]
+#![deny(clippy::uninlined_format_args)]
+// documentation-related lints (only checked when running rustdoc)
+#![allow(rustdoc::private_intra_doc_links)]
+#![deny(rustdoc::broken_intra_doc_links)]
+
+pub mod analysis;
+pub mod server;
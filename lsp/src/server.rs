@@ -0,0 +1,443 @@
+// Copyright 2022-2023 VMware, Inc.
+// SPDX-License-Identifier: BSD-2-Clause
+
+//! The language server main loop, which dispatches LSP messages to the
+//! [`analysis`](crate::analysis) functions.
+//!
+//! Verification runs on a separate thread so that it does not block other
+//! requests; its results are only published if the document has not changed in
+//! the meantime.
+
+use std::collections::HashMap;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use crossbeam_channel::SendError;
+use lsp_server::{
+    Connection, ExtractError, Message, Notification, ProtocolError, Request, Response,
+};
+use lsp_types::{
+    notification::{
+        DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument, DidSaveTextDocument,
+        Notification as _, PublishDiagnostics, ShowMessage,
+    },
+    request::{DocumentSymbolRequest, GotoDefinition, HoverRequest, Request as _},
+    Diagnostic, DocumentSymbolResponse, GotoDefinitionResponse, Hover, HoverContents,
+    HoverProviderCapability, InitializeParams, LanguageString, Location, MarkedString, MessageType,
+    OneOf, PublishDiagnosticsParams, SaveOptions, ServerCapabilities, ShowMessageParams,
+    TextDocumentSyncCapability, TextDocumentSyncKind, TextDocumentSyncOptions,
+    TextDocumentSyncSaveOptions, Url,
+};
+use serde::Deserialize;
+use solver::{backends::SolverType, conf::SolverConf};
+use thiserror::Error;
+
+use crate::analysis;
+
+/// An error that stops the server.
+#[derive(Error, Debug)]
+pub enum ServerError {
+    /// The client did not follow the protocol.
+    #[error("{0}")]
+    Protocol(#[from] ProtocolError),
+    /// A message from the client could not be decoded.
+    #[error("invalid message: {0}")]
+    Json(#[from] serde_json::Error),
+    /// The connection to the client was closed unexpectedly.
+    #[error("connection closed")]
+    Disconnected,
+}
+
+impl<T> From<SendError<T>> for ServerError {
+    fn from(_: SendError<T>) -> Self {
+        Self::Disconnected
+    }
+}
+
+/// The solver to use for verification.
+#[allow(missing_docs)]
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Solver {
+    #[default]
+    Z3,
+    Cvc4,
+    Cvc5,
+}
+
+/// Settings passed by the client as `initializationOptions`.
+#[derive(Deserialize, Debug, Default, Clone, PartialEq, Eq)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Settings {
+    /// Verify a document each time it is saved, reporting each failed assertion
+    /// as a diagnostic.
+    pub verify_on_save: bool,
+    /// SMT solver to use for verification.
+    pub solver: Solver,
+    /// SMT solver timeout in seconds (0 for no timeout).
+    pub timeout: usize,
+}
+
+impl Settings {
+    fn solver_conf(&self, fname: &String) -> SolverConf {
+        let solver_type = match self.solver {
+            Solver::Z3 => SolverType::Z3,
+            Solver::Cvc4 => SolverType::Cvc4,
+            Solver::Cvc5 => SolverType::Cvc5,
+        };
+        SolverConf::new(solver_type, false, fname, self.timeout, 0)
+    }
+}
+
+/// The open documents, by URI.
+type Documents = Arc<Mutex<HashMap<Url, String>>>;
+
+struct Server {
+    connection: Connection,
+    settings: Settings,
+    documents: Documents,
+}
+
+fn publish(
+    connection: &Connection,
+    uri: Url,
+    diagnostics: Vec<Diagnostic>,
+) -> Result<(), ServerError> {
+    let params = PublishDiagnosticsParams {
+        uri,
+        diagnostics,
+        version: None,
+    };
+    connection
+        .sender
+        .send(Notification::new(PublishDiagnostics::METHOD.to_string(), params).into())?;
+    Ok(())
+}
+
+fn capabilities() -> ServerCapabilities {
+    ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Options(
+            TextDocumentSyncOptions {
+                open_close: Some(true),
+                change: Some(TextDocumentSyncKind::FULL),
+                save: Some(TextDocumentSyncSaveOptions::SaveOptions(SaveOptions {
+                    include_text: Some(false),
+                })),
+                ..Default::default()
+            },
+        )),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        definition_provider: Some(OneOf::Left(true)),
+        document_symbol_provider: Some(OneOf::Left(true)),
+        ..Default::default()
+    }
+}
+
+/// Run the server on a connection until the client shuts it down.
+pub fn run(connection: Connection) -> Result<(), ServerError> {
+    let params = connection.initialize(serde_json::to_value(capabilities())?)?;
+    let params: InitializeParams = serde_json::from_value(params)?;
+    let settings = match params.initialization_options {
+        Some(options) => serde_json::from_value(options)?,
+        None => Settings::default(),
+    };
+    let mut server = Server {
+        connection,
+        settings,
+        documents: Arc::default(),
+    };
+    server.main_loop()
+}
+
+impl Server {
+    fn main_loop(&mut self) -> Result<(), ServerError> {
+        while let Ok(msg) = self.connection.receiver.recv() {
+            match msg {
+                Message::Request(req) => {
+                    if self.connection.handle_shutdown(&req)? {
+                        return Ok(());
+                    }
+                    let id = req.id.clone();
+                    let resp = match self.request(req) {
+                        Ok(resp) => resp,
+                        // a malformed message shouldn't bring down the server
+                        Err(ServerError::Json(e)) => {
+                            eprintln!("flyvy-lsp: ignoring invalid request: {e}");
+                            Response::new_err(
+                                id,
+                                lsp_server::ErrorCode::InvalidParams as i32,
+                                e.to_string(),
+                            )
+                        }
+                        Err(e) => return Err(e),
+                    };
+                    self.connection.sender.send(resp.into())?;
+                }
+                Message::Notification(not) => match self.notification(not) {
+                    Ok(()) => {}
+                    Err(ServerError::Json(e)) => {
+                        eprintln!("flyvy-lsp: ignoring invalid notification: {e}");
+                    }
+                    Err(e) => return Err(e),
+                },
+                Message::Response(_) => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn document(&self, uri: &Url) -> Option<String> {
+        self.documents.lock().unwrap().get(uri).cloned()
+    }
+
+    fn request(&self, req: Request) -> Result<Response, ServerError> {
+        let id = req.id.clone();
+        let resp = match req.method.as_str() {
+            HoverRequest::METHOD => {
+                let (_, params) = extract_request::<HoverRequest>(req)?;
+                let pos = params.text_document_position_params;
+                let hover = self.document(&pos.text_document.uri).and_then(|text| {
+                    let (m, _) = analysis::check(&text);
+                    let offset = analysis::offset(&text, pos.position);
+                    analysis::hover(&text, &m, offset)
+                });
+                Response::new_ok(
+                    id,
+                    hover.map(|(range, value)| Hover {
+                        contents: HoverContents::Scalar(MarkedString::LanguageString(
+                            LanguageString {
+                                language: "fly".to_string(),
+                                value,
+                            },
+                        )),
+                        range: Some(range),
+                    }),
+                )
+            }
+            GotoDefinition::METHOD => {
+                let (_, params) = extract_request::<GotoDefinition>(req)?;
+                let pos = params.text_document_position_params;
+                let uri = pos.text_document.uri;
+                let location = self.document(&uri).and_then(|text| {
+                    analysis::definition(&text, analysis::offset(&text, pos.position))
+                });
+                Response::new_ok(
+                    id,
+                    location.map(|range| GotoDefinitionResponse::Scalar(Location { uri, range })),
+                )
+            }
+            DocumentSymbolRequest::METHOD => {
+                let (_, params) = extract_request::<DocumentSymbolRequest>(req)?;
+                let symbols = self.document(&params.text_document.uri).map(|text| {
+                    let (m, _) = analysis::check(&text);
+                    DocumentSymbolResponse::Nested(analysis::document_symbols(&text, &m))
+                });
+                Response::new_ok(id, symbols)
+            }
+            _ => Response::new_err(
+                id,
+                lsp_server::ErrorCode::MethodNotFound as i32,
+                format!("unsupported request {}", req.method),
+            ),
+        };
+        Ok(resp)
+    }
+
+    fn notification(&mut self, not: Notification) -> Result<(), ServerError> {
+        match not.method.as_str() {
+            DidOpenTextDocument::METHOD => {
+                let params = extract_notification::<DidOpenTextDocument>(not)?;
+                let doc = params.text_document;
+                self.update(doc.uri, doc.text)?;
+            }
+            DidChangeTextDocument::METHOD => {
+                let params = extract_notification::<DidChangeTextDocument>(not)?;
+                // with full synchronization the last change is the whole document
+                if let Some(change) = params.content_changes.into_iter().last() {
+                    self.update(params.text_document.uri, change.text)?;
+                }
+            }
+            DidSaveTextDocument::METHOD => {
+                let params = extract_notification::<DidSaveTextDocument>(not)?;
+                if self.settings.verify_on_save {
+                    self.verify(params.text_document.uri);
+                }
+            }
+            DidCloseTextDocument::METHOD => {
+                let params = extract_notification::<DidCloseTextDocument>(not)?;
+                let uri = params.text_document.uri;
+                self.documents.lock().unwrap().remove(&uri);
+                publish(&self.connection, uri, vec![])?;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Record the new text of a document and publish its syntax and sort
+    /// errors.
+    fn update(&mut self, uri: Url, text: String) -> Result<(), ServerError> {
+        let (_, diagnostics) = analysis::check(&text);
+        self.documents.lock().unwrap().insert(uri.clone(), text);
+        publish(&self.connection, uri, diagnostics)
+    }
+
+    /// Verify a document in the background, publishing the failed assertions
+    /// together with the document's other diagnostics.
+    fn verify(&self, uri: Url) {
+        let Some(text) = self.document(&uri) else {
+            return;
+        };
+        let sender = self.connection.sender.clone();
+        let documents = self.documents.clone();
+        let conf = self.settings.solver_conf(&uri.path().to_string());
+        thread::spawn(move || {
+            // the solver panics if it cannot be launched, which should not
+            // bring down the server
+            let result =
+                panic::catch_unwind(AssertUnwindSafe(|| analysis::verify(&conf, &uri, &text)));
+            let msg = match result {
+                Ok(failures) => {
+                    if documents.lock().unwrap().get(&uri) != Some(&text) {
+                        return;
+                    }
+                    let (_, mut diagnostics) = analysis::check(&text);
+                    diagnostics.extend(failures);
+                    let params = PublishDiagnosticsParams {
+                        uri,
+                        diagnostics,
+                        version: None,
+                    };
+                    Notification::new(PublishDiagnostics::METHOD.to_string(), params)
+                }
+                Err(_) => {
+                    let params = ShowMessageParams {
+                        typ: MessageType::ERROR,
+                        message: format!("verification of {uri} failed"),
+                    };
+                    Notification::new(ShowMessage::METHOD.to_string(), params)
+                }
+            };
+            // the client may have disconnected in the meantime
+            _ = sender.send(msg.into());
+        });
+    }
+}
+
+fn extract_request<R: lsp_types::request::Request>(
+    req: Request,
+) -> Result<(lsp_server::RequestId, R::Params), ServerError> {
+    req.extract(R::METHOD).map_err(|e| match e {
+        ExtractError::JsonError { error, .. } => ServerError::Json(error),
+        ExtractError::MethodMismatch(_) => unreachable!("dispatched on method"),
+    })
+}
+
+fn extract_notification<N: lsp_types::notification::Notification>(
+    not: Notification,
+) -> Result<N::Params, ServerError> {
+    not.extract(N::METHOD).map_err(|e| match e {
+        ExtractError::JsonError { error, .. } => ServerError::Json(error),
+        ExtractError::MethodMismatch(_) => unreachable!("dispatched on method"),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lsp_server::RequestId;
+    use lsp_types::{
+        notification::{Exit, Initialized},
+        request::{Initialize, Shutdown},
+        DidOpenTextDocumentParams, InitializedParams, TextDocumentItem,
+    };
+    use serde_json::{json, Value};
+
+    fn request<R: lsp_types::request::Request>(
+        client: &Connection,
+        id: i32,
+        params: R::Params,
+    ) -> Value {
+        let req = Request::new(RequestId::from(id), R::METHOD.to_string(), params);
+        client.sender.send(req.into()).unwrap();
+        match client.receiver.recv().unwrap() {
+            Message::Response(resp) => {
+                assert_eq!(resp.id, RequestId::from(id));
+                resp.result.unwrap()
+            }
+            msg => panic!("expected response but got {msg:?}"),
+        }
+    }
+
+    fn notify<N: lsp_types::notification::Notification>(client: &Connection, params: N::Params) {
+        let not = Notification::new(N::METHOD.to_string(), params);
+        client.sender.send(not.into()).unwrap();
+    }
+
+    #[test]
+    fn test_server() {
+        let (server, client) = Connection::memory();
+        let server = thread::spawn(move || run(server));
+
+        let init = json!({ "capabilities": {}, "initializationOptions": { "verifyOnSave": true } });
+        let init = serde_json::from_value(init).unwrap();
+        let caps = request::<Initialize>(&client, 1, init);
+        assert_eq!(caps["capabilities"]["hoverProvider"], json!(true));
+        notify::<Initialized>(&client, InitializedParams {});
+
+        let uri = Url::parse("file:///test.fly").unwrap();
+        notify::<DidOpenTextDocument>(
+            &client,
+            DidOpenTextDocumentParams {
+                text_document: TextDocumentItem {
+                    uri: uri.clone(),
+                    language_id: "fly".to_string(),
+                    version: 0,
+                    text: "mutable p: bool\nassume p & q\n".to_string(),
+                },
+            },
+        );
+        match client.receiver.recv().unwrap() {
+            Message::Notification(not) => {
+                let params = not.extract::<PublishDiagnosticsParams>(PublishDiagnostics::METHOD);
+                let diagnostics = params.unwrap().diagnostics;
+                assert_eq!(diagnostics.len(), 1);
+                assert_eq!(diagnostics[0].message, "unknown variable/constant q");
+            }
+            msg => panic!("expected diagnostics but got {msg:?}"),
+        }
+
+        // malformed messages are answered with an error or ignored
+        let not = Notification::new(DidOpenTextDocument::METHOD.to_string(), json!({}));
+        client.sender.send(not.into()).unwrap();
+        let req = Request::new(
+            RequestId::from(4),
+            HoverRequest::METHOD.to_string(),
+            json!({}),
+        );
+        client.sender.send(req.into()).unwrap();
+        match client.receiver.recv().unwrap() {
+            Message::Response(resp) => {
+                assert_eq!(resp.id, RequestId::from(4));
+                assert!(resp.error.is_some());
+            }
+            msg => panic!("expected response but got {msg:?}"),
+        }
+
+        let hover = request::<HoverRequest>(
+            &client,
+            2,
+            serde_json::from_value(json!({
+                "textDocument": { "uri": uri },
+                "position": { "line": 1, "character": 7 },
+            }))
+            .unwrap(),
+        );
+        assert_eq!(hover["contents"]["value"], json!("mutable p: bool"));
+
+        request::<Shutdown>(&client, 3, ());
+        notify::<Exit>(&client, ());
+        server.join().unwrap().unwrap();
+    }
+}
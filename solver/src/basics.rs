@@ -184,6 +184,39 @@ impl<C: BasicSolverCanceler> SolverCancelers<C> {
             true
         }
     }
+
+    /// Return whether this set of cancelers has been canceled.
+    pub fn is_canceled(&self) -> bool {
+        self.0.lock().unwrap().0
+    }
+}
+
+impl<C: BasicSolverCanceler> SolverCancelers<SolverCancelers<C>> {
+    /// Remove one occurrence of the given set of cancelers, which is no longer needed
+    /// once the queries it tracks are done.
+    fn remove_canceler(&self, canceler: &SolverCancelers<C>) {
+        let mut cancelers = self.0.lock().unwrap();
+        if let Some(i) = cancelers
+            .1
+            .iter()
+            .position(|c| Arc::ptr_eq(&c.0, &canceler.0))
+        {
+            cancelers.1.swap_remove(i);
+        }
+    }
+}
+
+/// Removes the cancelers of a query from the cancelers of a [`CancelableSolver`] when dropped,
+/// so that they are not kept for the lifetime of the solver.
+struct QueryCancelers<'a, C: BasicSolverCanceler> {
+    solver: &'a SolverCancelers<SolverCancelers<C>>,
+    query: SolverCancelers<C>,
+}
+
+impl<C: BasicSolverCanceler> Drop for QueryCancelers<'_, C> {
+    fn drop(&mut self) {
+        self.solver.remove_canceler(&self.query);
+    }
 }
 
 impl<C: BasicSolverCanceler> BasicSolverCanceler for SolverCancelers<C> {
@@ -197,6 +230,15 @@ impl<C: BasicSolverCanceler> BasicSolverCanceler for SolverCancelers<C> {
     }
 }
 
+impl<C1: BasicSolverCanceler, C2: BasicSolverCanceler> BasicSolverCanceler for (C1, C2) {
+    /// Cancel both cancelers, which can be used to cancel the queries of solvers
+    /// with different canceler types at once.
+    fn cancel(&self) {
+        self.0.cancel();
+        self.1.cancel();
+    }
+}
+
 /// A basic solver which uses a single solver configuration
 pub struct SingleSolver(SolverConf);
 
@@ -205,6 +247,14 @@ pub struct SingleSolver(SolverConf);
 /// (2) the query is canceled, or (3) all solvers return unknown.
 pub struct FallbackSolvers(Vec<SolverConf>);
 
+/// A solver which forwards queries to another solver, such that all of its queries can be
+/// canceled at once using the given [`SolverCancelers`]. Once canceled, new queries immediately
+/// return [`SolverError::Killed`].
+pub struct CancelableSolver<'a, S: BasicSolver> {
+    solver: &'a S,
+    cancelers: SolverCancelers<SolverCancelers<S::Canceler>>,
+}
+
 /// A set of solvers used in a parallel fashion: on each query the solvers
 /// are tried in parallel until (1) one of them returns a sat/unsat/error response,
 /// (2) the query is canceled, or (3) all solvers return unknown.
@@ -364,3 +414,107 @@ impl BasicSolver for ParallelSolvers {
         ))
     }
 }
+
+impl<'a, S: BasicSolver> CancelableSolver<'a, S> {
+    /// Create a new solver which forwards queries to `solver` and can be canceled using `cancelers`.
+    pub fn new(solver: &'a S, cancelers: SolverCancelers<SolverCancelers<S::Canceler>>) -> Self {
+        Self { solver, cancelers }
+    }
+}
+
+impl<S: BasicSolver> BasicSolver for CancelableSolver<'_, S> {
+    type Canceler = S::Canceler;
+
+    fn check_sat(
+        &self,
+        query_conf: &QueryConf<Self::Canceler>,
+        assertions: &[Term],
+        assumptions: &HashMap<usize, (Term, bool)>,
+    ) -> Result<BasicSolverResp, SolverError> {
+        // Track the query using its own cancelers if it has them, so that canceling this
+        // solver also cancels the queries that share them.
+        let local_cancelers = query_conf.cancelers.clone().unwrap_or_default();
+        if !self.cancelers.add_canceler(local_cancelers.clone()) {
+            return Err(SolverError::Killed);
+        }
+        let _query = QueryCancelers {
+            solver: &self.cancelers,
+            query: local_cancelers.clone(),
+        };
+
+        let local_query_conf = QueryConf {
+            sig: query_conf.sig,
+            n_states: query_conf.n_states,
+            cancelers: Some(local_cancelers),
+            minimal_model: query_conf.minimal_model,
+            save_tee: query_conf.save_tee,
+        };
+        self.solver
+            .check_sat(&local_query_conf, assertions, assumptions)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A solver that answers unknown to every query, remembering the cancelers of the last one.
+    /// If it is given cancelers, it cancels them while answering.
+    #[derive(Default)]
+    struct UnknownSolver(
+        Mutex<Option<SolverCancelers<SmtPid>>>,
+        Option<SolverCancelers<SolverCancelers<SmtPid>>>,
+    );
+
+    impl BasicSolver for UnknownSolver {
+        type Canceler = SmtPid;
+
+        fn check_sat(
+            &self,
+            query_conf: &QueryConf<Self::Canceler>,
+            _assertions: &[Term],
+            _assumptions: &HashMap<usize, (Term, bool)>,
+        ) -> Result<BasicSolverResp, SolverError> {
+            *self.0.lock().unwrap() = query_conf.cancelers.clone();
+            if let Some(cancelers) = &self.1 {
+                cancelers.cancel();
+            }
+            Ok(BasicSolverResp::Unknown("unknown".to_string()))
+        }
+    }
+
+    #[test]
+    fn test_cancelable_solver() {
+        let sig = Signature {
+            sorts: vec![],
+            relations: vec![],
+        };
+        let query_conf = QueryConf {
+            sig: &sig,
+            n_states: 1,
+            cancelers: None,
+            minimal_model: false,
+            save_tee: false,
+        };
+        let inner = UnknownSolver::default();
+        let cancelers = SolverCancelers::new();
+        let solver = CancelableSolver::new(&inner, cancelers.clone());
+
+        let resp = solver.check_sat(&query_conf, &[], &HashMap::new());
+        assert!(matches!(resp, Ok(BasicSolverResp::Unknown(_))));
+        let query_cancelers = inner.0.lock().unwrap().clone().unwrap();
+        assert!(!query_cancelers.is_canceled());
+        // the cancelers of a finished query are dropped
+        assert!(cancelers.0.lock().unwrap().1.is_empty());
+
+        // canceling the solver cancels its queries, and later queries are not sent
+        let inner = UnknownSolver(Mutex::default(), Some(cancelers.clone()));
+        let solver = CancelableSolver::new(&inner, cancelers.clone());
+        let resp = solver.check_sat(&query_conf, &[], &HashMap::new());
+        assert!(matches!(resp, Ok(BasicSolverResp::Unknown(_))));
+        let query_cancelers = inner.0.lock().unwrap().clone().unwrap();
+        assert!(query_cancelers.is_canceled());
+        let resp = solver.check_sat(&query_conf, &[], &HashMap::new());
+        assert!(matches!(resp, Err(SolverError::Killed)));
+    }
+}
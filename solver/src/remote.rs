@@ -0,0 +1,491 @@
+// Copyright 2022-2023 VMware, Inc.
+// SPDX-License-Identifier: BSD-2-Clause
+
+//! Solvers running in local worker processes.
+//!
+//! [`RemoteSolvers`] is a [`BasicSolver`] which sends each query to one of a pool of
+//! worker processes, and each worker answers it using its own solvers (see [`serve`]).
+//! This way the solver state lives in the workers rather than in the coordinator.
+//! The protocol is one JSON object per line over the workers' stdin and stdout:
+//! every [`Request`] is answered by a [`Response`]. Workers which crash are restarted.
+//!
+//! Each worker runs in its own process group, together with the solvers it starts,
+//! so that killing a worker also kills its solvers.
+
+use std::{
+    io::{self, BufRead, BufReader, Write},
+    os::unix::process::CommandExt,
+    process::{Child, ChildStdin, ChildStdout, Command, Stdio},
+    sync::{Arc, Condvar, Mutex},
+};
+
+use nix::{
+    sys::signal::{killpg, Signal},
+    unistd::Pid,
+};
+
+use fly::{
+    semantics::Model,
+    syntax::{Signature, Term},
+};
+use serde::{Deserialize, Serialize};
+use smtlib::proc::SolverError;
+
+use crate::basics::{BasicSolver, BasicSolverCanceler, BasicSolverResp, QueryConf};
+
+/// How many times a query is retried when the worker answering it crashes.
+const MAX_RETRIES: usize = 3;
+
+/// A query sent to a worker.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Request {
+    /// The signature used
+    pub sig: Signature,
+    /// The number of states
+    pub n_states: usize,
+    /// Whether to return a minimal model in case of satifiability
+    pub minimal_model: bool,
+    /// The assertions of the query
+    pub assertions: Vec<Term>,
+    /// The assumptions of the query, with their keys
+    pub assumptions: Vec<(usize, Term, bool)>,
+}
+
+/// A worker's answer to a query.
+#[derive(Debug, Serialize, Deserialize)]
+pub enum Response {
+    /// A sat response together with a satisfying trace
+    Sat(Vec<Model>),
+    /// An unsat response together with an unsat core
+    Unsat(Vec<usize>),
+    /// An unknown response together with a reason
+    Unknown(String),
+    /// The worker's solver failed with the given error
+    Error(String),
+}
+
+/// Answer the queries read from `input` using `solver`, writing the responses to `output`,
+/// until `input` is closed. This is the main loop of a worker process.
+pub fn serve<S: BasicSolver>(
+    solver: &S,
+    input: impl BufRead,
+    mut output: impl Write,
+) -> io::Result<()> {
+    for line in input.lines() {
+        let request: Request = serde_json::from_str(&line?)?;
+        let query_conf = QueryConf {
+            sig: &request.sig,
+            n_states: request.n_states,
+            cancelers: None,
+            minimal_model: request.minimal_model,
+            save_tee: false,
+        };
+        let assumptions = request
+            .assumptions
+            .into_iter()
+            .map(|(key, term, value)| (key, (term, value)))
+            .collect();
+        let response = match solver.check_sat(&query_conf, &request.assertions, &assumptions) {
+            Ok(BasicSolverResp::Sat(models)) => Response::Sat(models),
+            Ok(BasicSolverResp::Unsat(core)) => Response::Unsat(core.into_iter().collect()),
+            Ok(BasicSolverResp::Unknown(reason)) => Response::Unknown(reason),
+            Err(err) => Response::Error(err.to_string()),
+        };
+        serde_json::to_writer(&mut output, &response)?;
+        writeln!(output)?;
+        output.flush()?;
+    }
+    Ok(())
+}
+
+/// A worker process, together with the pipes used to communicate with it.
+struct Worker {
+    child: Arc<Mutex<Child>>,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+}
+
+impl Worker {
+    fn spawn(mut command: Command) -> io::Result<Self> {
+        let mut child = command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .process_group(0)
+            .spawn()?;
+        let stdin = child.stdin.take().unwrap();
+        let stdout = BufReader::new(child.stdout.take().unwrap());
+        Ok(Worker {
+            child: Arc::new(Mutex::new(child)),
+            stdin,
+            stdout,
+        })
+    }
+
+    fn query(&mut self, request: &Request) -> io::Result<Response> {
+        serde_json::to_writer(&mut self.stdin, request)?;
+        writeln!(self.stdin)?;
+        self.stdin.flush()?;
+
+        let mut line = String::new();
+        if self.stdout.read_line(&mut line)? == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "the worker exited",
+            ));
+        }
+        Ok(serde_json::from_str(&line)?)
+    }
+}
+
+/// Kill a worker together with the solvers it started, which are in its process group.
+fn kill(child: &Child) {
+    let pgid = Pid::from_raw(child.id().try_into().unwrap());
+    // the worker might have exited already, in which case this fails harmlessly
+    let _ = killpg(pgid, Signal::SIGKILL);
+}
+
+impl Drop for Worker {
+    fn drop(&mut self) {
+        let mut child = self.child.lock().unwrap();
+        kill(&child);
+        let _ = child.wait();
+    }
+}
+
+/// The state of a query sent to a worker, shared with the query's canceler.
+struct QueryState {
+    /// The worker answering the query, or `None` once the query is done.
+    child: Option<Arc<Mutex<Child>>>,
+    canceled: bool,
+}
+
+/// Cancels a query of a [`RemoteSolvers`] by killing the worker answering it.
+pub struct WorkerCanceler(Arc<Mutex<QueryState>>);
+
+impl BasicSolverCanceler for WorkerCanceler {
+    fn cancel(&self) {
+        let mut state = self.0.lock().unwrap();
+        state.canceled = true;
+        if let Some(child) = &state.child {
+            kill(&child.lock().unwrap());
+        }
+    }
+}
+
+/// The workers of a [`RemoteSolvers`].
+struct Workers {
+    /// The workers which aren't answering a query
+    idle: Vec<Worker>,
+    /// The number of workers, idle or not. This only decreases if a worker can't be restarted.
+    count: usize,
+}
+
+/// A pool of worker processes which answer queries, used as a [`BasicSolver`].
+/// Each query is sent to an idle worker, waiting for one if they are all busy.
+pub struct RemoteSolvers {
+    command: Box<dyn Fn() -> Command + Send + Sync>,
+    workers: Mutex<Workers>,
+    available: Condvar,
+}
+
+impl RemoteSolvers {
+    /// Start the given number of workers, each of which is launched by a command returned
+    /// by `command`. The workers should run [`serve`].
+    pub fn new(
+        workers: usize,
+        command: impl Fn() -> Command + Send + Sync + 'static,
+    ) -> io::Result<Self> {
+        assert!(workers > 0, "at least one worker is needed");
+        let idle = (0..workers)
+            .map(|_| Worker::spawn(command()))
+            .collect::<io::Result<_>>()?;
+        Ok(RemoteSolvers {
+            command: Box::new(command),
+            workers: Mutex::new(Workers {
+                idle,
+                count: workers,
+            }),
+            available: Condvar::new(),
+        })
+    }
+
+    /// Take an idle worker, waiting for one if they are all busy.
+    /// Fails if there are no workers left.
+    fn take(&self) -> Result<Worker, SolverError> {
+        let mut workers = self.workers.lock().unwrap();
+        while workers.idle.is_empty() {
+            if workers.count == 0 {
+                return Err(SolverError::UnexpectedClose(
+                    "no workers are left, since they could not be restarted".to_string(),
+                ));
+            }
+            workers = self.available.wait(workers).unwrap();
+        }
+        Ok(workers.idle.pop().unwrap())
+    }
+
+    fn put(&self, worker: Worker) {
+        self.workers.lock().unwrap().idle.push(worker);
+        self.available.notify_one();
+    }
+
+    /// Replace a worker which was killed or has crashed with a new one. If the new worker
+    /// can't be started, the pool shrinks by one worker and the error is returned.
+    fn restart(&self, worker: Worker) -> Result<(), SolverError> {
+        drop(worker);
+        match Worker::spawn((self.command)()) {
+            Ok(worker) => {
+                self.put(worker);
+                Ok(())
+            }
+            Err(err) => {
+                log::error!("could not restart worker: {err}");
+                self.workers.lock().unwrap().count -= 1;
+                // wake up all waiting queries, in case there are no workers left
+                self.available.notify_all();
+                Err(SolverError::Io(err))
+            }
+        }
+    }
+}
+
+impl BasicSolver for RemoteSolvers {
+    type Canceler = WorkerCanceler;
+
+    fn check_sat(
+        &self,
+        query_conf: &QueryConf<Self::Canceler>,
+        assertions: &[Term],
+        assumptions: &std::collections::HashMap<usize, (Term, bool)>,
+    ) -> Result<BasicSolverResp, SolverError> {
+        let request = Request {
+            sig: query_conf.sig.clone(),
+            n_states: query_conf.n_states,
+            minimal_model: query_conf.minimal_model,
+            assertions: assertions.to_vec(),
+            assumptions: assumptions
+                .iter()
+                .map(|(key, (term, value))| (*key, term.clone(), *value))
+                .collect(),
+        };
+
+        let mut crashes = 0;
+        loop {
+            let mut worker = self.take()?;
+            let state = Arc::new(Mutex::new(QueryState {
+                child: Some(worker.child.clone()),
+                canceled: false,
+            }));
+            if query_conf
+                .cancelers
+                .as_ref()
+                .is_some_and(|c| !c.add_canceler(WorkerCanceler(state.clone())))
+            {
+                self.put(worker);
+                return Err(SolverError::Killed);
+            }
+
+            let res = worker.query(&request);
+            let canceled = {
+                let mut state = state.lock().unwrap();
+                state.child = None;
+                state.canceled
+            };
+            match res {
+                // the worker might have been killed, so it cannot be reused
+                _ if canceled => {
+                    // if the worker can't be restarted, this is logged and the pool shrinks
+                    let _ = self.restart(worker);
+                    return Err(SolverError::Killed);
+                }
+                Ok(response) => {
+                    self.put(worker);
+                    return match response {
+                        Response::Sat(models) => Ok(BasicSolverResp::Sat(models)),
+                        Response::Unsat(core) => {
+                            Ok(BasicSolverResp::Unsat(core.into_iter().collect()))
+                        }
+                        Response::Unknown(reason) => Ok(BasicSolverResp::Unknown(reason)),
+                        Response::Error(err) => Err(SolverError::UnexpectedClose(err)),
+                    };
+                }
+                Err(err) => {
+                    log::warn!("worker failed ({err}), restarting it");
+                    self.restart(worker)?;
+                    crashes += 1;
+                    if crashes > MAX_RETRIES {
+                        return Err(SolverError::UnexpectedClose(format!(
+                            "workers failed {crashes} times on the same query: {err}"
+                        )));
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::basics::SolverCancelers;
+    use std::collections::HashMap;
+
+    /// A solver which says that every query is unsat, with all of its assumptions in the core.
+    struct UnsatSolver;
+
+    impl BasicSolver for UnsatSolver {
+        type Canceler = WorkerCanceler;
+
+        fn check_sat(
+            &self,
+            _query_conf: &QueryConf<Self::Canceler>,
+            _assertions: &[Term],
+            assumptions: &HashMap<usize, (Term, bool)>,
+        ) -> Result<BasicSolverResp, SolverError> {
+            Ok(BasicSolverResp::Unsat(
+                assumptions.keys().copied().collect(),
+            ))
+        }
+    }
+
+    fn request() -> Request {
+        let sig = Signature {
+            sorts: vec!["node".to_string()],
+            relations: vec![],
+        };
+        Request {
+            sig,
+            n_states: 2,
+            minimal_model: true,
+            assertions: vec![fly::parser::term("forall x:node. x = x")],
+            assumptions: vec![(3, Term::Literal(true), false)],
+        }
+    }
+
+    #[test]
+    fn test_serve() {
+        let mut input = serde_json::to_string(&request()).unwrap();
+        input.push('\n');
+        let mut output = vec![];
+        serve(&UnsatSolver, input.as_bytes(), &mut output).unwrap();
+
+        let output = String::from_utf8(output).unwrap();
+        assert_eq!(output.lines().count(), 1);
+        let response: Response = serde_json::from_str(&output).unwrap();
+        assert!(matches!(response, Response::Unsat(core) if core == vec![3]));
+    }
+
+    #[test]
+    fn test_remote_solvers_restart() {
+        // a worker which answers a single query and then exits
+        let solvers = RemoteSolvers::new(1, || {
+            let mut command = Command::new("sh");
+            command
+                .arg("-c")
+                .arg(r#"read -r line && echo '{"Unknown":"fake"}'"#);
+            command
+        })
+        .unwrap();
+
+        let req = request();
+        let query_conf = QueryConf {
+            sig: &req.sig,
+            n_states: 2,
+            cancelers: None,
+            minimal_model: true,
+            save_tee: false,
+        };
+        // the second query finds the worker exited, so it is restarted and the query retried
+        for _ in 0..2 {
+            let resp = solvers.check_sat(&query_conf, &req.assertions, &HashMap::new());
+            assert!(matches!(resp, Ok(BasicSolverResp::Unknown(reason)) if reason == "fake"));
+        }
+
+        // a canceled query isn't sent
+        let cancelers = SolverCancelers::new();
+        cancelers.cancel();
+        let query_conf = QueryConf {
+            cancelers: Some(cancelers),
+            ..query_conf
+        };
+        let resp = solvers.check_sat(&query_conf, &req.assertions, &HashMap::new());
+        assert!(matches!(resp, Err(SolverError::Killed)));
+    }
+
+    #[test]
+    fn test_remote_solvers_cancel() {
+        let pid_file =
+            std::env::temp_dir().join(format!("remote-solvers-{}.pid", std::process::id()));
+        let _ = std::fs::remove_file(&pid_file);
+        // a worker which starts a long-running process (like a solver) for each query
+        let script = format!(
+            "read -r line; sleep 60 > /dev/null & echo $! > {}; wait",
+            pid_file.display()
+        );
+        let solvers = RemoteSolvers::new(1, move || {
+            let mut command = Command::new("sh");
+            command.arg("-c").arg(&script);
+            command
+        })
+        .unwrap();
+
+        let req = request();
+        let cancelers = SolverCancelers::new();
+        let query_conf = QueryConf {
+            sig: &req.sig,
+            n_states: 2,
+            cancelers: Some(cancelers.clone()),
+            minimal_model: true,
+            save_tee: false,
+        };
+        let pid = std::thread::scope(|s| {
+            let query =
+                s.spawn(|| solvers.check_sat(&query_conf, &req.assertions, &HashMap::new()));
+            let pid = loop {
+                match std::fs::read_to_string(&pid_file) {
+                    Ok(pid) if pid.ends_with('\n') => break pid.trim().to_string(),
+                    _ => std::thread::sleep(std::time::Duration::from_millis(10)),
+                }
+            };
+            cancelers.cancel();
+            let resp = query.join().unwrap();
+            assert!(matches!(resp, Err(SolverError::Killed)));
+            pid
+        });
+        std::fs::remove_file(&pid_file).unwrap();
+
+        // the process started by the worker is killed with it (it might remain a zombie for a while)
+        let stat = std::fs::read_to_string(format!("/proc/{pid}/stat")).unwrap_or_default();
+        assert!(stat.is_empty() || stat.split_whitespace().nth(2) == Some("Z"));
+    }
+
+    #[test]
+    fn test_remote_solvers_restart_failure() {
+        // the first worker exits right away, and later workers can't be started
+        let started = std::sync::atomic::AtomicBool::new(false);
+        let solvers = RemoteSolvers::new(1, move || {
+            if started.swap(true, std::sync::atomic::Ordering::SeqCst) {
+                Command::new("/nonexistent/worker")
+            } else {
+                let mut command = Command::new("sh");
+                command.arg("-c").arg("true");
+                command
+            }
+        })
+        .unwrap();
+
+        let req = request();
+        let query_conf = QueryConf {
+            sig: &req.sig,
+            n_states: 2,
+            cancelers: None,
+            minimal_model: true,
+            save_tee: false,
+        };
+        let resp = solvers.check_sat(&query_conf, &req.assertions, &HashMap::new());
+        assert!(matches!(resp, Err(SolverError::Io(_))));
+        // there are no workers left, so the next query fails instead of waiting for one
+        let resp = solvers.check_sat(&query_conf, &req.assertions, &HashMap::new());
+        assert!(matches!(resp, Err(SolverError::UnexpectedClose(_))));
+    }
+}
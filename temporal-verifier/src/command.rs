@@ -4,12 +4,16 @@
 //! The temporal-verifier binary's command-line interface.
 
 use bounded::checker::CheckerAnswer;
+use bounded::simulate::Simulator;
 use codespan_reporting::diagnostic::{Diagnostic, Label};
 use path_slash::PathExt;
-use solver::basics::SingleSolver;
+use rand::{rngs::StdRng, SeedableRng};
+use solver::basics::{SingleSolver, SolverCancelers};
 use std::collections::HashMap;
-use std::path::Path;
+use std::io;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::Duration;
 use std::{fs, process};
 
 use clap::Args;
@@ -20,18 +24,30 @@ use codespan_reporting::{
         termcolor::{ColorChoice, StandardStream},
     },
 };
+use fly::mypyvy::{self, import_error_diagnostic};
+use fly::parser::{parse_error_diagnostic, parse_recovering, syntax_error_diagnostic};
 use fly::semantics::models_to_string;
-use fly::syntax::{Signature, Sort};
-use fly::{self, parser::parse_error_diagnostic, printer, sorts, timing};
+use fly::syntax::{Signature, Sort, Span, Term, ThmStmt};
+use fly::transitions::extract;
+use fly::{self, format, printer, sorts, timing};
 use inference::basics::{parse_quantifier, InferenceConfig, QfBody};
+use inference::budget::Budget;
+use inference::compare;
+use inference::events::EventLog;
+use inference::finite;
 use inference::fixpoint::{self, qalpha_dynamic};
 use inference::houdini;
+use inference::minimize;
 use inference::quant::QuantifierConfig;
+use inference::templates;
 use inference::updr::Updr;
-use solver::backends;
 use solver::conf::SolverConf;
+use solver::{backends, SmtPid};
 use verify::module::verify_module;
 
+use crate::simulate::Repl;
+use crate::vmt;
+
 #[derive(clap::ValueEnum, Copy, Clone, Debug, PartialEq, Eq)]
 enum SolverType {
     Z3,
@@ -39,6 +55,24 @@ enum SolverType {
     Cvc5,
 }
 
+#[derive(clap::ValueEnum, Copy, Clone, Debug, PartialEq, Eq)]
+enum ExportFormat {
+    /// VMT-LIB (SMT-LIB2 with transition system annotations)
+    Vmt,
+    /// ASCII AIGER circuit of the instance at the sort bounds given by --bound
+    Aiger,
+}
+
+#[derive(clap::ValueEnum, Copy, Clone, Debug, PartialEq, Eq)]
+enum SeenStatesArg {
+    /// Remember every state exactly
+    Full,
+    /// Remember a 64-bit hash of each state (may miss rare states)
+    Fingerprint64,
+    /// Remember a 128-bit hash of each state
+    Fingerprint128,
+}
+
 #[derive(clap::ValueEnum, Copy, Clone, Debug, PartialEq, Eq)]
 enum ColorOutput {
     Never,
@@ -76,7 +110,7 @@ struct VerifyArgs {
     /// Print timing statistics
     time: bool,
 
-    /// File name for a .fly file
+    /// File name for a .fly file (or a mypyvy .pyv file)
     file: String,
 }
 
@@ -143,10 +177,19 @@ struct InferenceConfigArgs {
     /// in a sequential fallback fashion.
     fallback: bool,
 
+    #[arg(long)]
+    /// Answer the solver queries in this many worker processes (restarted if they crash)
+    workers: Option<usize>,
+
     #[arg(long)]
     /// Defines the type of quantifier-free body (cnf/pdnf/pdnf-naive)
     qf_body: Option<String>,
 
+    #[arg(long)]
+    /// Race runs with several quantifier configurations, derived from the quantifier alternations
+    /// of the module, and report the first one to find a safe fixpoint (ignores the custom quantifiers)
+    auto_quant: bool,
+
     #[arg(long)]
     /// Do not search gradually for the quantified serach space needed to find an invariant,
     /// and instead begin with the maximal domain matching the specification.
@@ -209,6 +252,16 @@ struct InferenceConfigArgs {
     /// Try to extend model traces before looking for CEX in the frame
     extend_depth: Option<usize>,
 
+    #[arg(long)]
+    /// Before making any SMT queries, weaken the lemmas using the reachable states at the given
+    /// sort bounds, given as SORT=N as in --sample-bound node=2
+    sample_bound: Vec<String>,
+
+    #[arg(long)]
+    /// The maximal number of transitions from the initial states when sampling reachable states
+    /// (unbounded if not provided)
+    sample_depth: Option<usize>,
+
     #[arg(long)]
     /// Launch no new runs after safety has been proven
     until_safe: bool,
@@ -220,6 +273,11 @@ struct InferenceConfigArgs {
     #[arg(long)]
     /// Grow the domain of quantified lemmas by this factor each iteration (default: 5)
     growth_factor: Option<usize>,
+
+    #[arg(long)]
+    /// Write progress events (CTI's found, lemmas weakened, fixpoints reached, etc.)
+    /// to this file as JSON lines
+    events: Option<PathBuf>,
 }
 
 impl InferenceConfigArgs {
@@ -242,6 +300,7 @@ impl InferenceConfigArgs {
         let mut cfg = InferenceConfig {
             fname,
             fallback: self.fallback,
+            workers: self.workers,
             cfg: self.q_cfg_args.to_cfg(sig),
             qf_body,
             max_size: self.max_size.unwrap_or(fixpoint::defaults::MAX_QUANT),
@@ -258,10 +317,23 @@ impl InferenceConfigArgs {
             minimal_smt: self.minimal_smt,
             extend_width: self.extend_width,
             extend_depth: self.extend_depth,
+            sample_bound: match self.sample_bound.is_empty() {
+                true => None,
+                false => Some(parse_universe(&self.sample_bound, sig)),
+            },
+            sample_depth: self.sample_depth,
             no_search: self.no_search,
             until_safe: self.until_safe,
             abort_unsafe: self.abort_unsafe,
+            auto_quant: self.auto_quant,
             growth_factor: self.growth_factor,
+            events: match &self.events {
+                None => EventLog::default(),
+                Some(path) => EventLog::create(path).unwrap_or_else(|err| {
+                    eprintln!("could not create events file {}: {err}", path.display());
+                    process::exit(1)
+                }),
+            },
         };
 
         if self.qf_body.is_none() {
@@ -281,19 +353,89 @@ struct QalphaArgs {
     #[command(flatten)]
     infer_cfg: InferenceConfigArgs,
 
-    /// File name for a .fly file
+    /// File name for a .fly file (or a mypyvy .pyv file)
+    file: String,
+}
+
+#[derive(Args, Clone, Debug, PartialEq, Eq)]
+struct QalphaWorkerArgs {
+    #[arg(long)]
+    /// Try the solvers one by one in a sequential fallback fashion
+    fallback: bool,
+
+    /// File name for a .fly file (or a mypyvy .pyv file)
+    file: String,
+}
+
+#[derive(Args, Clone, Debug, PartialEq, Eq)]
+struct HoudiniArgs {
+    #[command(flatten)]
+    q_cfg_args: QuantifierConfigArgs,
+
+    #[arg(long)]
+    /// Also start from candidate invariants generated from templates: universally quantified
+    /// clauses with at most this many literals. The inferred invariant is then minimized
+    templates: Option<usize>,
+
+    #[arg(long)]
+    /// The maximal nesting depth of terms in the templates (unbounded if not provided)
+    nesting: Option<usize>,
+
+    #[arg(long, action)]
+    /// Do not include equality terms in the templates
+    no_include_eq: bool,
+
+    #[arg(long)]
+    /// Leave out templates that are false in a reachable state at the given sort bounds,
+    /// given as SORT=N as in --sample-bound node=2
+    sample_bound: Vec<String>,
+
+    #[arg(long)]
+    /// The maximal number of transitions from the initial states when sampling reachable states
+    /// (unbounded if not provided)
+    sample_depth: Option<usize>,
+
+    /// File name for a .fly file (or a mypyvy .pyv file)
+    file: String,
+}
+
+#[derive(Args, Clone, Debug, PartialEq, Eq)]
+struct FiniteArgs {
+    #[command(flatten)]
+    q_cfg_args: QuantifierConfigArgs,
+
+    /// What size bound to use for the given sort, given as SORT=N as in --bound node=2
+    #[arg(long)]
+    bound: Vec<String>,
+
+    #[arg(long)]
+    /// The maximal number of literals in each lemma (default: 3)
+    clause_size: Option<usize>,
+
+    #[arg(long)]
+    /// The maximal nesting depth of terms in the vocabulary (unbounded if not provided)
+    nesting: Option<usize>,
+
+    #[arg(long, action)]
+    /// Do not include equality terms in the vocabulary
+    no_include_eq: bool,
+
+    /// File name for a .fly file (or a mypyvy .pyv file)
     file: String,
 }
 
 #[derive(clap::Subcommand, Clone, Debug, PartialEq, Eq)]
 enum InferCommand {
     /// Run Houdini
-    Houdini {
-        /// File name for a .fly file
-        file: String,
-    },
+    Houdini(HoudiniArgs),
     /// Run quantified-alpha-from-below
     Qalpha(QalphaArgs),
+    /// Learn universally quantified lemmas from the reachable states at small bounds,
+    /// and keep the ones that are inductive
+    Finite(FiniteArgs),
+    /// Answer qalpha's solver queries read from stdin (started by qalpha --workers)
+    #[command(hide = true)]
+    QalphaWorker(QalphaWorkerArgs),
 }
 
 #[derive(Args, Clone, Debug, PartialEq, Eq)]
@@ -305,6 +447,15 @@ struct InferArgs {
     /// Print timing statistics
     time: bool,
 
+    #[arg(long, global = true)]
+    /// Stop after this many seconds, reporting the current (not yet inductive) invariant
+    time_limit: Option<u64>,
+
+    #[arg(long, global = true)]
+    /// Stop once the process uses this many MiB of memory, reporting the current
+    /// (not yet inductive) invariant
+    memory_limit: Option<usize>,
+
     #[arg(long)]
     /// Don't print the found invariant (for testing)
     no_print_invariant: bool,
@@ -315,7 +466,7 @@ struct InferArgs {
 
 #[derive(Args, Clone, Debug, PartialEq, Eq)]
 struct BoundedArgs {
-    /// File name for a .fly file
+    /// File name for a .fly file (or a mypyvy .pyv file)
     file: String,
     /// Maximum number of transitions to consider during model checking
     #[arg(long)]
@@ -333,52 +484,116 @@ impl BoundedArgs {
     ///
     /// Ensures that every sort in the given signature is given a bound.
     fn get_universe(&self, sig: &Signature) -> HashMap<String, usize> {
-        let mut universe: HashMap<String, usize> = HashMap::new();
-        for b in &self.bound {
-            if let [sort_name, bound_size] = b.split('=').collect::<Vec<&str>>()[..] {
-                let sort_name = sort_name.to_string();
-                if !sig.sorts.contains(&sort_name) {
-                    eprintln!("unknown sort name {sort_name} in bound {b}");
-                    process::exit(1);
-                }
-                if let Ok(bound_size) = bound_size.parse::<usize>() {
-                    universe.insert(sort_name, bound_size);
-                } else {
-                    eprintln!("could not parse bound as integer in {b}");
-                    process::exit(1);
-                }
+        parse_universe(&self.bound, sig)
+    }
+}
+
+/// Parses bounds of the form SORT=N into a universe size map.
+///
+/// Ensures that every sort in the given signature is given a bound.
+fn parse_universe(bounds: &[String], sig: &Signature) -> HashMap<String, usize> {
+    let mut universe: HashMap<String, usize> = HashMap::new();
+    for b in bounds {
+        if let [sort_name, bound_size] = b.split('=').collect::<Vec<&str>>()[..] {
+            let sort_name = sort_name.to_string();
+            if !sig.sorts.contains(&sort_name) {
+                eprintln!("unknown sort name {sort_name} in bound {b}");
+                process::exit(1);
+            }
+            if let Ok(bound_size) = bound_size.parse::<usize>() {
+                universe.insert(sort_name, bound_size);
             } else {
-                eprintln!("expected exactly one '=' in bound {b}");
+                eprintln!("could not parse bound as integer in {b}");
                 process::exit(1);
             }
-        }
-        if let Some(unbounded_sort) = sig.sorts.iter().find(|&s| !universe.contains_key(s)) {
-            eprintln!(
-                "need a bound for sort {unbounded_sort} on the command line, as in --bound {unbounded_sort}=N"
-            );
+        } else {
+            eprintln!("expected exactly one '=' in bound {b}");
             process::exit(1);
         }
-        universe
     }
+    if let Some(unbounded_sort) = sig.sorts.iter().find(|&s| !universe.contains_key(s)) {
+        eprintln!(
+            "need a bound for sort {unbounded_sort} on the command line, as in --bound {unbounded_sort}=N"
+        );
+        process::exit(1);
+    }
+    universe
 }
 
 #[derive(clap::Subcommand, Clone, Debug, PartialEq, Eq)]
 enum Command {
     /// Verify all assertions using user-provided invariants.
-    Verify(VerifyArgs),
+    Verify {
+        #[command(flatten)]
+        verify: VerifyArgs,
+        /// After verifying, report every minimal subset of each proof's invariants which
+        /// still proves its assertion, and the invariants implied by the others
+        #[arg(long)]
+        minimize_proof: bool,
+    },
     /// Verify assertions by inferring invariants with UPDR.
     UpdrVerify(VerifyArgs),
     /// Infer invariants using other invariant inference algorithms.
     Infer(InferArgs),
+    /// Compare an inferred invariant to the handwritten proof, reporting for each lemma
+    /// whether it is implied by the other side, equivalent to a lemma there, or stronger.
+    CompareInvariants {
+        #[command(flatten)]
+        solver: SolverArgs,
+        /// File name for a .fly file (or a mypyvy .pyv file) with the handwritten proof
+        file: String,
+        /// File with the inferred invariant, given by lines of the form `invariant <term>`
+        /// (such as the output of `infer`, from which the first proof is used)
+        inferred: String,
+    },
     /// Parse and re-print a fly file (for debugging)
     Print {
-        /// File name for a .fly file
+        /// File name for a .fly file (or a mypyvy .pyv file)
         file: String,
     },
     /// Parse a fly file, inline definitions, and print (for debugging)
     Inline {
+        /// File name for a .fly file (or a mypyvy .pyv file)
+        file: String,
+    },
+    /// Format a fly file, preserving comments
+    Fmt {
         /// File name for a .fly file
         file: String,
+        /// Check that the file is already formatted instead of printing it
+        #[arg(long)]
+        check: bool,
+        /// Overwrite the file with the formatted output
+        #[arg(long, conflicts_with = "check")]
+        write: bool,
+    },
+    /// Export the transition system in a format for other model checkers
+    Export {
+        /// Output format
+        #[arg(long, value_enum)]
+        format: ExportFormat,
+        /// What size bound to use for the given sort, given as SORT=N as in
+        /// --bound node=2 (only for finite formats)
+        #[arg(long)]
+        bound: Vec<String>,
+        /// File name for a .fly file (or a mypyvy .pyv file)
+        file: String,
+    },
+    /// Interactively simulate the module at the given sort bounds.
+    Simulate {
+        /// What size bound to use for the given sort, given as SORT=N as in
+        /// --bound node=2
+        #[arg(long)]
+        bound: Vec<String>,
+        /// Seed for random steps (random by default)
+        #[arg(long)]
+        seed: Option<u64>,
+        /// Run the commands in a trace saved by the simulator before reading
+        /// commands from standard input
+        #[arg(long)]
+        replay: Option<String>,
+        /// File name for a .fly file (or a mypyvy .pyv file)
+        file: String,
     },
     /// Apply bounded model checking to each assertion using a set of states.
     SetCheck {
@@ -387,9 +602,39 @@ enum Command {
         /// Whether to only keep track of the last state of the trace
         #[arg(long)]
         compress_traces: bool,
+        /// Search with this many random walks instead of exploring every
+        /// reachable state (requires --depth)
+        #[arg(long, conflicts_with = "compress_traces")]
+        walks: Option<usize>,
+        /// Seed for the random walks
+        #[arg(long, default_value_t = 0, requires = "walks")]
+        seed: u64,
+        /// Maximum number of states each random walk remembers, to avoid
+        /// revisiting them
+        #[arg(long, default_value_t = 100_000, requires = "walks")]
+        seen_limit: usize,
+        /// How to remember the states that have been seen
+        #[arg(long, value_enum, default_value_t = SeenStatesArg::Full, conflicts_with = "walks")]
+        seen: SeenStatesArg,
+        /// Number of threads for expanding the frontier (all cores by default)
+        #[arg(long, conflicts_with = "walks")]
+        threads: Option<usize>,
+        /// Directory to spill the frontier to when it gets large
+        #[arg(long, conflicts_with = "walks")]
+        spill_dir: Option<String>,
+        /// Number of frontier states to keep in memory before spilling
+        #[arg(long, default_value_t = 1_000_000, requires = "spill_dir")]
+        spill_after: usize,
     },
     /// Apply bounded model checking to each assertion using a SAT solver.
-    SatCheck(BoundedArgs),
+    SatCheck {
+        #[command(flatten)]
+        bounded: BoundedArgs,
+        /// Whether to also try to prove safety at any depth using k-induction
+        /// (always used without --depth)
+        #[arg(long)]
+        k_induction: bool,
+    },
     /// Apply bounded model checking to each assertion using binary decision
     /// diagrams (BDDs).
     BddCheck {
@@ -399,6 +644,15 @@ enum Command {
         #[arg(long)]
         reversed: bool,
     },
+    /// Check each assertion at the given sort bounds using IC3/PDR with a
+    /// SAT solver.
+    Ic3Check {
+        #[command(flatten)]
+        bounded: BoundedArgs,
+        /// Whether to print the inductive invariant if one is found
+        #[arg(long)]
+        print_invariant: bool,
+    },
     /// Apply bounded model checking to each assertion using an SMT solver.
     SmtCheck {
         #[command(flatten)]
@@ -411,8 +665,10 @@ enum Command {
 impl InferCommand {
     fn file(&self) -> &str {
         match self {
-            InferCommand::Houdini { file } => file,
+            InferCommand::Houdini(HoudiniArgs { file, .. }) => file,
             InferCommand::Qalpha(QalphaArgs { file, .. }) => file,
+            InferCommand::Finite(FiniteArgs { file, .. }) => file,
+            InferCommand::QalphaWorker(QalphaWorkerArgs { file, .. }) => file,
         }
     }
 }
@@ -420,20 +676,34 @@ impl InferCommand {
 impl Command {
     fn file(&self) -> &str {
         match self {
-            Command::Verify(VerifyArgs { file, .. }) => file,
+            Command::Verify {
+                verify: VerifyArgs { file, .. },
+                ..
+            } => file,
             Command::Infer(InferArgs { infer_cmd, .. }) => infer_cmd.file(),
             Command::UpdrVerify(VerifyArgs { file, .. }) => file,
+            Command::CompareInvariants { file, .. } => file,
             Command::Print { file, .. } => file,
             Command::Inline { file, .. } => file,
+            Command::Fmt { file, .. } => file,
+            Command::Export { file, .. } => file,
+            Command::Simulate { file, .. } => file,
             Command::SetCheck {
                 bounded: BoundedArgs { file, .. },
                 ..
             } => file,
-            Command::SatCheck(BoundedArgs { file, .. }) => file,
+            Command::SatCheck {
+                bounded: BoundedArgs { file, .. },
+                ..
+            } => file,
             Command::BddCheck {
                 bounded: BoundedArgs { file, .. },
                 ..
             } => file,
+            Command::Ic3Check {
+                bounded: BoundedArgs { file, .. },
+                ..
+            } => file,
             Command::SmtCheck {
                 bounded: BoundedArgs { file, .. },
                 ..
@@ -485,6 +755,43 @@ impl InferArgs {
         self.solver
             .get_solver_conf(&self.infer_cmd.file().to_string())
     }
+
+    fn budget(&self) -> Budget {
+        Budget {
+            time: self.time_limit.map(Duration::from_secs),
+            memory: self.memory_limit.map(|mib| mib * 1024 * 1024),
+        }
+    }
+}
+
+/// Read the invariants in the given file, from its lines of the form `invariant <term>`.
+/// Other lines are ignored, so that the output of `infer` can be used as is. Since that
+/// output may contain several proofs (such as a fixpoint and its minimized version), only
+/// the invariants of the first `proof { ... }` block are read if there is one.
+fn read_invariants(path: &str, sig: &Signature) -> Result<Vec<Term>, String> {
+    let contents =
+        fs::read_to_string(path).map_err(|err| format!("could not read {path}: {err}"))?;
+    let lines = contents.lines().enumerate();
+    let lines: Box<dyn Iterator<Item = (usize, &str)>> =
+        match contents.lines().position(|line| line.trim() == "proof {") {
+            Some(start) => Box::new(
+                lines
+                    .skip(start + 1)
+                    .take_while(|(_, line)| line.trim() != "}"),
+            ),
+            None => Box::new(lines),
+        };
+    let mut invariants = vec![];
+    for (i, line) in lines {
+        if let Some(inv) = line.trim().strip_prefix("invariant ") {
+            let mut term = fly::parser::parse_term(inv)
+                .map_err(|err| format!("{path}:{}: could not parse invariant: {err}", i + 1))?;
+            sorts::sort_check_term(sig, &mut term)
+                .map_err(|err| format!("{path}:{}: {err}", i + 1))?;
+            invariants.push(term);
+        }
+    }
+    Ok(invariants)
 }
 
 impl App {
@@ -506,25 +813,92 @@ impl App {
             ..Default::default()
         };
 
-        let mut m = match fly::parser::parse(&file) {
-            Ok(v) => v,
-            Err(err) => {
-                let diagnostic = parse_error_diagnostic((), &err);
+        let sort_error_diagnostic = |err: &sorts::SortError, span: Option<Span>| {
+            let mut diagnostic = Diagnostic::error().with_message(format!("{err}"));
+            if let Some(span) = span {
+                diagnostic = diagnostic.with_labels(vec![Label::primary((), span.start..span.end)]);
+            }
+            diagnostic
+        };
+        let report_sort_errors = |errors: &[(sorts::SortError, Option<Span>)]| {
+            match errors.len() {
+                0 => return,
+                1 => eprintln!("sort checking error:"),
+                _ => eprintln!("sort checking errors:"),
+            }
+            for (err, span) in errors {
+                let diagnostic = sort_error_diagnostic(err, *span);
                 terminal::emit(&mut writer.lock(), &config, &files, &diagnostic).unwrap();
+            }
+        };
+
+        let is_mypyvy = Path::new(self.command.file())
+            .extension()
+            .is_some_and(|ext| ext == "pyv");
+        let parsed = if is_mypyvy {
+            if matches!(self.command, Command::Fmt { .. }) {
+                eprintln!("fmt only supports fly files");
                 process::exit(1);
             }
+            match mypyvy::parse(&file) {
+                Ok(m) => Ok(m),
+                Err(err) => {
+                    let diagnostic = import_error_diagnostic((), &err);
+                    terminal::emit(&mut writer.lock(), &config, &files, &diagnostic).unwrap();
+                    process::exit(1);
+                }
+            }
+        } else {
+            fly::parser::parse(&file)
         };
 
-        let r = sorts::sort_check_module(&mut m);
-        if let Err((err, span)) = r {
-            eprintln!("sort checking error:");
+        let mut m = match parsed {
+            Ok(v) => v,
+            Err(err) => {
+                // re-parse with error recovery to report all syntax errors,
+                // and then sort check whatever could be parsed
+                let (mut m, errors) = parse_recovering(&file);
+                if errors.is_empty() {
+                    let diagnostic = parse_error_diagnostic((), &err);
+                    terminal::emit(&mut writer.lock(), &config, &files, &diagnostic).unwrap();
+                }
+                for err in &errors {
+                    let diagnostic = syntax_error_diagnostic((), err);
+                    terminal::emit(&mut writer.lock(), &config, &files, &diagnostic).unwrap();
+                }
+                report_sort_errors(&sorts::sort_check_module_recovering(&mut m));
+                process::exit(1);
+            }
+        };
 
-            let mut diagnostic = Diagnostic::error().with_message(format!("{err}"));
-            if let Some(span) = span {
-                diagnostic = diagnostic.with_labels(vec![Label::primary((), span.start..span.end)]);
+        // formatting does not require the module to sort check
+        if let Command::Fmt {
+            file: path,
+            check,
+            write,
+        } = &self.command
+        {
+            let formatted = format::format(&file).expect("module should parse");
+            if *check {
+                if formatted != file {
+                    let line = (file.lines().zip(formatted.lines()))
+                        .take_while(|(a, b)| a == b)
+                        .count()
+                        + 1;
+                    eprintln!("{path}:{line}: file is not formatted");
+                    process::exit(1);
+                }
+            } else if *write {
+                fs::write(path, formatted).expect("could not write formatted file");
+            } else {
+                print!("{formatted}");
             }
-            terminal::emit(&mut writer.lock(), &config, &files, &diagnostic).unwrap();
+            return;
+        }
 
+        let sort_errors = sorts::sort_check_module_recovering(&mut m);
+        if !sort_errors.is_empty() {
+            report_sort_errors(&sort_errors);
             process::exit(1);
         }
 
@@ -533,7 +907,10 @@ impl App {
                 // don't inline for printing
                 println!("{}", printer::fmt(&m));
             }
-            Command::Verify(ref args) => {
+            Command::Verify {
+                verify: ref args,
+                minimize_proof,
+            } => {
                 let conf = args.get_solver_conf();
                 m.inline_defs();
                 let r = verify_module(&conf, &m);
@@ -541,7 +918,15 @@ impl App {
                     timing::report();
                 }
                 match r {
-                    Ok(()) => println!("verifies!"),
+                    Ok(()) => {
+                        println!("verifies!");
+                        if minimize_proof {
+                            let solver = SingleSolver::new(conf);
+                            for analysis in minimize::minimize_proofs(&m, &solver) {
+                                analysis.report();
+                            }
+                        }
+                    }
                     Err(err) => {
                         eprintln!("verification errors:");
 
@@ -555,15 +940,79 @@ impl App {
                     }
                 }
             }
+            Command::CompareInvariants {
+                ref solver,
+                ref file,
+                ref inferred,
+            } => {
+                m.inline_defs();
+                let handwritten = match m.statements.iter().find_map(|s| match s {
+                    ThmStmt::Assert(proof) if !proof.invariants.is_empty() => Some(proof),
+                    _ => None,
+                }) {
+                    Some(proof) => proof
+                        .invariants
+                        .iter()
+                        .map(|inv| inv.x.clone())
+                        .collect::<Vec<_>>(),
+                    None => {
+                        eprintln!("{file} has no proof with invariants");
+                        process::exit(1);
+                    }
+                };
+                let inferred = read_invariants(inferred, &m.signature).unwrap_or_else(|err| {
+                    eprintln!("{err}");
+                    process::exit(1);
+                });
+                let solver = SingleSolver::new(solver.get_solver_conf(file));
+                let comparison = compare::compare(&m, &solver, &inferred, &handwritten);
+                comparison.report(&inferred, &handwritten);
+            }
             Command::Infer(
                 ref args @ InferArgs {
-                    infer_cmd: InferCommand::Houdini { .. },
+                    infer_cmd: InferCommand::Houdini(ref hargs),
                     ..
                 },
             ) => {
                 let conf = args.get_solver_conf();
                 m.inline_defs();
-                let r = houdini::infer_module(&conf, &m);
+                let candidates = match hargs.templates {
+                    None => vec![],
+                    Some(clause_size) => {
+                        let states = match hargs.sample_bound.is_empty() {
+                            true => vec![],
+                            false => {
+                                let universe = parse_universe(&hargs.sample_bound, &m.signature);
+                                match bounded::set::reachable(
+                                    &m,
+                                    &universe,
+                                    hargs.sample_depth,
+                                    false,
+                                ) {
+                                    Ok(states) => states,
+                                    Err(err) => {
+                                        eprintln!("{err}");
+                                        process::exit(1);
+                                    }
+                                }
+                            }
+                        };
+                        let candidates = houdini::template_candidates(
+                            &hargs.q_cfg_args.to_cfg(&m.signature),
+                            hargs.nesting,
+                            !hargs.no_include_eq,
+                            clause_size,
+                            &states,
+                        );
+                        println!(
+                            "generated {} candidate invariants from templates",
+                            candidates.len()
+                        );
+                        candidates
+                    }
+                };
+                let r =
+                    houdini::infer_module_with_candidates(&conf, &m, &candidates, &args.budget());
                 if args.time {
                     timing::report();
                 }
@@ -594,27 +1043,141 @@ impl App {
                         .infer_cfg
                         .to_cfg(&m.signature, args.infer_cmd.file().to_string()),
                 );
-                qalpha_dynamic(infer_cfg, &m, !args.no_print_invariant);
+                if let Err(err) =
+                    qalpha_dynamic(infer_cfg, &m, !args.no_print_invariant, &args.budget())
+                {
+                    eprintln!("could not start the workers: {err}");
+                    process::exit(1);
+                }
+                if args.time {
+                    timing::report();
+                }
+            }
+            Command::Infer(InferArgs {
+                infer_cmd: InferCommand::QalphaWorker(ref wargs),
+                ..
+            }) => {
+                if let Err(err) = fixpoint::qalpha_worker(&wargs.file, wargs.fallback) {
+                    eprintln!("worker failed: {err}");
+                    process::exit(1);
+                }
+            }
+            Command::Infer(
+                ref args @ InferArgs {
+                    infer_cmd: InferCommand::Finite(ref fargs),
+                    ..
+                },
+            ) => {
+                m.inline_defs();
+                let universe = parse_universe(&fargs.bound, &m.signature);
+                let cfg = fargs.q_cfg_args.to_cfg(&m.signature);
+                let atoms = templates::atoms(&cfg, fargs.nesting, !fargs.no_include_eq);
+                let clause_size = fargs.clause_size.unwrap_or(3);
+                // the budget is checked at every depth of the search and for every clause,
+                // and then the queries are killed once it is exhausted
+                let canceled: SolverCancelers<SmtPid> = SolverCancelers::new();
+                let (r, _) = args.budget().enforce(&canceled, || {
+                    finite::reachable_lemmas(&m, &universe, &cfg, &atoms, clause_size, &|| {
+                        canceled.is_canceled()
+                    })
+                    .and_then(|lemmas| {
+                        println!(
+                            "found {} lemmas that hold in all reachable states",
+                            lemmas.len()
+                        );
+                        finite::inductive_lemmas(&args.get_solver_conf(), &m, lemmas, &canceled)
+                    })
+                });
                 if args.time {
                     timing::report();
                 }
+                match r {
+                    Ok(lemmas) => {
+                        println!("found an inductive invariant with {} lemmas", lemmas.len());
+                        if !args.no_print_invariant {
+                            println!("proof {{");
+                            for lemma in &lemmas {
+                                println!("  invariant {lemma}");
+                            }
+                            println!("}}");
+                        }
+                    }
+                    Err(finite::FiniteError::BudgetExhausted {
+                        lemmas,
+                        relatively_inductive,
+                        ctis,
+                    }) => {
+                        println!(
+                            "budget exhausted with {} lemmas that are not yet inductive, \
+                             {} of them inductive relative to the rest and {} outstanding CTI's",
+                            lemmas.len(),
+                            relatively_inductive.len(),
+                            ctis.len()
+                        );
+                        if !args.no_print_invariant {
+                            println!("proof {{");
+                            for lemma in &lemmas {
+                                println!("  invariant {lemma}");
+                            }
+                            println!("}}");
+                            println!("# inductive relative to the rest:");
+                            for lemma in &relatively_inductive {
+                                println!("#   {lemma}");
+                            }
+                        }
+                        process::exit(1);
+                    }
+                    Err(err) => {
+                        eprintln!("{err}");
+                        process::exit(1);
+                    }
+                }
             }
+            Command::Fmt { .. } => unreachable!("formatting is done before sort checking"),
             Command::Inline { .. } => {
                 let mut m = m;
                 m.inline_defs();
                 println!("{}", printer::fmt(&m));
             }
+            Command::Export { format, bound, .. } => {
+                m.inline_defs();
+                match format {
+                    ExportFormat::Vmt => {
+                        let d = match extract(&m) {
+                            Ok(d) => d,
+                            Err(err) => {
+                                eprintln!("could not extract transition system: {err}");
+                                process::exit(1);
+                            }
+                        };
+                        print!("{}", vmt::to_vmt(&m.signature, &d))
+                    }
+                    ExportFormat::Aiger => {
+                        let univ = parse_universe(&bound, &m.signature);
+                        match bounded::aiger::export(&m, &univ) {
+                            Ok(aag) => print!("{aag}"),
+                            Err(error) => {
+                                eprintln!("{error}");
+                                process::exit(1)
+                            }
+                        }
+                    }
+                }
+            }
             Command::UpdrVerify(ref args @ VerifyArgs { .. }) => {
                 let conf = Arc::new(SingleSolver::new(args.get_solver_conf()));
                 let mut updr = Updr::new(conf);
                 let _result = updr.search(&m);
             }
 
-            Command::SetCheck {
-                bounded,
-                compress_traces,
+            Command::Simulate {
+                bound,
+                seed,
+                replay,
+                ..
             } => {
                 m.inline_defs();
+                let signature = m.signature.clone();
                 let back_convert_model = match m.convert_non_bool_relations() {
                     Ok(f) => f,
                     Err(e) => {
@@ -622,19 +1185,96 @@ impl App {
                         process::exit(1)
                     }
                 };
+                let univ = parse_universe(&bound, &m.signature);
+                let sim = match Simulator::new(&m, &univ) {
+                    Ok(sim) => sim,
+                    Err(error) => {
+                        eprintln!("{error}");
+                        process::exit(1)
+                    }
+                };
+                let rng = match seed {
+                    Some(seed) => StdRng::seed_from_u64(seed),
+                    None => StdRng::from_entropy(),
+                };
+                let mut repl = Repl::new(&sim, &signature, &back_convert_model, rng);
+                let mut out = io::stdout().lock();
+                match replay {
+                    Some(replay) => {
+                        let script = fs::read_to_string(&replay).unwrap_or_else(|e| {
+                            eprintln!("could not read {replay}: {e}");
+                            process::exit(1)
+                        });
+                        repl.run(script.as_bytes(), &mut out, false);
+                    }
+                    None => {
+                        repl.command("init", &mut out);
+                    }
+                }
+                repl.run(io::stdin().lock(), &mut out, true);
+            }
+            Command::SetCheck {
+                bounded,
+                compress_traces,
+                walks,
+                seed,
+                seen_limit,
+                seen,
+                threads,
+                spill_dir,
+                spill_after,
+            } => {
+                m.inline_defs();
                 let univ = bounded.get_universe(&m.signature);
-                match bounded::set::check(
-                    &m,
-                    &univ,
-                    bounded.depth,
-                    compress_traces.into(),
-                    bounded.print_timing.unwrap_or(true),
-                ) {
+                let print_timing = bounded.print_timing.unwrap_or(true);
+                let answer = match walks {
+                    Some(walks) => {
+                        let Some(depth) = bounded.depth else {
+                            eprintln!("random walks do not support unbounded depth. please specify --depth N on the command line");
+                            process::exit(1)
+                        };
+                        let config = bounded::set::WalkConfig {
+                            walks,
+                            depth,
+                            seed,
+                            seen_limit,
+                        };
+                        bounded::set::simulate(&m, &univ, &config, print_timing)
+                    }
+                    None => {
+                        let config = bounded::set::SearchConfig {
+                            seen: match seen {
+                                SeenStatesArg::Full => bounded::set::SeenStates::Full,
+                                SeenStatesArg::Fingerprint64 => {
+                                    bounded::set::SeenStates::Fingerprint64
+                                }
+                                SeenStatesArg::Fingerprint128 => {
+                                    bounded::set::SeenStates::Fingerprint128
+                                }
+                            },
+                            threads,
+                            spill_dir: spill_dir.map(PathBuf::from),
+                            spill_after,
+                        };
+                        bounded::set::check_with(
+                            &m,
+                            &univ,
+                            bounded.depth,
+                            compress_traces.into(),
+                            print_timing,
+                            &config,
+                        )
+                    }
+                };
+                match answer {
                     Ok(CheckerAnswer::Counterexample(models)) => {
+                        println!("found counterexample:\n{}", models_to_string(&models))
+                    }
+                    Ok(CheckerAnswer::Unknown) if walks.is_some() => {
                         println!(
-                            "found counterexample:\n{}",
-                            models_to_string(models.iter().map(back_convert_model))
-                        )
+                            "answer: no counterexample found by random walks up to depth {} for given sort bounds",
+                            bounded.depth.unwrap()
+                        );
                     }
                     Ok(CheckerAnswer::Unknown) => {
                         println!(
@@ -651,46 +1291,36 @@ impl App {
                     Err(error) => eprintln!("{error}"),
                 }
             }
-            Command::SatCheck(bounded) => {
+            Command::SatCheck {
+                bounded,
+                k_induction,
+            } => {
                 m.inline_defs();
-                let back_convert_model = match m.convert_non_bool_relations() {
-                    Ok(f) => f,
-                    Err(e) => {
-                        eprintln!("{e}");
-                        process::exit(1)
-                    }
-                };
-                let depth = match bounded.depth {
-                    Some(depth) => depth,
-                    None => {
-                        eprintln!("sat checker does not support unbounded depth. please specify --depth N on the command line");
-                        process::exit(1)
-                    }
-                };
                 let univ = bounded.get_universe(&m.signature);
-                match bounded::sat::check(&m, &univ, depth, bounded.print_timing.unwrap_or(true)) {
+                match bounded::sat::check(
+                    &m,
+                    &univ,
+                    bounded.depth,
+                    k_induction,
+                    bounded.print_timing.unwrap_or(true),
+                ) {
                     Ok(CheckerAnswer::Counterexample(models)) => {
+                        println!("found counterexample:\n{}", models_to_string(&models))
+                    }
+                    Ok(CheckerAnswer::Unknown) => {
                         println!(
-                            "found counterexample:\n{}",
-                            models_to_string(models.iter().map(back_convert_model))
+                            "answer: safe up to depth {} for given sort bounds",
+                            bounded.depth.unwrap()
                         )
                     }
-                    Ok(CheckerAnswer::Unknown) => {
-                        println!("answer: safe up to depth {depth} for given sort bounds")
+                    Ok(CheckerAnswer::Convergence(())) => {
+                        println!("answer: safe forever with given sort bounds")
                     }
-                    Ok(CheckerAnswer::Convergence(())) => unreachable!(),
                     Err(error) => eprintln!("{error}"),
                 }
             }
             Command::BddCheck { bounded, reversed } => {
                 m.inline_defs();
-                let back_convert_model = match m.convert_non_bool_relations() {
-                    Ok(f) => f,
-                    Err(e) => {
-                        eprintln!("{e}");
-                        process::exit(1)
-                    }
-                };
                 let univ = bounded.get_universe(&m.signature);
                 let check = match reversed {
                     false => bounded::bdd::check,
@@ -700,13 +1330,11 @@ impl App {
                     &m,
                     &univ,
                     bounded.depth,
+                    false,
                     bounded.print_timing.unwrap_or(true),
                 ) {
                     Ok(CheckerAnswer::Counterexample(models)) => {
-                        println!(
-                            "found counterexample:\n{}",
-                            models_to_string(models.iter().map(back_convert_model))
-                        )
+                        println!("found counterexample:\n{}", models_to_string(&models))
                     }
                     Ok(CheckerAnswer::Unknown) => {
                         println!(
@@ -723,6 +1351,46 @@ impl App {
                     Err(error) => eprintln!("{error}"),
                 }
             }
+            Command::Ic3Check {
+                bounded,
+                print_invariant,
+            } => {
+                m.inline_defs();
+                let univ = bounded.get_universe(&m.signature);
+                match bounded::ic3::check(
+                    &m,
+                    &univ,
+                    bounded.depth,
+                    bounded.print_timing.unwrap_or(true),
+                ) {
+                    Ok(CheckerAnswer::Counterexample(models)) => {
+                        println!("found counterexample:\n{}", models_to_string(&models))
+                    }
+                    Ok(CheckerAnswer::Unknown) => {
+                        println!(
+                            "answer: safe up to depth {} for given sort bounds",
+                            bounded.depth.unwrap()
+                        );
+                    }
+                    Ok(CheckerAnswer::Convergence((clauses, indices))) => {
+                        println!("answer: safe forever with given sort bounds");
+                        if print_invariant {
+                            let (terms, bindings) =
+                                bounded::ic3::clauses_to_terms(&clauses, &indices);
+                            println!("inductive invariant:");
+                            for term in terms {
+                                println!("  {term}");
+                            }
+                            let mut bindings: Vec<_> = bindings.into_iter().collect();
+                            bindings.sort();
+                            for ((sort, i), name) in bindings {
+                                println!("where {name} = @{sort}_{i}");
+                            }
+                        }
+                    }
+                    Err(error) => eprintln!("{error}"),
+                }
+            }
             Command::SmtCheck { bounded, solver } => {
                 m.inline_defs();
                 let depth = match bounded.depth {
@@ -19,6 +19,8 @@
 
 pub mod command;
 pub mod concurrent;
+pub mod simulate;
+pub mod vmt;
 
 #[doc(hidden)]
 pub use command::App;
@@ -0,0 +1,384 @@
+// Copyright 2022-2023 VMware, Inc.
+// SPDX-License-Identifier: BSD-2-Clause
+
+//! An interactive simulator for exploring the behavior of a module at given
+//! sort bounds.
+//!
+//! Every command that changes the current state is recorded in a normalized
+//! form (random choices are replaced by the choice that was made), so that
+//! `save` writes out a script that reproduces the trace when passed back with
+//! `--replay`.
+
+use std::io::{BufRead, Write};
+
+use bounded::simulate::{Simulator, State};
+use fly::{
+    parser::parse_term,
+    semantics::{Element, Model},
+    sorts::sort_check_term,
+    syntax::{Binder, Signature, Sort},
+};
+use rand::{rngs::StdRng, Rng};
+
+const HELP: &str = "\
+commands:
+  init [N | random | =STATE]  restart from the Nth initial state (default 0)
+  actions                     list the actions of the transition relation
+  enabled                     list the enabled actions, as step commands
+  step A [ARG...] [@K]        take action A with the given arguments, going to
+                              its Kth successor (default 0)
+  random [N]                  take N random steps (default 1)
+  undo                        go back one step
+  state                       print the current state
+  trace                       print every state of the trace
+  eval TERM                   evaluate a term in the current state
+  save FILE                   save the trace as a script for --replay
+  help                        print this message
+  quit                        exit the simulator";
+
+/// The state of a simulation session.
+pub struct Repl<'a> {
+    sim: &'a Simulator<'a>,
+    /// The signature before non-boolean relations were converted, in which
+    /// user terms are interpreted
+    signature: &'a Signature,
+    back_convert: &'a dyn Fn(&Model) -> Model,
+    rng: StdRng,
+    /// The states of the trace so far, starting from an initial state
+    trace: Vec<State>,
+    /// The command that produced each state of the trace
+    commands: Vec<String>,
+}
+
+fn element(sort: &Sort, e: Element) -> String {
+    match sort {
+        Sort::Bool => (e == 1).to_string(),
+        Sort::Uninterpreted(s) => format!("@{s}_{e}"),
+    }
+}
+
+fn assignment(binders: &[Binder], args: &[Element]) -> String {
+    (binders.iter().zip(args))
+        .map(|(b, &e)| format!("{}={}", b.name, element(&b.sort, e)))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn parse_number(s: &str) -> Result<usize, String> {
+    s.parse()
+        .map_err(|_| format!("expected a number but found {s}"))
+}
+
+impl<'a> Repl<'a> {
+    /// Start a session. There is no current state until the first `init`.
+    pub fn new(
+        sim: &'a Simulator<'a>,
+        signature: &'a Signature,
+        back_convert: &'a dyn Fn(&Model) -> Model,
+        rng: StdRng,
+    ) -> Self {
+        Repl {
+            sim,
+            signature,
+            back_convert,
+            rng,
+            trace: vec![],
+            commands: vec![],
+        }
+    }
+
+    fn current(&self) -> Result<&State, String> {
+        self.trace
+            .last()
+            .ok_or_else(|| "no current state (use init)".to_string())
+    }
+
+    fn model(&self, state: &State) -> Model {
+        (self.back_convert)(&self.sim.model(state))
+    }
+
+    fn push(&mut self, state: State, command: String, out: &mut impl Write) {
+        writeln!(out, "state {}", self.trace.len()).unwrap();
+        for i in self.sim.violations(&state) {
+            writeln!(out, "assertion {i} does not hold").unwrap();
+        }
+        self.trace.push(state);
+        self.commands.push(command);
+    }
+
+    fn init(&mut self, args: &[&str], out: &mut impl Write) -> Result<(), String> {
+        let state = match args {
+            [] => self.sim.initial(0),
+            ["random"] => self.sim.random_initial(&mut self.rng),
+            [n] => match n.strip_prefix('=') {
+                Some(bits) => self.sim.parse_state(bits),
+                None => self.sim.initial(parse_number(n)?),
+            },
+            _ => return Err("usage: init [N | random | =STATE]".to_string()),
+        };
+        let state = state.ok_or_else(|| "no such initial state".to_string())?;
+        self.trace.clear();
+        self.commands.clear();
+        writeln!(
+            out,
+            "starting from one of {} initial states",
+            self.sim.num_initial()
+        )
+        .unwrap();
+        self.push(state.clone(), format!("init ={state}"), out);
+        Ok(())
+    }
+
+    fn actions(&self, out: &mut impl Write) {
+        for (i, action) in self.sim.actions.iter().enumerate() {
+            let binders = (action.binders.iter())
+                .map(|b| format!("{}:{}", b.name, b.sort))
+                .collect::<Vec<_>>()
+                .join(", ");
+            writeln!(out, "action {i}({binders}): {}", action.body).unwrap();
+        }
+    }
+
+    /// The `step` command that takes the given successor of an instance.
+    fn step_command(&self, instance: usize, successor: usize) -> String {
+        let instance = &self.sim.instances[instance];
+        let mut command = format!("step {}", instance.action);
+        for arg in &instance.args {
+            command.push_str(&format!(" {arg}"));
+        }
+        if successor > 0 {
+            command.push_str(&format!(" @{successor}"));
+        }
+        command
+    }
+
+    fn enabled(&self, out: &mut impl Write) -> Result<(), String> {
+        let state = self.current()?;
+        let enabled = self.sim.enabled(state);
+        if enabled.is_empty() {
+            writeln!(out, "no actions are enabled").unwrap();
+        }
+        for (i, successors) in enabled {
+            let instance = &self.sim.instances[i];
+            let action = &self.sim.actions[instance.action];
+            let mut line = format!(
+                "{:<20} # action {}({})",
+                self.step_command(i, 0),
+                instance.action,
+                assignment(&action.binders, &instance.args),
+            );
+            if successors.len() > 1 {
+                line.push_str(&format!(", {} successors", successors.len()));
+            }
+            writeln!(out, "{line}").unwrap();
+        }
+        Ok(())
+    }
+
+    fn step(&mut self, args: &[&str], out: &mut impl Write) -> Result<(), String> {
+        let (successor, args) = match args.split_last() {
+            Some((last, rest)) if last.starts_with('@') => (parse_number(&last[1..])?, rest),
+            _ => (0, args),
+        };
+        let [action, args @ ..] = args else {
+            return Err("usage: step A [ARG...] [@K]".to_string());
+        };
+        let action = parse_number(action)?;
+        let args = args
+            .iter()
+            .map(|arg| parse_number(arg))
+            .collect::<Result<Vec<_>, _>>()?;
+        let instance = self
+            .sim
+            .instance(action, &args)
+            .ok_or_else(|| format!("there is no action {action} with arguments {args:?}"))?;
+        let successors = self
+            .sim
+            .successors(self.current()?, &self.sim.instances[instance]);
+        if successors.is_empty() {
+            return Err("that action is not enabled".to_string());
+        }
+        let Some(next) = successors.into_iter().nth(successor) else {
+            return Err(format!("that action has no successor {successor}"));
+        };
+        let command = self.step_command(instance, successor);
+        self.push(next, command, out);
+        Ok(())
+    }
+
+    fn random(&mut self, args: &[&str], out: &mut impl Write) -> Result<(), String> {
+        let n = match args {
+            [] => 1,
+            [n] => parse_number(n)?,
+            _ => return Err("usage: random [N]".to_string()),
+        };
+        for _ in 0..n {
+            let enabled = self.sim.enabled(self.current()?);
+            if enabled.is_empty() {
+                return Err("no actions are enabled".to_string());
+            }
+            let choice = self.rng.gen_range(0..enabled.len());
+            let (instance, mut successors) = enabled.into_iter().nth(choice).unwrap();
+            let successor = self.rng.gen_range(0..successors.len());
+            let command = self.step_command(instance, successor);
+            writeln!(out, "{command}").unwrap();
+            self.push(successors.swap_remove(successor), command, out);
+        }
+        Ok(())
+    }
+
+    fn eval(&self, term: &str, out: &mut impl Write) -> Result<(), String> {
+        let model = self.model(self.current()?);
+        let mut term = parse_term(term).map_err(|e| format!("expected {}", e.expected))?;
+        let sort = sort_check_term(self.signature, &mut term).map_err(|e| format!("{e}"))?;
+        writeln!(out, "{}", element(&sort, model.eval(&term))).unwrap();
+        Ok(())
+    }
+
+    fn save(&self, file: &str) -> Result<(), String> {
+        let script = (self.commands.iter())
+            .map(|command| format!("{command}\n"))
+            .collect::<String>();
+        std::fs::write(file, script).map_err(|e| format!("could not write {file}: {e}"))
+    }
+
+    /// Run a single command. Returns false if the session should end.
+    pub fn command(&mut self, line: &str, out: &mut impl Write) -> bool {
+        let line = line.split('#').next().unwrap().trim();
+        let (command, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let args = rest.split_whitespace().collect::<Vec<_>>();
+        let result = match command {
+            "" => Ok(()),
+            "init" => self.init(&args, out),
+            "actions" => {
+                self.actions(out);
+                Ok(())
+            }
+            "enabled" => self.enabled(out),
+            "step" => self.step(&args, out),
+            "random" => self.random(&args, out),
+            "undo" => {
+                if self.trace.len() > 1 {
+                    self.trace.pop();
+                    self.commands.pop();
+                    writeln!(out, "state {}", self.trace.len() - 1).unwrap();
+                    Ok(())
+                } else {
+                    Err("already at the initial state".to_string())
+                }
+            }
+            "state" => self
+                .current()
+                .map(|state| write!(out, "{}", self.model(state)).unwrap()),
+            "trace" => {
+                for (i, state) in self.trace.iter().enumerate() {
+                    writeln!(out, "state {i}:\n{}", self.model(state)).unwrap();
+                }
+                Ok(())
+            }
+            "eval" => self.eval(rest, out),
+            "save" => match args[..] {
+                [file] => self.save(file),
+                _ => Err("usage: save FILE".to_string()),
+            },
+            "help" => {
+                writeln!(out, "{HELP}").unwrap();
+                Ok(())
+            }
+            "quit" | "exit" => return false,
+            _ => Err(format!("unknown command {command} (try help)")),
+        };
+        if let Err(e) = result {
+            writeln!(out, "error: {e}").unwrap();
+        }
+        true
+    }
+
+    /// Run commands from `input` until it ends or the user quits, prompting for
+    /// each command if `prompt` is set.
+    pub fn run(&mut self, input: impl BufRead, out: &mut impl Write, prompt: bool) {
+        let mut lines = input.lines();
+        loop {
+            if prompt {
+                write!(out, "> ").unwrap();
+                out.flush().unwrap();
+            }
+            let Some(Ok(line)) = lines.next() else {
+                return;
+            };
+            if !self.command(&line, out) {
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Repl;
+    use bounded::simulate::Simulator;
+    use fly::{parser::parse, semantics::Model, sorts::sort_check_module};
+    use rand::SeedableRng;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_simulate() {
+        let mut m = parse(
+            "
+sort node
+mutable owner: node
+mutable count(node): bool
+
+assume forall n:node. !count(n)
+assume always exists n:node. owner' = n & (forall x:node. count'(x) <-> count(x) | x = n)
+assert always forall n:node. count(n) -> owner = n
+",
+        )
+        .unwrap();
+        sort_check_module(&mut m).unwrap();
+        let sig = m.signature.clone();
+        let back_convert = m.convert_non_bool_relations().unwrap();
+        let back_convert = |model: &Model| back_convert(model);
+        let universe = HashMap::from([("node".to_string(), 2)]);
+        let sim = Simulator::new(&m, &universe).unwrap();
+        let rng = rand::rngs::StdRng::seed_from_u64(0);
+        let mut repl = Repl::new(&sim, &sig, &back_convert, rng);
+
+        let session = "
+init
+enabled
+step 0 1
+eval owner
+eval count(owner)
+step 0 0
+eval forall n:node. count(n)
+undo
+step 2
+eval owner =
+random 2
+";
+        let mut out = vec![];
+        repl.run(session.as_bytes(), &mut out, false);
+        insta::assert_display_snapshot!(String::from_utf8(out).unwrap(), @r###"
+        starting from one of 2 initial states
+        state 0
+        step 0 0             # action 0(n=@node_0)
+        step 0 1             # action 0(n=@node_1)
+        state 1
+        @node_1
+        true
+        state 2
+        assertion 0 does not hold
+        true
+        state 1
+        error: there is no action 2 with arguments []
+        error: expected one of "!", "(", "X", "X^-1", "always", "eventually", "exists", "forall", "if", identifier
+        step 0 1
+        state 2
+        step 0 1
+        state 3
+        "###);
+        // the random steps are saved as the steps that were taken
+        assert_eq!(repl.commands[1..], ["step 0 1", "step 0 1", "step 0 1"]);
+    }
+}
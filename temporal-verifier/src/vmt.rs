@@ -0,0 +1,176 @@
+// Copyright 2022-2023 VMware, Inc.
+// SPDX-License-Identifier: BSD-2-Clause
+
+//! Export a transition system to the [VMT-LIB] format, for cross-checking with
+//! other model checkers.
+//!
+//! VMT-LIB is SMT-LIB2 with a convention for annotating terms: a state
+//! variable `x` is tied to its next-state copy with `(! x :next x.next)`, and
+//! the initial condition, transition relation and properties are marked with
+//! `:init`, `:trans` and `:invar-property`. Mutable relations with arguments
+//! are exported as parametric state variables (as done by first-order model
+//! checkers such as ic3po), where the annotated term is the relation applied to
+//! the parameters.
+//!
+//! VMT has no notion of axioms, so axioms are conjoined to the initial
+//! condition and, in both the pre- and post-state, to the transition relation.
+//!
+//! [VMT-LIB]: https://vmt-lib.fbk.eu/
+
+use fly::{
+    syntax::{RelationDecl, Signature, Term},
+    term::prime::Next,
+    transitions::DestructuredModule,
+};
+use smtlib::sexp::{app, atom_i, atom_s, sexp_l, Atom, Sexp};
+use solver::sexp;
+
+/// The name of the next-state copy of the mutable symbol `name`.
+fn next_name(name: &str) -> String {
+    format!("{name}.next")
+}
+
+/// Rename primed symbols (which [`sexp::term`] emits as `name'`) to their
+/// next-state symbols.
+fn rename_primes(s: Sexp) -> Sexp {
+    match s {
+        Sexp::Atom(Atom::S(name)) => match name.strip_suffix('\'') {
+            Some(name) => {
+                assert!(
+                    !name.ends_with('\''),
+                    "transitions should have at most one prime"
+                );
+                atom_s(next_name(name))
+            }
+            None => Sexp::Atom(Atom::S(name)),
+        },
+        Sexp::List(ss) => Sexp::List(ss.into_iter().map(rename_primes).collect()),
+        _ => s,
+    }
+}
+
+fn term(t: &Term) -> Sexp {
+    rename_primes(sexp::term(t))
+}
+
+/// `(declare-fun name (args) sort)` for a symbol with the arguments and sort of
+/// `r`.
+fn declare(name: &str, r: &RelationDecl) -> Sexp {
+    app(
+        "declare-fun",
+        [
+            atom_s(name),
+            sexp_l(r.args.iter().map(sexp::sort)),
+            sexp::sort(&r.sort),
+        ],
+    )
+}
+
+/// `(define-fun name () Bool (! body :attr value))`
+fn annotated(name: &str, body: Sexp, attr: &str, value: Sexp) -> Sexp {
+    app(
+        "define-fun",
+        [
+            atom_s(name),
+            sexp_l([]),
+            atom_s("Bool"),
+            app("!", [body, atom_s(attr), value]),
+        ],
+    )
+}
+
+/// Convert a transition system to VMT-LIB, with one command per line.
+pub fn to_vmt(sig: &Signature, module: &DestructuredModule) -> String {
+    let mut cmds = vec![];
+    for sort in &sig.sorts {
+        cmds.push(app("declare-sort", [atom_s(sort), atom_i(0)]));
+    }
+    for r in &sig.relations {
+        cmds.push(declare(&r.name, r));
+        if !r.mutable {
+            continue;
+        }
+        cmds.push(declare(&next_name(&r.name), r));
+        // tie the state variable to its next-state copy
+        let params = (0..r.args.len())
+            .map(|i| format!("x{i}"))
+            .collect::<Vec<_>>();
+        let current = if params.is_empty() {
+            atom_s(&r.name)
+        } else {
+            app(&r.name, params.iter().map(atom_s))
+        };
+        cmds.push(app(
+            "define-fun",
+            [
+                atom_s(format!(".{}", r.name)),
+                sexp_l(
+                    (params.iter().zip(&r.args))
+                        .map(|(param, sort)| app(param, [sexp::sort(sort)])),
+                ),
+                sexp::sort(&r.sort),
+                app("!", [current, atom_s(":next"), atom_s(next_name(&r.name))]),
+            ],
+        ));
+    }
+
+    let next = Next::new(sig);
+    let init = Term::and(module.axioms.iter().chain(&module.inits));
+    let trans = Term::and(
+        (module.axioms.iter().cloned())
+            .chain(module.axioms.iter().map(|t| next.prime(t)))
+            .chain(module.transitions.iter().cloned()),
+    );
+    cmds.push(annotated(".init", term(&init), ":init", atom_s("true")));
+    cmds.push(annotated(".trans", term(&trans), ":trans", atom_s("true")));
+    for (i, proof) in module.proofs.iter().enumerate() {
+        cmds.push(annotated(
+            &format!(".prop{i}"),
+            term(&proof.safety.x),
+            ":invar-property",
+            atom_i(i),
+        ));
+    }
+
+    cmds.iter().map(|cmd| format!("{cmd}\n")).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::to_vmt;
+    use fly::{parser::parse, sorts::sort_check_module, transitions::extract};
+
+    #[test]
+    fn test_vmt() {
+        let mut m = parse(
+            "
+sort node
+immutable leader: node
+mutable pending(node): bool
+mutable owner(node): node
+
+assume always forall n:node. owner(n) = owner(leader)
+assume forall n:node. !pending(n)
+assume always exists n:node. (forall m:node. pending'(m) <-> pending(m) | m = n) &
+    (forall m:node. owner'(m) = owner(m))
+assert always !pending(leader)
+",
+        )
+        .unwrap();
+        sort_check_module(&mut m).unwrap();
+        let d = extract(&m).unwrap();
+        insta::assert_display_snapshot!(to_vmt(&m.signature, &d), @r###"
+        (declare-sort node 0)
+        (declare-fun leader () node)
+        (declare-fun pending (node) Bool)
+        (declare-fun pending.next (node) Bool)
+        (define-fun .pending ((x0 node)) Bool (! (pending x0) :next pending.next))
+        (declare-fun owner (node) node)
+        (declare-fun owner.next (node) node)
+        (define-fun .owner ((x0 node)) node (! (owner x0) :next owner.next))
+        (define-fun .init () Bool (! (and (forall ((n node)) (= (owner n) (owner leader))) (forall ((n node)) (not (pending n)))) :init true))
+        (define-fun .trans () Bool (! (and (forall ((n node)) (= (owner n) (owner leader))) (forall ((n node)) (= (owner.next n) (owner.next leader))) (exists ((n node)) (and (forall ((m node)) (= (pending.next m) (or (pending m) (= m n)))) (forall ((m node)) (= (owner.next m) (owner m)))))) :trans true))
+        (define-fun .prop0 () Bool (! (not (pending leader)) :invar-property 0))
+        "###);
+    }
+}
@@ -0,0 +1,49 @@
+// Copyright 2022-2023 VMware, Inc.
+// SPDX-License-Identifier: BSD-2-Clause
+
+use std::process::Command;
+
+fn temporal_verifier() -> Command {
+    let mut cmd = Command::new("../target/debug/temporal-verifier");
+    cmd.arg("--color=never");
+    cmd
+}
+
+#[test]
+fn compare_invariants_lockserver() {
+    let out = temporal_verifier()
+        .arg("compare-invariants")
+        .arg("examples/lockserver.fly")
+        .arg("tests/lockserver_inferred.txt")
+        .output()
+        .expect("could not run temporal-verifier");
+    let stdout = String::from_utf8(out.stdout).expect("non-utf8 output");
+    let stderr = String::from_utf8(out.stderr).expect("non-utf8 output");
+    assert!(
+        out.status.success(),
+        "temporal-verifier should succeed: {stderr}"
+    );
+
+    // only the first proof of the inferred file is compared, and each of its lemmas
+    // is equivalent to a handwritten lemma, up to renaming and rewriting
+    let lines = stdout.lines().collect::<Vec<_>>();
+    assert!(lines.contains(
+        &"3 / 3 inferred lemmas are implied by the handwritten invariant (3 equivalent to a handwritten lemma)"
+    ));
+    assert!(lines.contains(
+        &"3 / 9 handwritten lemmas are implied by the inferred invariant (3 equivalent to an inferred lemma)"
+    ));
+    let equivalent = |inferred: usize, handwritten: usize| {
+        let i = lines
+            .iter()
+            .position(|line| line.starts_with(&format!("#{inferred} invariant")))
+            .unwrap();
+        assert_eq!(
+            lines[i + 1],
+            format!("  equivalent to handwritten lemma #{handwritten}")
+        );
+    };
+    equivalent(1, 1);
+    equivalent(2, 8);
+    equivalent(3, 4);
+}
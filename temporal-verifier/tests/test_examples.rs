@@ -212,6 +212,13 @@ fn get_file_tests(path: &Path) -> Vec<Test> {
         .collect()
 }
 
+/// Whether a file is a model to test, either a fly file or an imported
+/// mypyvy file.
+fn is_input_file(path: &Path) -> bool {
+    let ext = path.extension();
+    ext == Some(OsStr::new("fly")) || ext == Some(OsStr::new("pyv"))
+}
+
 fn get_toml_tests(toml_file: &Path) -> Vec<Test> {
     let f = fs::read_to_string(toml_file).expect("could not open config file");
     let tests: Tests =
@@ -220,7 +227,7 @@ fn get_toml_tests(toml_file: &Path) -> Vec<Test> {
     dir.read_dir()
         .expect("could not read toml dir")
         .filter_map(|e| e.ok())
-        .filter(|entry| entry.path().extension() == Some(OsStr::new("fly")))
+        .filter(|entry| is_input_file(&entry.path()))
         .flat_map(|entry| {
             tests
                 .tests
@@ -250,7 +257,7 @@ fn get_tests(root_dir: &str) -> HashMap<PathBuf, Vec<Test>> {
         .flat_map(|entry| {
             if entry.path().ends_with("tests.toml") {
                 get_toml_tests(entry.path())
-            } else if entry.path().extension() == Some(OsStr::new("fly")) {
+            } else if is_input_file(entry.path()) {
                 get_file_tests(entry.path())
             } else {
                 vec![]